@@ -6,6 +6,8 @@ pub struct Config {
   pub hidden: HashSet<InscriptionId>,
   pub bitcoin_rpc_pass: Option<String>,
   pub bitcoin_rpc_user: Option<String>,
+  #[serde(default)]
+  pub fee_policy: FeePolicy,
 }
 
 impl Config {
@@ -0,0 +1,249 @@
+use super::*;
+
+/// The service fee charged for each method, read from the `fee_policy`
+/// section of the config file. Methods left out of the config keep their
+/// default fee.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeePolicy {
+  pub mint: MethodFee,
+  pub mints: MethodFee,
+  pub remint: MethodFee,
+  pub remints: MethodFee,
+  pub cancel: MethodFee,
+  /// Discount in percent for each whitelist level. Addresses whitelisted
+  /// without a level are at level `default`, which keeps its full discount
+  /// unless the config sets it.
+  #[serde(deserialize_with = "deserialize_whitelist")]
+  pub whitelist: BTreeMap<String, u8>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FeeMethod {
+  Mint,
+  Mints,
+  Remint,
+  Remints,
+  Cancel,
+}
+
+/// The fee of one method: `base` once per request, plus `per_item` for each
+/// inscription or input and `postage_percent` of their postage. The tier
+/// with the highest `min_items` the request reaches replaces `per_item`.
+/// The total is kept between `min` and `max` before any whitelist discount.
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MethodFee {
+  pub base: u64,
+  pub per_item: u64,
+  pub postage_percent: f64,
+  pub tiers: Vec<FeeTier>,
+  pub min: Option<u64>,
+  pub max: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTier {
+  pub min_items: u64,
+  pub per_item: u64,
+}
+
+impl Default for FeePolicy {
+  fn default() -> Self {
+    let mint = MethodFee {
+      per_item: 3000,
+      ..Default::default()
+    };
+    let remint = MethodFee {
+      base: 3000,
+      ..mint.clone()
+    };
+
+    Self {
+      mints: mint.clone(),
+      mint,
+      remints: remint.clone(),
+      remint,
+      cancel: MethodFee {
+        base: 1000,
+        ..Default::default()
+      },
+      whitelist: BTreeMap::from([(Self::DEFAULT_LEVEL.into(), 100)]),
+    }
+  }
+}
+
+fn deserialize_whitelist<'de, D>(deserializer: D) -> Result<BTreeMap<String, u8>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  let mut whitelist = BTreeMap::<String, u8>::deserialize(deserializer)?;
+  whitelist
+    .entry(FeePolicy::DEFAULT_LEVEL.into())
+    .or_insert(100);
+  Ok(whitelist)
+}
+
+impl FeePolicy {
+  pub const DEFAULT_LEVEL: &'static str = "default";
+
+  pub fn method(&self, method: FeeMethod) -> &MethodFee {
+    match method {
+      FeeMethod::Mint => &self.mint,
+      FeeMethod::Mints => &self.mints,
+      FeeMethod::Remint => &self.remint,
+      FeeMethod::Remints => &self.remints,
+      FeeMethod::Cancel => &self.cancel,
+    }
+  }
+
+  /// Discount in percent for whitelist `level`. Levels missing from the
+  /// policy get no discount.
+  pub fn discount(&self, level: Option<&str>) -> u8 {
+    level
+      .and_then(|level| self.whitelist.get(level))
+      .map_or(0, |discount| (*discount).min(100))
+  }
+
+  /// The service fee of `method` for `items` inscriptions or inputs of
+  /// `postage` each, requested from an address at whitelist `level`.
+  pub fn service_fee(
+    &self,
+    method: FeeMethod,
+    items: u64,
+    postage: Amount,
+    level: Option<&str>,
+  ) -> Amount {
+    let fee = self.method(method).fee(items, postage);
+    let discount = u64::from(self.discount(level));
+    Amount::from_sat(fee.to_sat() * (100 - discount) / 100)
+  }
+}
+
+impl MethodFee {
+  /// The fee before any whitelist discount.
+  pub fn fee(&self, items: u64, postage: Amount) -> Amount {
+    let per_item = self
+      .tiers
+      .iter()
+      .filter(|tier| items >= tier.min_items)
+      .max_by_key(|tier| tier.min_items)
+      .map_or(self.per_item, |tier| tier.per_item);
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let postage_fee =
+      ((postage.to_sat() * items) as f64 * self.postage_percent / 100.0).round() as u64;
+
+    let mut fee = self.base + per_item * items + postage_fee;
+    if let Some(min) = self.min {
+      fee = fee.max(min);
+    }
+    if let Some(max) = self.max {
+      fee = fee.min(max);
+    }
+
+    Amount::from_sat(fee)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_policy_matches_flat_fees() {
+    let policy = FeePolicy::default();
+    let postage = TransactionBuilder::TARGET_POSTAGE;
+
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 3, postage, None),
+      Amount::from_sat(9000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Remints, 2, postage, None),
+      Amount::from_sat(9000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Cancel, 4, Amount::ZERO, None),
+      Amount::from_sat(1000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 3, postage, Some("default")),
+      Amount::ZERO
+    );
+  }
+
+  #[test]
+  fn tiers_caps_and_discounts() {
+    let policy: FeePolicy = serde_yaml::from_str(
+      "
+mint:
+  base: 500
+  per_item: 2000
+  postage_percent: 10
+  tiers:
+    - min_items: 10
+      per_item: 1500
+    - min_items: 100
+      per_item: 1000
+  max: 120000
+mints:
+  per_item: 100
+  min: 1000
+whitelist:
+  partner: 50
+  staff: 150
+",
+    )
+    .unwrap();
+
+    let postage = Amount::from_sat(1000);
+
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 2, postage, None),
+      Amount::from_sat(500 + 2 * 2000 + 200)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 10, postage, None),
+      Amount::from_sat(500 + 10 * 1500 + 1000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 100, postage, None),
+      Amount::from_sat(110500)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 200, postage, None),
+      Amount::from_sat(120000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mints, 2, postage, None),
+      Amount::from_sat(1000)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 2, postage, Some("partner")),
+      Amount::from_sat(2350)
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 2, postage, Some("staff")),
+      Amount::ZERO
+    );
+    assert_eq!(
+      policy.service_fee(FeeMethod::Mint, 2, postage, Some("default")),
+      Amount::ZERO
+    );
+    assert_eq!(policy.remint, FeePolicy::default().remint);
+  }
+
+  #[test]
+  fn default_level_can_be_overridden() {
+    let policy: FeePolicy = serde_yaml::from_str("whitelist:\n  default: 20\n").unwrap();
+    assert_eq!(policy.discount(Some(FeePolicy::DEFAULT_LEVEL)), 20);
+  }
+
+  #[test]
+  fn unknown_fields_are_rejected() {
+    assert!(serde_yaml::from_str::<FeePolicy>("mint:\n  per_itme: 1000\n").is_err());
+  }
+}
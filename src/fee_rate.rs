@@ -1,4 +1,4 @@
-use super::*;
+use {super::*, bitcoincore_rpc::json::EstimateMode, clap::ValueEnum};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct FeeRate(pub(crate) f64);
@@ -34,6 +34,78 @@ impl FeeRate {
     #[allow(clippy::cast_sign_loss)]
     Amount::from_sat((self.0 * vsize as f64).round() as u64)
   }
+
+  pub fn rate(&self) -> f64 {
+    self.0
+  }
+}
+
+/// The `estimatesmartfee` mode used when no fee rate is given.
+#[derive(Default, ValueEnum, Copy, Clone, Debug, PartialEq)]
+pub enum FeeMode {
+  Economical,
+  #[default]
+  Conservative,
+}
+
+impl From<FeeMode> for EstimateMode {
+  fn from(mode: FeeMode) -> Self {
+    match mode {
+      FeeMode::Economical => EstimateMode::Economical,
+      FeeMode::Conservative => EstimateMode::Conservative,
+    }
+  }
+}
+
+/// How reveal transactions are priced from the commit fee rate: `same`,
+/// `+<SATS>` sats/vB more, or `x<FACTOR>` times the commit rate.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RevealFeePolicy {
+  Same,
+  Add(f64),
+  Multiply(f64),
+}
+
+impl RevealFeePolicy {
+  pub fn apply(&self, fee_rate: FeeRate) -> Result<FeeRate> {
+    FeeRate::try_from(match self {
+      Self::Same => fee_rate.0,
+      Self::Add(sats) => fee_rate.0 + sats,
+      Self::Multiply(factor) => fee_rate.0 * factor,
+    })
+  }
+}
+
+/// Reveals pay slightly more than the commit by default, so a mint whose
+/// commit confirms doesn't have its reveals left behind.
+impl Default for RevealFeePolicy {
+  fn default() -> Self {
+    Self::Add(0.02)
+  }
+}
+
+impl FromStr for RevealFeePolicy {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let value = |value: &str| -> Result<f64> {
+      let value = f64::from_str(value)?;
+      if value.is_sign_negative() || !value.is_finite() {
+        bail!("invalid reveal fee policy `{s}`");
+      }
+      Ok(value)
+    };
+
+    if s == "same" {
+      Ok(Self::Same)
+    } else if let Some(sats) = s.strip_prefix('+') {
+      Ok(Self::Add(value(sats)?))
+    } else if let Some(factor) = s.strip_prefix('x') {
+      Ok(Self::Multiply(value(factor)?))
+    } else {
+      bail!("invalid reveal fee policy `{s}`, expected `same`, `+<SATS>` or `x<FACTOR>`")
+    }
+  }
 }
 
 #[cfg(test)]
@@ -69,4 +141,26 @@ mod tests {
       Amount::from_sat(123456789)
     );
   }
+
+  #[test]
+  fn reveal_fee_policy() {
+    let apply = |policy: &str| {
+      policy
+        .parse::<RevealFeePolicy>()
+        .unwrap()
+        .apply(FeeRate(10.0))
+        .unwrap()
+    };
+
+    assert_eq!(apply("same"), FeeRate(10.0));
+    assert_eq!(apply("+0.5"), FeeRate(10.5));
+    assert_eq!(apply("x1.5"), FeeRate(15.0));
+    assert_eq!(
+      RevealFeePolicy::default().apply(FeeRate(10.0)).unwrap(),
+      FeeRate(10.02)
+    );
+    assert!("+-1".parse::<RevealFeePolicy>().is_err());
+    assert!("x".parse::<RevealFeePolicy>().is_err());
+    assert!("1.5".parse::<RevealFeePolicy>().is_err());
+  }
 }
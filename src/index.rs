@@ -1,13 +1,14 @@
 use bitcoin::hashes::hex::FromHex;
-use mysql::prelude::*;
-use mysql::{params, Opts, OptsBuilder, PooledConn};
 use {
   self::{
     entry::{
       BlockHashValue, Entry, InscriptionEntry, InscriptionEntryValue, InscriptionIdValue,
       OutPointValue, SatPointValue, SatRange,
     },
-    updater::Updater,
+    mirror::{skip_mirror_blocks, write_mirror_block},
+    undo::{UndoLog, UndoTable},
+    updater::{Updater, UNDO_LOG_DEPTH},
+    utxo_provider::{BitcoinCore, Esplora, LocalIndex, UtxoProvider, UtxoProviderKind},
   },
   super::*,
   crate::wallet::Wallet,
@@ -16,34 +17,66 @@ use {
   chrono::SubsecRound,
   indicatif::{ProgressBar, ProgressStyle},
   log::log_enabled,
-  redb::{Database, ReadableTable, Table, TableDefinition, WriteStrategy, WriteTransaction},
+  redb::{
+    Database, MultimapTable, MultimapTableDefinition, ReadableMultimapTable, ReadableTable,
+    RedbKey, RedbValue, Table, TableDefinition, WriteStrategy, WriteTransaction,
+  },
   reqwest,
   std::collections::HashMap,
   std::sync::atomic::{self, AtomicBool},
+  std::{mem, ops::Bound},
 };
 
 mod entry;
 mod fetcher;
+mod mirror;
+mod mysql_database;
 mod rtx;
+mod sqlite_database;
+mod undo;
 mod updater;
+pub mod utxo_provider;
+
+pub use self::{
+  mirror::{
+    BackfillProgress, InscriptionMetadata, InscriptionRow, MirrorDiscrepancy, MirrorInscription,
+    MirrorLocation, MirrorStore, Order, OrderState, OrderStatus, TransferRow,
+  },
+  mysql_database::MysqlDatabase,
+  sqlite_database::SqliteDatabase,
+};
 
 const SCHEMA_VERSION: u64 = 3;
 
+/// Inscriptions written per mirror transaction during a backfill, and so
+/// the most work an interrupted backfill repeats.
+const MIRROR_BACKFILL_BATCH_SIZE: usize = 1000;
+
 macro_rules! define_table {
   ($name:ident, $key:ty, $value:ty) => {
     const $name: TableDefinition<$key, $value> = TableDefinition::new(stringify!($name));
   };
 }
 
+macro_rules! define_multimap_table {
+  ($name:ident, $key:ty, $value:ty) => {
+    const $name: MultimapTableDefinition<$key, $value> =
+      MultimapTableDefinition::new(stringify!($name));
+  };
+}
+
 define_table! { HEIGHT_TO_BLOCK_HASH, u64, &BlockHashValue }
+define_table! { HEIGHT_TO_UNDO_LOG, u64, &[u8] }
 define_table! { INSCRIPTION_ID_TO_INSCRIPTION_ENTRY, &InscriptionIdValue, InscriptionEntryValue }
 define_table! { INSCRIPTION_ID_TO_SATPOINT, &InscriptionIdValue, &SatPointValue }
 define_table! { INSCRIPTION_NUMBER_TO_INSCRIPTION_ID, u64, &InscriptionIdValue }
 define_table! { OUTPOINT_TO_SAT_RANGES, &OutPointValue, &[u8] }
+define_table! { OUTPOINT_TO_TXOUT, &OutPointValue, &[u8] }
 define_table! { OUTPOINT_TO_VALUE, &OutPointValue, u64}
 define_table! { SATPOINT_TO_INSCRIPTION_ID, &SatPointValue, &InscriptionIdValue }
 define_table! { SAT_TO_INSCRIPTION_ID, u64, &InscriptionIdValue }
 define_table! { SAT_TO_SATPOINT, u64, &SatPointValue }
+define_multimap_table! { SCRIPT_PUBKEY_TO_OUTPOINT, &[u8], &OutPointValue }
 define_table! { STATISTIC_TO_COUNT, u64, u64 }
 define_table! { WRITE_TRANSACTION_STARTING_BLOCK_COUNT_TO_TIMESTAMP, u64, u128 }
 
@@ -73,134 +106,6 @@ impl Encodable for ConstructTransaction {
   }
 }
 
-pub struct MysqlDatabase {
-  pub pool: mysql::Pool,
-  pub network: Network,
-}
-
-pub struct MysqlInscription {
-  pub inscription_id: InscriptionId,
-  pub new_satpoint: SatPoint,
-  pub new_address: String,
-}
-
-impl MysqlDatabase {
-  pub fn new(
-    host: Option<String>,
-    username: Option<String>,
-    password: Option<String>,
-    network: Network,
-  ) -> Result<MysqlDatabase> {
-    let opts_builder = OptsBuilder::new()
-      .ip_or_hostname(host)
-      .user(username)
-      .pass(password)
-      .db_name(Some(Self::get_database(network)));
-    let pool =
-      mysql::Pool::new::<Opts>(opts_builder.into()).map_err(|_| anyhow!("Create pool fail"))?;
-
-    Ok(MysqlDatabase { pool, network })
-  }
-
-  pub fn get_conn(&self) -> Result<PooledConn> {
-    self.pool.get_conn().map_err(|_| anyhow!("Connect fail"))
-  }
-
-  pub fn get_database(network: Network) -> String {
-    match network {
-      Network::Bitcoin => "ord_mainnet".to_owned(),
-      Network::Testnet => "ord_testnet".to_owned(),
-      Network::Signet => todo!(),
-      Network::Regtest => "ord_regtest".to_owned(),
-    }
-  }
-
-  pub fn get_whitelist_table(&self) -> String {
-    "INSCRIPTION_WHITELIST".to_owned()
-  }
-
-  fn _is_whitelist(&self, new_address: &String) -> Result<bool> {
-    let tb = self.get_whitelist_table();
-    let mut conn = self.get_conn()?;
-    let query = format!("SELECT * FROM {} WHERE new_address = '{}'", tb, new_address);
-    let result: Vec<mysql::Row> = conn.query(query).map_err(|_| anyhow!("Query fail"))?;
-    if !result.is_empty() {
-      Ok(true)
-    } else {
-      Ok(false)
-    }
-  }
-
-  pub fn is_whitelist(&self, new_address: &String) -> bool {
-    self._is_whitelist(new_address).unwrap_or(false)
-  }
-
-  pub fn get_inscription_table(&self) -> String {
-    "INSCRIPTION_ID_AND_SATPOINT".to_owned()
-  }
-
-  pub fn get_inscription_by_address(
-    &self,
-    new_address: &String,
-  ) -> Result<BTreeMap<SatPoint, InscriptionId>> {
-    let tb = self.get_inscription_table();
-    let query = format!("SELECT * FROM {} WHERE new_address = '{}'", tb, new_address);
-    let mut conn = self.get_conn()?;
-    let result: Vec<mysql::Row> = conn.query(query).map_err(|_| anyhow!("Query fail"))?;
-    let mut map: BTreeMap<SatPoint, InscriptionId> = BTreeMap::new();
-    for row in result {
-      let inscription_id = SatPoint::from_str(
-        &row
-          .get::<String, _>("new_satpoint")
-          .ok_or(anyhow!("Row inscription_id not exist"))?,
-      )?;
-      let new_satpoint = InscriptionId::from_str(
-        &row
-          .get::<String, _>("inscription_id")
-          .ok_or(anyhow!("Row new_satpoint not exist"))?,
-      )?;
-      map.insert(inscription_id, new_satpoint);
-    }
-    Ok(map)
-  }
-
-  pub fn insert_inscriptions(&self, data: Vec<MysqlInscription>) -> Result {
-    if data.is_empty() {
-      return Ok(());
-    };
-
-    let tb = self.get_inscription_table();
-    let query = format!(
-      "INSERT INTO {} (inscription_id, new_satpoint, new_address)
-       VALUES (:inscription_id, :new_satpoint, :new_address)
-       ON DUPLICATE KEY UPDATE inscription_id = :inscription_id , new_satpoint = :new_satpoint, new_address = :new_address",
-      tb
-    );
-
-    let mut conn = self.get_conn()?;
-
-    conn
-      .query_drop("START TRANSACTION")
-      .map_err(|_| anyhow!("Create transaction fail"))?;
-    for item in data.iter() {
-      conn
-        .exec_drop(
-          query.clone(),
-          params! {
-            "inscription_id" => format!("{}", item.inscription_id),
-            "new_satpoint" =>  format!("{}", item.new_satpoint),
-            "new_address" => item.new_address.clone(),
-          },
-        )
-        .map_err(|_| anyhow!("Execute transaction fail"))?;
-    }
-    conn
-      .query_drop("COMMIT")
-      .map_err(|_| anyhow!("Commit transaction fail"))?;
-    Ok(())
-  }
-}
-
 pub struct Index {
   client: Client,
   database: Database,
@@ -211,7 +116,7 @@ pub struct Index {
   height_limit: Option<u64>,
   options: Options,
   reorged: AtomicBool,
-  mysql_database: Option<Arc<MysqlDatabase>>,
+  mirror: Option<Arc<dyn MirrorStore>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -290,23 +195,6 @@ impl<T> BitcoinCoreRpcResultExt<T> for Result<T, bitcoincore_rpc::Error> {
   }
 }
 
-#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
-pub struct ListUnspentStatusEntry {
-  pub confirmed: bool,
-  pub block_height: Option<usize>,
-  pub block_hash: Option<bitcoin::BlockHash>,
-  pub block_time: Option<u32>,
-}
-
-#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
-pub struct ListUnspentResultEntry {
-  pub txid: bitcoin::Txid,
-  pub vout: u32,
-  pub status: ListUnspentStatusEntry,
-  #[serde(with = "bitcoin::util::amount::serde::as_sat")]
-  pub value: Amount,
-}
-
 impl Index {
   pub fn open(options: &Options) -> Result<Self> {
     let client = options.bitcoin_rpc_client()?;
@@ -370,6 +258,7 @@ impl Index {
         };
 
         tx.open_table(HEIGHT_TO_BLOCK_HASH)?;
+        tx.open_table(HEIGHT_TO_UNDO_LOG)?;
         tx.open_table(INSCRIPTION_ID_TO_INSCRIPTION_ENTRY)?;
         tx.open_table(INSCRIPTION_ID_TO_SATPOINT)?;
         tx.open_table(INSCRIPTION_NUMBER_TO_INSCRIPTION_ID)?;
@@ -387,6 +276,11 @@ impl Index {
             .insert(&OutPoint::null().store(), [].as_slice())?;
         }
 
+        if options.index_addresses {
+          tx.open_table(OUTPOINT_TO_TXOUT)?;
+          tx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
+        }
+
         tx.commit()?;
 
         database
@@ -411,7 +305,7 @@ impl Index {
       height_limit: options.height_limit,
       reorged: AtomicBool::new(false),
       options: options.clone(),
-      mysql_database: None,
+      mirror: None,
     })
   }
 
@@ -470,180 +364,86 @@ impl Index {
       height_limit: options.height_limit,
       reorged: AtomicBool::new(false),
       options: options.clone(),
-      mysql_database: None,
+      mirror: None,
     })
   }
 
-  pub fn open_with_mysql(options: &Options, mysql_database: Arc<MysqlDatabase>) -> Result<Self> {
+  pub fn open_with_mirror(options: &Options, mirror: Arc<dyn MirrorStore>) -> Result<Self> {
     let mut index = Self::open(options)?;
-    index.mysql_database = Some(mysql_database);
+    index.mirror = Some(mirror);
     Ok(index)
   }
 
+  pub fn utxo_provider(&self) -> Box<dyn UtxoProvider + '_> {
+    match self.options.utxo_provider {
+      UtxoProviderKind::Esplora => Box::new(Esplora::new(
+        &self.options.esplora_url(),
+        self.options.rpc_timeout(),
+      )),
+      UtxoProviderKind::BitcoinCore => Box::new(BitcoinCore::new(&self.client)),
+      UtxoProviderKind::Index => Box::new(LocalIndex::new(self)),
+    }
+  }
+
   pub(crate) fn get_unspent_outputs_by_commit_id(
     &self,
     addr: &str,
     remain_outpoint: BTreeMap<OutPoint, bool>,
     txid: Txid,
   ) -> Result<(BTreeMap<OutPoint, Amount>, Transaction)> {
-    let mut utxos = self._get_unspent_outputs_by_mempool_v1(
-      self.options.chain().default_mempool_url(),
-      addr,
-      remain_outpoint,
-    )?;
-
-    let url = format!(
-      "{}tx/{}/hex",
-      self.options.chain().default_mempool_url(),
-      txid,
-    );
-
-    let rep = Vec::from_hex(&reqwest::blocking::get(url)?.text()?)?;
-    let tx: Transaction = Decodable::consensus_decode(&mut rep.as_slice()).unwrap();
+    let mut utxos = self.get_unspent_outputs_by_mempool_v1(addr, remain_outpoint)?;
 
-    for input in tx.input.clone() {
-      let txid = format!("{}", input.previous_output.txid);
-      let url = format!(
-        "{}tx/{}/hex",
-        self.options.chain().default_mempool_url(),
-        txid,
-      );
+    let utxo_provider = self.utxo_provider();
+    let tx = utxo_provider.get_transaction(txid)?;
 
-      let rep = Vec::from_hex(&reqwest::blocking::get(url)?.text()?)?;
-      let tx: Transaction = Decodable::consensus_decode(&mut rep.as_slice()).unwrap();
+    for input in &tx.input {
       utxos.insert(
         input.previous_output,
-        Amount::from_sat(tx.output[input.previous_output.vout as usize].value),
+        utxo_provider.get_output_value(input.previous_output)?,
       );
     }
     Ok((utxos, tx))
   }
 
+  pub(crate) fn get_transaction_by_mempool(&self, txid: Txid) -> Result<Transaction> {
+    self.utxo_provider().get_transaction(txid)
+  }
+
+  pub(crate) fn is_output_spent_by_mempool(&self, outpoint: OutPoint) -> Result<bool> {
+    self.utxo_provider().is_spent(outpoint)
+  }
+
   pub(crate) fn get_unspent_outputs_by_outpoints(
     &self,
     inputs: &Vec<OutPoint>,
   ) -> Result<BTreeMap<OutPoint, Amount>> {
+    let utxo_provider = self.utxo_provider();
     let mut utxos = BTreeMap::new();
     for input in inputs {
-      let txid = format!("{}", input.txid);
-      let url = format!(
-        "{}tx/{}/hex",
-        self.options.chain().default_mempool_url(),
-        txid,
-      );
-
-      let rep = Vec::from_hex(&reqwest::blocking::get(url)?.text()?)?;
-      let tx: Transaction = Decodable::consensus_decode(&mut rep.as_slice()).unwrap();
-      utxos.insert(
-        *input,
-        Amount::from_sat(tx.output[input.vout as usize].value),
-      );
+      utxos.insert(*input, utxo_provider.get_output_value(*input)?);
     }
     Ok(utxos)
   }
 
-  fn _get_unspent_outputs_by_mempool(
-    &self,
-    url: &str,
-    addr: &str,
-    remain_outpoint: BTreeMap<OutPoint, bool>,
-  ) -> Result<BTreeMap<OutPoint, Amount>> {
-    let mut utxos = BTreeMap::new();
-    let url = format!("{}address/{}/utxo", url, addr,);
-    let rep = reqwest::blocking::get(url)?.text()?;
-    utxos.extend(
-      serde_json::from_str::<Vec<ListUnspentResultEntry>>(&rep)
-        .map_err(|_| anyhow!(format!("Req utxo error:{}", rep)))?
-        .into_iter()
-        .map(|utxo| {
-          let outpoint = OutPoint::new(utxo.txid, utxo.vout);
-          let amount = utxo.value;
-
-          (outpoint, amount)
-        }),
-    );
-    let rtx = self.database.begin_read()?;
-    let outpoint_to_value = rtx.open_table(OUTPOINT_TO_VALUE)?;
-    let mut filter_utxos = BTreeMap::new();
-    for (outpoint, amount) in utxos.into_iter() {
-      if remain_outpoint.contains_key(&outpoint)
-        || outpoint_to_value.get(&outpoint.store())?.is_some()
-      {
-        filter_utxos.insert(outpoint, amount);
-      }
-    }
-    if filter_utxos.is_empty() {
-      Err(anyhow!("Not found utxo for addr"))
-    } else {
-      Ok(filter_utxos)
-    }
-  }
-
-  fn _get_unspent_outputs_by_mempool_v1(
+  /// Confirmed outputs of `addr` known to the index, plus any of
+  /// `remain_outpoint` the UTXO provider still reports, confirmed or not.
+  pub(crate) fn get_unspent_outputs_by_mempool_v1(
     &self,
-    url: &str,
     addr: &str,
     remain_outpoint: BTreeMap<OutPoint, bool>,
   ) -> Result<BTreeMap<OutPoint, Amount>> {
-    let mut utxos = BTreeMap::new();
-    let url = format!("{}address/{}/utxo", url, addr,);
-    let rep = reqwest::blocking::get(url)?.text()?;
-    utxos.extend(
-      serde_json::from_str::<Vec<ListUnspentResultEntry>>(&rep)
-        .map_err(|_| anyhow!(format!("Req utxo error:{}", rep)))?
-        .into_iter()
-        .filter(|utxo| utxo.status.confirmed)
-        .map(|utxo| {
-          let outpoint = OutPoint::new(utxo.txid, utxo.vout);
-          let amount = utxo.value;
-
-          (outpoint, amount)
-        }),
-    );
+    let utxos = self.utxo_provider().get_address_utxos(addr)?;
     let rtx = self.database.begin_read()?;
     let outpoint_to_value = rtx.open_table(OUTPOINT_TO_VALUE)?;
     let mut filter_utxos = BTreeMap::new();
-    for (outpoint, amount) in utxos.into_iter() {
-      if remain_outpoint.contains_key(&outpoint)
-        || outpoint_to_value.get(&outpoint.store())?.is_some()
+    for utxo in utxos {
+      if remain_outpoint.contains_key(&utxo.outpoint)
+        || utxo.confirmed && outpoint_to_value.get(&utxo.outpoint.store())?.is_some()
       {
-        filter_utxos.insert(outpoint, amount);
-      }
-    }
-    if filter_utxos.is_empty() {
-      Err(anyhow!("Not found utxo for addr"))
-    } else {
-      Ok(filter_utxos)
-    }
-  }
-
-  pub(crate) fn get_unspent_outputs_by_mempool(
-    &self,
-    addr: &str,
-    remain_outpoint: BTreeMap<OutPoint, bool>,
-  ) -> Result<BTreeMap<OutPoint, Amount>> {
-    self._get_unspent_outputs_by_mempool(
-      self.options.chain().default_mempool_url(),
-      addr,
-      remain_outpoint,
-    )
-  }
-
-  pub(crate) fn get_unspent_outputs_by_mempool_v1(
-    &self,
-    addr: &str,
-    remain_outpoint: BTreeMap<OutPoint, bool>,
-  ) -> Result<BTreeMap<OutPoint, Amount>> {
-    if self.options.chain() == Chain::Mainnet {
-      let mempool_url = "https://mempool.space/api/";
-      let utxos = self._get_unspent_outputs_by_mempool(mempool_url, addr, remain_outpoint.clone());
-      if let Ok(utxos) = utxos {
-        if !utxos.is_empty() {
-          return Ok(utxos);
-        }
+        filter_utxos.insert(utxo.outpoint, utxo.value);
       }
     }
-    self.get_unspent_outputs_by_mempool(addr, remain_outpoint)
+    Ok(filter_utxos)
   }
 
   pub(crate) fn get_unspent_outputs(&self, _wallet: Wallet) -> Result<BTreeMap<OutPoint, Amount>> {
@@ -720,6 +520,80 @@ impl Index {
     Ok(())
   }
 
+  pub(crate) fn has_address_index(&self) -> Result<bool> {
+    match self.begin_read()?.0.open_table(OUTPOINT_TO_TXOUT) {
+      Ok(_) => Ok(true),
+      Err(redb::Error::TableDoesNotExist(_)) => Ok(false),
+      Err(err) => Err(err.into()),
+    }
+  }
+
+  fn require_address_index(&self, feature: &str) -> Result {
+    if !self.has_address_index()? {
+      bail!("{feature} requires index created with `--index-addresses` flag")
+    }
+
+    Ok(())
+  }
+
+  pub fn get_address_unspent_outputs(
+    &self,
+    address: &Address,
+  ) -> Result<BTreeMap<OutPoint, Amount>> {
+    self.require_address_index("Listing address outputs")?;
+    self.get_unspent_outputs_by_script_pubkey(&address.script_pubkey())
+  }
+
+  pub fn get_address_inscriptions(
+    &self,
+    address: &Address,
+  ) -> Result<BTreeMap<SatPoint, InscriptionId>> {
+    self.require_address_index("Listing address inscriptions")?;
+    self.get_inscriptions_by_script_pubkey(&address.script_pubkey())
+  }
+
+  fn get_unspent_outputs_by_script_pubkey(
+    &self,
+    script_pubkey: &Script,
+  ) -> Result<BTreeMap<OutPoint, Amount>> {
+    let rtx = self.database.begin_read()?;
+    let script_pubkey_to_outpoint = rtx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
+    let outpoint_to_txout = rtx.open_table(OUTPOINT_TO_TXOUT)?;
+
+    let mut utxos = BTreeMap::new();
+    for outpoint in script_pubkey_to_outpoint.get(script_pubkey.as_bytes())? {
+      let txout = outpoint_to_txout
+        .get(outpoint.value())?
+        .ok_or_else(|| anyhow!("address table entry without output"))?;
+      let txout: TxOut = consensus::encode::deserialize(txout.value())?;
+      utxos.insert(
+        Entry::load(*outpoint.value()),
+        Amount::from_sat(txout.value),
+      );
+    }
+
+    Ok(utxos)
+  }
+
+  fn get_inscriptions_by_script_pubkey(
+    &self,
+    script_pubkey: &Script,
+  ) -> Result<BTreeMap<SatPoint, InscriptionId>> {
+    let rtx = self.database.begin_read()?;
+    let script_pubkey_to_outpoint = rtx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
+    let satpoint_to_id = rtx.open_table(SATPOINT_TO_INSCRIPTION_ID)?;
+
+    let mut inscriptions = BTreeMap::new();
+    for outpoint in script_pubkey_to_outpoint.get(script_pubkey.as_bytes())? {
+      inscriptions.extend(Self::inscriptions_on_output(
+        &satpoint_to_id,
+        Entry::load(*outpoint.value()),
+      )?);
+    }
+
+    Ok(inscriptions)
+  }
+
   pub(crate) fn info(&self) -> Result<Info> {
     let wtx = self.begin_write()?;
 
@@ -775,98 +649,609 @@ impl Index {
     Updater::reorg_height(self, target_height)
   }
 
-  pub fn update(&self) -> Result {
-    Updater::update(self)
+  /// Rolls back to the highest indexed block that is still in Bitcoin Core's
+  /// active chain, returning its height.
+  pub fn recover_from_reorg(&self) -> Result<u64> {
+    let fork_height = self.fork_height()?;
+    log::info!("Roll back to fork height {fork_height}");
+    self.reorg_height(fork_height)?;
+    self.reorged.store(false, atomic::Ordering::Relaxed);
+    Ok(fork_height)
   }
 
-  pub(crate) fn is_reorged(&self) -> bool {
-    self.reorged.load(atomic::Ordering::Relaxed)
-  }
+  /// Only the blocks that can still be rolled back to are checked, since a
+  /// fork any deeper cannot be recovered from without reindexing.
+  fn fork_height(&self) -> Result<u64> {
+    for (height, hash) in self.blocks(usize::try_from(UNDO_LOG_DEPTH + 1)?)? {
+      if self.client.get_block_hash(height).into_option()? == Some(hash) {
+        return Ok(height);
+      }
+    }
 
-  fn begin_read(&self) -> Result<rtx::Rtx> {
-    Ok(rtx::Rtx(self.database.begin_read()?))
+    bail!(
+      "none of the last {} indexed blocks are in the active chain, the reorg is deeper than the undo logs reach and the index must be rebuilt",
+      UNDO_LOG_DEPTH + 1
+    )
   }
 
-  fn begin_write(&self) -> Result<WriteTransaction> {
-    if cfg!(test) {
-      let mut tx = self.database.begin_write()?;
-      tx.set_durability(redb::Durability::None);
-      Ok(tx)
-    } else {
-      Ok(self.database.begin_write()?)
+  /// Writes the blocks the mirror is missing, returning how many were
+  /// caught up. Each missing block still covered by an undo log is replayed
+  /// on its own, so the mirror's change log stays accurate. If the gap
+  /// reaches further back than the undo logs, the mirror is instead rebuilt
+  /// from the current index state with `backfill_mirror`.
+  pub fn catch_up_mirror(&self) -> Result<u64> {
+    let Some(mirror) = self.mirror.as_deref() else {
+      return Ok(0);
+    };
+
+    if let Some(progress) = mirror.get_backfill_progress()? {
+      bail!(
+        "mirror backfill of block {} is unfinished, resume it with `ord_index --backfill`",
+        progress.height
+      );
     }
-  }
 
-  fn increment_statistic(wtx: &WriteTransaction, statistic: Statistic, n: u64) -> Result {
-    let mut statistic_to_count = wtx.open_table(STATISTIC_TO_COUNT)?;
-    let value = statistic_to_count
-      .get(&(statistic.key()))?
-      .map(|x| x.value())
-      .unwrap_or(0)
-      + n;
-    statistic_to_count.insert(&statistic.key(), &value)?;
-    Ok(())
-  }
+    let Some(indexed) = self.block_count()?.checked_sub(1) else {
+      return Ok(0);
+    };
 
-  #[cfg(test)]
-  pub(crate) fn statistic(&self, statistic: Statistic) -> u64 {
-    self
-      .database
-      .begin_read()
-      .unwrap()
-      .open_table(STATISTIC_TO_COUNT)
-      .unwrap()
-      .get(&statistic.key())
-      .unwrap()
-      .map(|x| x.value())
-      .unwrap_or(0)
-  }
+    let mut synced = mirror.get_synced_height()?;
+    if synced >= Some(indexed) {
+      return Ok(0);
+    }
+    let start = synced.map(|synced| synced + 1).unwrap_or(0);
 
-  pub(crate) fn height(&self) -> Result<Option<Height>> {
-    self.begin_read()?.height()
-  }
+    let rtx = self.database.begin_read()?;
+    let height_to_undo_log = rtx.open_table(HEIGHT_TO_UNDO_LOG)?;
+    let inscription_id_to_satpoint = rtx.open_table(INSCRIPTION_ID_TO_SATPOINT)?;
 
-  pub(crate) fn block_count(&self) -> Result<u64> {
-    self.begin_read()?.block_count()
-  }
+    if height_to_undo_log.get(&start)?.is_none() {
+      log::info!("Mirror is missing blocks {start}..={indexed}, backfill it from the index");
+      self.backfill_mirror()?;
+      return Ok(indexed + 1 - start);
+    }
 
-  pub(crate) fn blocks(&self, take: usize) -> Result<Vec<(u64, BlockHash)>> {
+    // Walk back from the current state through each block's undo log to find
+    // where the inscriptions it moved ended up at that block.
+    let mut satpoints = HashMap::new();
     let mut blocks = Vec::new();
+    for height in (start..=indexed).rev() {
+      let undo_log = height_to_undo_log
+        .get(&height)?
+        .map(|undo_log| UndoLog::load(undo_log.value()))
+        .transpose()?
+        .ok_or_else(|| anyhow!("no undo log for block {height}, cannot catch up mirror"))?;
+
+      // The first change to an inscription in a block holds where it was
+      // before the block, so later moves in the same block are folded into a
+      // single transfer.
+      let mut data = Vec::new();
+      for (inscription_id, old_satpoint) in undo_log.inscription_satpoints() {
+        if data
+          .iter()
+          .any(|inscription: &MirrorInscription| inscription.inscription_id == inscription_id)
+        {
+          continue;
+        }
 
-    let rtx = self.begin_read()?;
+        let satpoint = match satpoints.get(&inscription_id) {
+          Some(satpoint) => *satpoint,
+          None => inscription_id_to_satpoint
+            .get(&inscription_id.store())?
+            .map(|satpoint| Entry::load(*satpoint.value())),
+        };
 
-    let block_count = rtx.block_count()?;
+        if let Some(satpoint) = satpoint {
+          if let Some(inscription) =
+            self.mirror_inscription(mirror, inscription_id, old_satpoint, satpoint)?
+          {
+            data.push(inscription);
+          }
+        }
+      }
 
-    let height_to_block_hash = rtx.0.open_table(HEIGHT_TO_BLOCK_HASH)?;
+      for (inscription_id, old) in undo_log.inscription_satpoints().rev() {
+        satpoints.insert(inscription_id, old);
+      }
 
-    for next in height_to_block_hash.range(0..block_count)?.rev().take(take) {
-      blocks.push((next.0.value(), Entry::load(*next.1.value())));
+      blocks.push((height, data));
     }
 
-    Ok(blocks)
+    let height_to_block_hash = rtx.open_table(HEIGHT_TO_BLOCK_HASH)?;
+    for (height, data) in blocks.into_iter().rev() {
+      if data.is_empty() {
+        continue;
+      }
+
+      let hash = height_to_block_hash
+        .get(&height)?
+        .map(|hash| BlockHash::load(*hash.value()))
+        .ok_or_else(|| anyhow!("no block hash for block {height}"))?;
+      let header = self
+        .block_header(hash)?
+        .ok_or_else(|| anyhow!("block header {hash} not found"))?;
+
+      log::info!("Catch up mirror block {height} with {} items", data.len());
+      write_mirror_block(mirror, &mut synced, height, header.time, data)?;
+    }
+    skip_mirror_blocks(mirror, &mut synced, indexed)?;
+
+    Ok(indexed + 1 - start)
   }
 
-  pub(crate) fn rare_sat_satpoints(&self) -> Result<Option<Vec<(Sat, SatPoint)>>> {
-    if self.has_sat_index()? {
-      let mut result = Vec::new();
+  /// Rebuilds the mirror from the index, for a mirror that was lost or
+  /// created after the index. Inscriptions are written in batches in index
+  /// order, and each batch records how far the backfill got, so an
+  /// interrupted backfill resumes where it stopped as long as the index
+  /// hasn't moved since. Returns the number of inscriptions written.
+  pub fn backfill_mirror(&self) -> Result<u64> {
+    let Some(mirror) = self.mirror.as_deref() else {
+      bail!("backfill requires a mirror database");
+    };
 
-      let rtx = self.database.begin_read()?;
+    let rtx = self.begin_read()?;
+    let Some(height) = rtx.block_count()?.checked_sub(1) else {
+      bail!("index is empty, nothing to backfill");
+    };
 
-      let sat_to_satpoint = rtx.open_table(SAT_TO_SATPOINT)?;
+    let resume = match mirror.get_backfill_progress()? {
+      Some(progress) if progress.height == height => {
+        log::info!("Resume mirror backfill of block {height}");
+        progress.last
+      }
+      progress => {
+        if let Some(progress) = progress {
+          log::info!(
+            "Index moved from block {} to {height} since the mirror backfill started, start over",
+            progress.height
+          );
+        }
+        mirror.start_backfill(height)?;
+        None
+      }
+    };
 
-      for (sat, satpoint) in sat_to_satpoint.range(0..)? {
-        result.push((Sat(sat.value()), Entry::load(*satpoint.value())));
+    let inscription_id_to_satpoint = rtx.0.open_table(INSCRIPTION_ID_TO_SATPOINT)?;
+    let total = inscription_id_to_satpoint.len()?;
+
+    let start = resume.map(|last| last.store());
+    let mut done = match &start {
+      Some(start) => inscription_id_to_satpoint
+        .range::<&InscriptionIdValue>(..=start)?
+        .count(),
+      None => 0,
+    };
+
+    let mut written = 0;
+    let mut batch = Vec::new();
+    for (inscription_id, satpoint) in inscription_id_to_satpoint.range::<&InscriptionIdValue>((
+      start.as_ref().map_or(Bound::Unbounded, Bound::Excluded),
+      Bound::Unbounded,
+    ))? {
+      let inscription_id = InscriptionId::load(*inscription_id.value());
+
+      if let Some(inscription) =
+        self.mirror_inscription(mirror, inscription_id, None, Entry::load(*satpoint.value()))?
+      {
+        batch.push(inscription);
       }
 
-      Ok(Some(result))
-    } else {
-      Ok(None)
+      done += 1;
+      if done % MIRROR_BACKFILL_BATCH_SIZE == 0 || done == total {
+        written += u64::try_from(batch.len()).unwrap();
+        mirror.backfill_inscriptions(mem::take(&mut batch), inscription_id)?;
+        log::info!("Backfilled {done}/{total} inscriptions");
+      }
     }
+
+    mirror.finish_backfill()?;
+    log::info!("Mirror backfilled to block {height}");
+
+    Ok(written)
   }
 
-  pub(crate) fn rare_sat_satpoint(&self, sat: Sat) -> Result<Option<SatPoint>> {
-    if self.has_sat_index()? {
-      Ok(
+  /// Compares where the index and the mirror have every inscription, and
+  /// returns the inscriptions they disagree on. Both sides are read a batch
+  /// at a time: the index in its own order, looking up each batch's mirror
+  /// rows, and then the mirror in inscription id order, looking up each row
+  /// in the index. With `repair`, the rows found are then rewritten from the
+  /// index, or deleted if the index doesn't mirror the inscription.
+  pub fn verify_mirror(&self, repair: bool) -> Result<Vec<MirrorDiscrepancy>> {
+    let Some(mirror) = self.mirror.as_deref() else {
+      bail!("verify requires a mirror database");
+    };
+
+    let rtx = self.begin_read()?;
+    let indexed = rtx.block_count()?.checked_sub(1);
+    let synced = mirror.get_synced_height()?;
+    if synced != indexed {
+      let height = |height: Option<u64>| height.map_or("none".into(), |height| height.to_string());
+      bail!(
+        "mirror is synced to block {} but the index is at block {}, catch up mirror first",
+        height(synced),
+        height(indexed)
+      );
+    }
+
+    let inscription_id_to_satpoint = rtx.0.open_table(INSCRIPTION_ID_TO_SATPOINT)?;
+
+    let mut discrepancies = Vec::new();
+    let mut batch = Vec::new();
+    let mut inscriptions = inscription_id_to_satpoint.iter()?.peekable();
+    while let Some((inscription_id, satpoint)) = inscriptions.next() {
+      let satpoint = Entry::load(*satpoint.value());
+      if Self::is_mirrored(satpoint) {
+        batch.push((InscriptionId::load(*inscription_id.value()), satpoint));
+      }
+
+      if batch.len() == MIRROR_BACKFILL_BATCH_SIZE || inscriptions.peek().is_none() {
+        discrepancies.extend(self.verify_mirror_batch(mirror, mem::take(&mut batch))?);
+      }
+    }
+
+    let mut after = None;
+    loop {
+      let rows = mirror.get_inscriptions_after(after, MIRROR_BACKFILL_BATCH_SIZE)?;
+      let Some(last) = rows.last() else {
+        break;
+      };
+      after = Some(last.inscription_id);
+
+      for row in rows {
+        let mirrored = inscription_id_to_satpoint
+          .get(&row.inscription_id.store())?
+          .map(|satpoint| Self::is_mirrored(Entry::load(*satpoint.value())))
+          .unwrap_or_default();
+
+        if !mirrored {
+          discrepancies.push(MirrorDiscrepancy::Extra {
+            inscription_id: row.inscription_id,
+            satpoint: row.satpoint,
+            height: row.height,
+          });
+        }
+      }
+    }
+
+    if repair && !discrepancies.is_empty() {
+      let mut data = Vec::new();
+      let mut delete = Vec::new();
+      for discrepancy in &discrepancies {
+        match discrepancy {
+          MirrorDiscrepancy::Missing {
+            inscription_id,
+            satpoint,
+            ..
+          } => data.extend(self.mirror_inscription(mirror, *inscription_id, None, *satpoint)?),
+          MirrorDiscrepancy::Mismatched {
+            inscription_id,
+            index,
+            ..
+          } => {
+            data.extend(self.mirror_inscription(mirror, *inscription_id, None, index.satpoint)?)
+          }
+          MirrorDiscrepancy::Extra { inscription_id, .. } => delete.push(*inscription_id),
+        }
+      }
+
+      mirror.repair_inscriptions(data, delete)?;
+    }
+
+    Ok(discrepancies)
+  }
+
+  /// Checks the mirror rows of a batch of mirrored inscriptions against
+  /// where the index has them.
+  fn verify_mirror_batch(
+    &self,
+    mirror: &dyn MirrorStore,
+    batch: Vec<(InscriptionId, SatPoint)>,
+  ) -> Result<Vec<MirrorDiscrepancy>> {
+    let mut rows = mirror
+      .get_inscriptions_in(
+        &batch
+          .iter()
+          .map(|(inscription_id, _satpoint)| *inscription_id)
+          .collect::<Vec<InscriptionId>>(),
+      )?
+      .into_iter()
+      .map(|row| (row.inscription_id, row))
+      .collect::<HashMap<InscriptionId, InscriptionRow>>();
+
+    let mut discrepancies = Vec::new();
+    for (inscription_id, satpoint) in batch {
+      let genesis_height = self
+        .get_inscription_entry(inscription_id)?
+        .ok_or_else(|| anyhow!("inscription {inscription_id} has no entry"))?
+        .height;
+
+      let Some(row) = rows.remove(&inscription_id) else {
+        discrepancies.push(MirrorDiscrepancy::Missing {
+          inscription_id,
+          satpoint,
+          genesis_height,
+        });
+        continue;
+      };
+
+      let index = self.mirror_location(mirror, satpoint)?;
+      let mirror = MirrorLocation {
+        satpoint: row.satpoint,
+        address: row.address,
+        output_value: row.output_value,
+      };
+
+      if index != mirror {
+        discrepancies.push(MirrorDiscrepancy::Mismatched {
+          inscription_id,
+          index,
+          mirror,
+          genesis_height,
+          height: row.height,
+        });
+      }
+    }
+
+    Ok(discrepancies)
+  }
+
+  /// How far `order` has got, from Bitcoin Core for its transactions and
+  /// from the index for its inscriptions.
+  pub fn get_order_state(&self, order: &Order) -> Result<OrderState> {
+    fn all(txids: &[Txid], mut f: impl FnMut(&Txid) -> Result<bool>) -> Result<bool> {
+      for txid in txids {
+        if !f(txid)? {
+          return Ok(false);
+        }
+      }
+      Ok(true)
+    }
+
+    let confirmations = |txid: &Txid| -> Result<Option<u32>> {
+      Ok(
+        self
+          .client
+          .get_raw_transaction_info(txid, None)
+          .into_option()?
+          .map(|info| info.confirmations.unwrap_or_default()),
+      )
+    };
+
+    if !order.reveal_txids.is_empty() {
+      if all(&order.reveal_txids, |txid| {
+        Ok(
+          self
+            .get_inscription_entry(InscriptionId::from(*txid))?
+            .is_some(),
+        )
+      })? {
+        return Ok(OrderState::InscriptionIndexed);
+      }
+
+      if all(&order.reveal_txids, |txid| {
+        Ok(confirmations(txid)?.unwrap_or_default() > 0)
+      })? {
+        return Ok(OrderState::RevealsConfirmed);
+      }
+    }
+
+    Ok(match confirmations(&order.commit_txid)? {
+      None => OrderState::Built,
+      Some(0) => OrderState::CommitSeen,
+      Some(_) => OrderState::CommitConfirmed,
+    })
+  }
+
+  /// Whether the mirror has a row for an inscription at `satpoint`.
+  /// Inscriptions that are lost or unbound are not mirrored.
+  fn is_mirrored(satpoint: SatPoint) -> bool {
+    satpoint.outpoint != OutPoint::null() && satpoint.outpoint != unbound_outpoint()
+  }
+
+  /// The mirror row for an inscription at `satpoint`. Inscriptions that are
+  /// lost or unbound are not mirrored, matching `Updater::index_block`.
+  fn mirror_inscription(
+    &self,
+    mirror: &dyn MirrorStore,
+    inscription_id: InscriptionId,
+    old_satpoint: Option<SatPoint>,
+    satpoint: SatPoint,
+  ) -> Result<Option<MirrorInscription>> {
+    if !Self::is_mirrored(satpoint) {
+      return Ok(None);
+    }
+
+    let location = self.mirror_location(mirror, satpoint)?;
+
+    let entry = self
+      .get_inscription_entry(inscription_id)?
+      .ok_or_else(|| anyhow!("inscription {inscription_id} not found"))?;
+
+    let inscription = self
+      .get_transaction(inscription_id.txid)?
+      .and_then(|tx| Inscription::from_transaction(&tx));
+
+    Ok(Some(MirrorInscription {
+      inscription_id,
+      old_satpoint,
+      new_satpoint: satpoint,
+      new_address: location.address,
+      output_value: location.output_value.unwrap_or_default(),
+      metadata: Some(InscriptionMetadata {
+        number: entry.number,
+        genesis_height: entry.height,
+        genesis_fee: entry.fee,
+        timestamp: entry.timestamp,
+        content_type: inscription
+          .as_ref()
+          .and_then(|inscription| inscription.content_type())
+          .map(str::to_owned),
+        content_length: inscription
+          .as_ref()
+          .and_then(|inscription| inscription.content_length())
+          .map(|length| length.try_into().unwrap()),
+      }),
+    }))
+  }
+
+  /// Where the mirror should have an inscription at `satpoint`, with the
+  /// address and value of the output it's on.
+  fn mirror_location(
+    &self,
+    mirror: &dyn MirrorStore,
+    satpoint: SatPoint,
+  ) -> Result<MirrorLocation> {
+    let output = self.mirror_output(satpoint.outpoint)?;
+
+    Ok(MirrorLocation {
+      satpoint,
+      address: Address::from_script(&output.script_pubkey, mirror.network())
+        .map(|address| address.to_string())
+        .unwrap_or_default(),
+      output_value: Some(output.value),
+    })
+  }
+
+  /// The output an inscription is on, from the address index if it has the
+  /// output, and otherwise from Bitcoin Core.
+  fn mirror_output(&self, outpoint: OutPoint) -> Result<TxOut> {
+    match self.database.begin_read()?.open_table(OUTPOINT_TO_TXOUT) {
+      Ok(outpoint_to_txout) => {
+        if let Some(txout) = outpoint_to_txout.get(&outpoint.store())? {
+          return Ok(consensus::encode::deserialize(txout.value())?);
+        }
+      }
+      Err(redb::Error::TableDoesNotExist(_)) => {}
+      Err(err) => return Err(err.into()),
+    }
+
+    self
+      .get_transaction(outpoint.txid)?
+      .and_then(|tx| tx.output.into_iter().nth(outpoint.vout.try_into().unwrap()))
+      .ok_or_else(|| anyhow!("output {outpoint} not found"))
+  }
+
+  pub fn update(&self) -> Result {
+    Updater::update(self)
+  }
+
+  pub fn options(&self) -> &Options {
+    &self.options
+  }
+
+  /// `fee_rate` if given, otherwise Bitcoin Core's `estimatesmartfee` rate
+  /// for confirmation within `--fee-target` blocks in `--fee-mode`.
+  pub fn fee_rate(&self, fee_rate: Option<FeeRate>) -> Result<FeeRate> {
+    if let Some(fee_rate) = fee_rate {
+      return Ok(fee_rate);
+    }
+
+    let target = self.options.fee_target();
+    let estimate = self
+      .client
+      .estimate_smart_fee(target, Some(self.options.fee_mode.into()))?;
+
+    match estimate.fee_rate {
+      Some(fee_rate) => FeeRate::try_from(fee_rate.to_sat() as f64 / 1000.0),
+      None => bail!(
+        "failed to estimate fee rate for confirmation within {target} blocks: {}",
+        estimate.errors.unwrap_or_default().join(", ")
+      ),
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn is_reorged(&self) -> bool {
+    self.reorged.load(atomic::Ordering::Relaxed)
+  }
+
+  fn begin_read(&self) -> Result<rtx::Rtx> {
+    Ok(rtx::Rtx(self.database.begin_read()?))
+  }
+
+  fn begin_write(&self) -> Result<WriteTransaction> {
+    if cfg!(test) {
+      let mut tx = self.database.begin_write()?;
+      tx.set_durability(redb::Durability::None);
+      Ok(tx)
+    } else {
+      Ok(self.database.begin_write()?)
+    }
+  }
+
+  fn increment_statistic(wtx: &WriteTransaction, statistic: Statistic, n: u64) -> Result {
+    let mut statistic_to_count = wtx.open_table(STATISTIC_TO_COUNT)?;
+    let value = statistic_to_count
+      .get(&(statistic.key()))?
+      .map(|x| x.value())
+      .unwrap_or(0)
+      + n;
+    statistic_to_count.insert(&statistic.key(), &value)?;
+    Ok(())
+  }
+
+  #[cfg(test)]
+  pub(crate) fn statistic(&self, statistic: Statistic) -> u64 {
+    self
+      .database
+      .begin_read()
+      .unwrap()
+      .open_table(STATISTIC_TO_COUNT)
+      .unwrap()
+      .get(&statistic.key())
+      .unwrap()
+      .map(|x| x.value())
+      .unwrap_or(0)
+  }
+
+  pub(crate) fn height(&self) -> Result<Option<Height>> {
+    self.begin_read()?.height()
+  }
+
+  pub(crate) fn block_count(&self) -> Result<u64> {
+    self.begin_read()?.block_count()
+  }
+
+  pub(crate) fn blocks(&self, take: usize) -> Result<Vec<(u64, BlockHash)>> {
+    let mut blocks = Vec::new();
+
+    let rtx = self.begin_read()?;
+
+    let block_count = rtx.block_count()?;
+
+    let height_to_block_hash = rtx.0.open_table(HEIGHT_TO_BLOCK_HASH)?;
+
+    for next in height_to_block_hash.range(0..block_count)?.rev().take(take) {
+      blocks.push((next.0.value(), Entry::load(*next.1.value())));
+    }
+
+    Ok(blocks)
+  }
+
+  pub(crate) fn rare_sat_satpoints(&self) -> Result<Option<Vec<(Sat, SatPoint)>>> {
+    if self.has_sat_index()? {
+      let mut result = Vec::new();
+
+      let rtx = self.database.begin_read()?;
+
+      let sat_to_satpoint = rtx.open_table(SAT_TO_SATPOINT)?;
+
+      for (sat, satpoint) in sat_to_satpoint.range(0..)? {
+        result.push((Sat(sat.value()), Entry::load(*satpoint.value())));
+      }
+
+      Ok(Some(result))
+    } else {
+      Ok(None)
+    }
+  }
+
+  pub(crate) fn rare_sat_satpoint(&self, sat: Sat) -> Result<Option<SatPoint>> {
+    if self.has_sat_index()? {
+      Ok(
         self
           .database
           .begin_read()?
@@ -1320,6 +1705,7 @@ mod tests {
   struct ContextBuilder {
     args: Vec<OsString>,
     tempdir: Option<TempDir>,
+    mirror: Option<Arc<dyn MirrorStore>>,
   }
 
   impl ContextBuilder {
@@ -1348,7 +1734,10 @@ mod tests {
       ];
 
       let options = Options::try_parse_from(command.into_iter().chain(self.args)).unwrap();
-      let index = Index::open(&options)?;
+      let index = match self.mirror {
+        Some(mirror) => Index::open_with_mirror(&options, mirror)?,
+        None => Index::open(&options)?,
+      };
       index.update().unwrap();
 
       Ok(Context {
@@ -1373,6 +1762,11 @@ mod tests {
       self.tempdir = Some(tempdir);
       self
     }
+
+    fn mirror(mut self, mirror: Arc<dyn MirrorStore>) -> Self {
+      self.mirror = Some(mirror);
+      self
+    }
   }
 
   struct Context {
@@ -1388,6 +1782,7 @@ mod tests {
       ContextBuilder {
         args: Vec::new(),
         tempdir: None,
+        mirror: None,
       }
     }
 
@@ -1632,155 +2027,1023 @@ mod tests {
   }
 
   #[test]
-  fn list_null_output() {
-    let context = Context::builder().arg("--index-sats").build();
-
+  fn list_null_output() {
+    let context = Context::builder().arg("--index-sats").build();
+
+    context.mine_blocks(1);
+    let no_value_output = TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      fee: 50 * COIN_VALUE,
+      ..Default::default()
+    };
+    let txid = context.rpc_server.broadcast_tx(no_value_output);
+    context.mine_blocks(1);
+
+    assert_eq!(
+      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
+      List::Unspent(Vec::new())
+    );
+  }
+
+  #[test]
+  fn list_null_input() {
+    let context = Context::builder().arg("--index-sats").build();
+
+    context.mine_blocks(1);
+    let no_value_output = TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      fee: 50 * COIN_VALUE,
+      ..Default::default()
+    };
+    context.rpc_server.broadcast_tx(no_value_output);
+    context.mine_blocks(1);
+
+    let no_value_input = TransactionTemplate {
+      inputs: &[(2, 1, 0)],
+      fee: 0,
+      ..Default::default()
+    };
+    let txid = context.rpc_server.broadcast_tx(no_value_input);
+    context.mine_blocks(1);
+
+    assert_eq!(
+      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
+      List::Unspent(Vec::new())
+    );
+  }
+
+  #[test]
+  fn list_spent_output() {
+    let context = Context::builder().arg("--index-sats").build();
+    context.mine_blocks(1);
+    context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      fee: 0,
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+    let txid = context.rpc_server.tx(1, 0).txid();
+    assert_eq!(
+      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
+      List::Spent,
+    );
+  }
+
+  #[test]
+  fn list_unknown_output() {
+    let context = Context::builder().arg("--index-sats").build();
+
+    assert_eq!(
+      context
+        .index
+        .list(
+          "0000000000000000000000000000000000000000000000000000000000000000:0"
+            .parse()
+            .unwrap()
+        )
+        .unwrap(),
+      None
+    );
+  }
+
+  #[test]
+  fn address_index_tracks_unspent_outputs() {
+    let context = Context::builder().arg("--index-addresses").build();
+    let coinbase = context.mine_blocks(1)[0].txdata[0].txid();
+
+    assert_eq!(
+      context
+        .index
+        .get_unspent_outputs_by_script_pubkey(&Script::new())
+        .unwrap(),
+      [(
+        OutPoint::new(coinbase, 0),
+        Amount::from_sat(50 * COIN_VALUE)
+      )]
+      .into_iter()
+      .collect(),
+    );
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      outputs: 2,
+      fee: 0,
+      ..Default::default()
+    });
+    let coinbase = context.mine_blocks(1)[0].txdata[0].txid();
+
+    assert_eq!(
+      context
+        .index
+        .get_unspent_outputs_by_script_pubkey(&Script::new())
+        .unwrap(),
+      [
+        (
+          OutPoint::new(coinbase, 0),
+          Amount::from_sat(50 * COIN_VALUE)
+        ),
+        (OutPoint::new(txid, 0), Amount::from_sat(25 * COIN_VALUE)),
+        (OutPoint::new(txid, 1), Amount::from_sat(25 * COIN_VALUE)),
+      ]
+      .into_iter()
+      .collect(),
+    );
+  }
+
+  #[test]
+  fn address_index_tracks_inscriptions() {
+    let context = Context::builder().arg("--index-addresses").build();
+    context.mine_blocks(1);
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+
+    assert_eq!(
+      context
+        .index
+        .get_inscriptions_by_script_pubkey(&Script::new())
+        .unwrap(),
+      [(
+        SatPoint {
+          outpoint: OutPoint { txid, vout: 0 },
+          offset: 0,
+        },
+        InscriptionId::from(txid),
+      )]
+      .into_iter()
+      .collect(),
+    );
+  }
+
+  #[test]
+  fn address_methods_require_address_index() {
+    let context = Context::builder().build();
+    let address = "bcrt1qs758ursh4q9z627kt3pp5yysm78ddny6txaqgw"
+      .parse::<Address>()
+      .unwrap();
+
+    assert_eq!(
+      context
+        .index
+        .get_address_unspent_outputs(&address)
+        .unwrap_err()
+        .to_string(),
+      "Listing address outputs requires index created with `--index-addresses` flag",
+    );
+  }
+
+  #[test]
+  fn find_first_sat() {
+    let context = Context::builder().arg("--index-sats").build();
+    assert_eq!(
+      context.index.find(0).unwrap().unwrap(),
+      SatPoint {
+        outpoint: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0"
+          .parse()
+          .unwrap(),
+        offset: 0,
+      }
+    )
+  }
+
+  #[test]
+  fn find_second_sat() {
+    let context = Context::builder().arg("--index-sats").build();
+    assert_eq!(
+      context.index.find(1).unwrap().unwrap(),
+      SatPoint {
+        outpoint: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0"
+          .parse()
+          .unwrap(),
+        offset: 1,
+      }
+    )
+  }
+
+  #[test]
+  fn find_first_sat_of_second_block() {
+    let context = Context::builder().arg("--index-sats").build();
+    context.mine_blocks(1);
+    assert_eq!(
+      context.index.find(50 * COIN_VALUE).unwrap().unwrap(),
+      SatPoint {
+        outpoint: "30f2f037629c6a21c1f40ed39b9bd6278df39762d68d07f49582b23bcb23386a:0"
+          .parse()
+          .unwrap(),
+        offset: 0,
+      }
+    )
+  }
+
+  #[test]
+  fn find_unmined_sat() {
+    let context = Context::builder().arg("--index-sats").build();
+    assert_eq!(context.index.find(50 * COIN_VALUE).unwrap(), None);
+  }
+
+  #[test]
+  fn find_first_sat_spent_in_second_block() {
+    let context = Context::builder().arg("--index-sats").build();
+    context.mine_blocks(1);
+    let spend_txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      fee: 0,
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+    assert_eq!(
+      context.index.find(50 * COIN_VALUE).unwrap().unwrap(),
+      SatPoint {
+        outpoint: OutPoint::new(spend_txid, 0),
+        offset: 0,
+      }
+    )
+  }
+
+  #[test]
+  fn inscriptions_are_tracked_correctly() {
+    for context in Context::configurations() {
+      context.mine_blocks(1);
+
+      let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(1, 0, 0)],
+        witness: inscription("text/plain", "hello").to_witness(),
+        ..Default::default()
+      });
+      let inscription_id = InscriptionId::from(txid);
+
+      context.mine_blocks(1);
+
+      context.index.assert_inscription_location(
+        inscription_id,
+        SatPoint {
+          outpoint: OutPoint { txid, vout: 0 },
+          offset: 0,
+        },
+        Some(50 * COIN_VALUE),
+      );
+    }
+  }
+
+  #[test]
+  fn reorg_height_rolls_back_blocks() {
+    for context in Context::configurations() {
+      context.mine_blocks(1);
+
+      let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(1, 0, 0)],
+        witness: inscription("text/plain", "hello").to_witness(),
+        ..Default::default()
+      });
+      let inscription_id = InscriptionId::from(txid);
+      context.mine_blocks(1);
+
+      let send = context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(2, 1, 0)],
+        ..Default::default()
+      });
+      context.mine_blocks(1);
+
+      context.index.assert_inscription_location(
+        inscription_id,
+        SatPoint {
+          outpoint: OutPoint {
+            txid: send,
+            vout: 0,
+          },
+          offset: 0,
+        },
+        Some(50 * COIN_VALUE),
+      );
+
+      context.index.reorg_height(2).unwrap();
+      assert_eq!(context.index.height().unwrap(), Some(Height(2)));
+
+      let satpoint = SatPoint {
+        outpoint: OutPoint { txid, vout: 0 },
+        offset: 0,
+      };
+
+      context
+        .index
+        .assert_inscription_location(inscription_id, satpoint, Some(50 * COIN_VALUE));
+
+      if context.index.has_sat_index().unwrap() {
+        assert_eq!(
+          context.index.list(OutPoint { txid, vout: 0 }).unwrap(),
+          Some(List::Unspent(vec![(50 * COIN_VALUE, 100 * COIN_VALUE)]))
+        );
+      }
+
+      context.rpc_server.invalidate_tip();
+      context.mine_blocks(1);
+
+      assert_eq!(context.index.height().unwrap(), Some(Height(3)));
+      context
+        .index
+        .assert_inscription_location(inscription_id, satpoint, Some(50 * COIN_VALUE));
+    }
+  }
+
+  #[test]
+  fn reorg_height_rolls_back_statistics() {
+    for context in Context::configurations() {
+      context.mine_blocks(1);
+
+      let statistics = || {
+        [
+          Statistic::Commits,
+          Statistic::OutputsTraversed,
+          Statistic::SatRanges,
+        ]
+        .map(|statistic| context.index.statistic(statistic))
+      };
+      let before = statistics();
+
+      context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(1, 0, 0)],
+        outputs: 2,
+        ..Default::default()
+      });
+      context.mine_blocks(1);
+      context.mine_blocks(1);
+      assert_ne!(statistics(), before);
+
+      context.index.reorg_height(1).unwrap();
+      assert_eq!(statistics(), before);
+    }
+  }
+
+  #[test]
+  fn reorg_height_rolls_back_address_index() {
+    let context = Context::builder().arg("--index-addresses").build();
+    context.mine_blocks(1);
+
+    let before = context
+      .index
+      .get_unspent_outputs_by_script_pubkey(&Script::new())
+      .unwrap();
+
+    context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      outputs: 2,
+      fee: 0,
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+
+    context.index.reorg_height(1).unwrap();
+
+    assert_eq!(
+      context
+        .index
+        .get_unspent_outputs_by_script_pubkey(&Script::new())
+        .unwrap(),
+      before,
+    );
+  }
+
+  #[test]
+  fn reorg_height_rolls_back_mirror() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(1);
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      ..Default::default()
+    });
+    let inscription_id = InscriptionId::from(txid);
+    context.mine_blocks(1);
+
+    let send = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(2, 1, 0)],
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(3));
+    assert_eq!(
+      mirror.get_inscription_by_address("").unwrap(),
+      [(
+        SatPoint {
+          outpoint: OutPoint {
+            txid: send,
+            vout: 0
+          },
+          offset: 0,
+        },
+        inscription_id
+      )]
+      .into_iter()
+      .collect(),
+    );
+
+    context.index.reorg_height(2).unwrap();
+
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(2));
+    assert_eq!(
+      mirror.get_inscription_by_address("").unwrap(),
+      [(
+        SatPoint {
+          outpoint: OutPoint { txid, vout: 0 },
+          offset: 0,
+        },
+        inscription_id
+      )]
+      .into_iter()
+      .collect(),
+    );
+  }
+
+  #[test]
+  fn reorg_height_fails_when_mirror_rollback_fails() {
+    let tempdir = TempDir::new().unwrap();
+    let path = tempdir.path().join("mirror.sqlite");
+    let mirror = Arc::new(SqliteDatabase::open(&path, Network::Regtest).unwrap());
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(2);
+
+    rusqlite::Connection::open(&path)
+      .unwrap()
+      .execute("DROP TABLE INSCRIPTION_ID_AND_SATPOINT_LOG", [])
+      .unwrap();
+
+    assert!(context.index.reorg_height(1).is_err());
+    assert_eq!(context.index.height().unwrap(), Some(Height(2)));
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(2));
+  }
+
+  #[test]
+  fn mirror_keeps_metadata_across_transfers() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(1);
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      fee: 100,
+      ..Default::default()
+    });
+    let inscription_id = InscriptionId::from(txid);
+    let blocks = context.mine_blocks(1);
+
+    let metadata = InscriptionMetadata {
+      number: 0,
+      genesis_height: 2,
+      genesis_fee: 100,
+      timestamp: blocks[0].header.time,
+      content_type: Some("text/plain".into()),
+      content_length: Some(5),
+    };
+
+    let rows = mirror.get_inscriptions_by_address("").unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].inscription_id, inscription_id);
+    assert_eq!(rows[0].output_value, Some(50 * COIN_VALUE - 100));
+    assert_eq!(rows[0].metadata.as_ref(), Some(&metadata));
+
+    let send = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(2, 1, 0)],
+      ..Default::default()
+    });
+    context.mine_blocks(1);
+
+    let rows = mirror.get_inscriptions_by_address("").unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(
+      rows[0].satpoint.outpoint,
+      OutPoint {
+        txid: send,
+        vout: 0
+      }
+    );
+    assert_eq!(rows[0].height, Some(3));
+    assert_eq!(rows[0].metadata.as_ref(), Some(&metadata));
+
+    context.index.reorg_height(2).unwrap();
+
+    let rows = mirror.get_inscriptions_by_address("").unwrap();
+    assert_eq!(rows[0].satpoint.outpoint, OutPoint { txid, vout: 0 });
+    assert_eq!(rows[0].output_value, Some(50 * COIN_VALUE - 100));
+    assert_eq!(rows[0].metadata.as_ref(), Some(&metadata));
+  }
+
+  #[test]
+  fn mirror_records_transfers() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(1);
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      ..Default::default()
+    });
+    let inscription_id = InscriptionId::from(txid);
+    let genesis = context.mine_blocks(1);
+
+    let send = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(2, 1, 0)],
+      ..Default::default()
+    });
+    let transfer = context.mine_blocks(1);
+
+    let genesis_satpoint = SatPoint {
+      outpoint: OutPoint { txid, vout: 0 },
+      offset: 0,
+    };
+
+    let transfers = vec![
+      TransferRow {
+        inscription_id,
+        from_satpoint: None,
+        to_satpoint: genesis_satpoint,
+        from_address: None,
+        to_address: "".into(),
+        txid,
+        height: 2,
+        timestamp: genesis[0].header.time,
+      },
+      TransferRow {
+        inscription_id,
+        from_satpoint: Some(genesis_satpoint),
+        to_satpoint: SatPoint {
+          outpoint: OutPoint {
+            txid: send,
+            vout: 0,
+          },
+          offset: 0,
+        },
+        from_address: Some("".into()),
+        to_address: "".into(),
+        txid: send,
+        height: 3,
+        timestamp: transfer[0].header.time,
+      },
+    ];
+
+    assert_eq!(
+      mirror.get_inscription_transfers(inscription_id).unwrap(),
+      transfers
+    );
+    assert_eq!(mirror.get_address_transfers("").unwrap(), transfers);
+    assert_eq!(mirror.get_address_transfers("foo").unwrap(), Vec::new());
+
+    context.index.reorg_height(2).unwrap();
+
+    assert_eq!(
+      mirror.get_inscription_transfers(inscription_id).unwrap(),
+      transfers[..1]
+    );
+  }
+
+  #[test]
+  fn backfill_mirror() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(2);
+
+    for height in 1..3 {
+      context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(height, 0, 0)],
+        witness: inscription("text/plain", "hello").to_witness(),
+        ..Default::default()
+      });
+    }
+    context.mine_blocks(1);
+
+    let rows = || {
+      let mut rows = mirror.get_inscriptions_by_address("").unwrap();
+      rows.sort_by_key(|row| row.inscription_id.store());
+      rows
+    };
+
+    let mut before = rows();
+    assert_eq!(before.len(), 2);
+
+    assert_eq!(context.index.backfill_mirror().unwrap(), 2);
+    assert_eq!(rows(), before);
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(3));
+    assert_eq!(mirror.get_backfill_progress().unwrap(), None);
+
+    mirror.start_backfill(3).unwrap();
+    mirror
+      .backfill_inscriptions(Vec::new(), before[0].inscription_id)
+      .unwrap();
+
+    assert_eq!(
+      context.index.catch_up_mirror().unwrap_err().to_string(),
+      "mirror backfill of block 3 is unfinished, resume it with `ord_index --backfill`",
+    );
+
+    assert_eq!(context.index.backfill_mirror().unwrap(), 1);
+    before.remove(0);
+    assert_eq!(rows(), before);
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(3));
+  }
+
+  #[test]
+  fn update_refuses_to_advance_past_mirror_gap() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(1);
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(1, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      ..Default::default()
+    });
+    context.mine_blocks(2);
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(3));
+
+    mirror.rollback_inscriptions(0).unwrap();
+    context.rpc_server.mine_blocks(1);
+
+    assert_eq!(
+      context.index.update().unwrap_err().to_string(),
+      "mirror is synced to block 0 but the index is at block 3, catch up mirror first",
+    );
+    assert_eq!(context.index.height().unwrap(), Some(Height(3)));
+
+    assert_eq!(context.index.catch_up_mirror().unwrap(), 3);
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(3));
+    assert_eq!(
+      mirror.get_inscription_by_address("").unwrap(),
+      [(
+        SatPoint {
+          outpoint: OutPoint { txid, vout: 0 },
+          offset: 0,
+        },
+        InscriptionId::from(txid)
+      )]
+      .into_iter()
+      .collect(),
+    );
+
+    context.index.update().unwrap();
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(4));
+  }
+
+  #[test]
+  fn catch_up_mirror_backfills_past_undo_logs() {
+    let tempdir = TempDir::new().unwrap();
+    let path = tempdir.path().join("mirror.sqlite");
+    let mirror = Arc::new(SqliteDatabase::open(&path, Network::Regtest).unwrap());
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
     context.mine_blocks(1);
-    let no_value_output = TransactionTemplate {
+
+    let txid = context.rpc_server.broadcast_tx(TransactionTemplate {
       inputs: &[(1, 0, 0)],
-      fee: 50 * COIN_VALUE,
+      witness: inscription("text/plain", "hello").to_witness(),
       ..Default::default()
-    };
-    let txid = context.rpc_server.broadcast_tx(no_value_output);
+    });
     context.mine_blocks(1);
 
+    mirror
+      .repair_inscriptions(
+        vec![MirrorInscription {
+          inscription_id: inscription_id(9),
+          old_satpoint: None,
+          new_satpoint: satpoint(9, 0),
+          new_address: String::new(),
+          output_value: 1,
+          metadata: None,
+        }],
+        Vec::new(),
+      )
+      .unwrap();
+    rusqlite::Connection::open(&path)
+      .unwrap()
+      .execute("UPDATE INSCRIPTION_SYNC_HEIGHT SET height = 0", [])
+      .unwrap();
+
+    let wtx = context.index.database.begin_write().unwrap();
+    wtx
+      .open_table(HEIGHT_TO_UNDO_LOG)
+      .unwrap()
+      .remove(&1)
+      .unwrap();
+    wtx.commit().unwrap();
+
+    assert_eq!(context.index.catch_up_mirror().unwrap(), 2);
+    assert_eq!(mirror.get_synced_height().unwrap(), Some(2));
     assert_eq!(
-      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
-      List::Unspent(Vec::new())
+      mirror.get_inscription_by_address("").unwrap(),
+      [(
+        SatPoint {
+          outpoint: OutPoint { txid, vout: 0 },
+          offset: 0,
+        },
+        InscriptionId::from(txid)
+      )]
+      .into_iter()
+      .collect(),
     );
   }
 
   #[test]
-  fn list_null_input() {
-    let context = Context::builder().arg("--index-sats").build();
+  fn verify_and_repair_mirror() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(3);
 
+    for height in 1..4 {
+      context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(height, 0, 0)],
+        witness: inscription("text/plain", "hello").to_witness(),
+        ..Default::default()
+      });
+    }
     context.mine_blocks(1);
-    let no_value_output = TransactionTemplate {
-      inputs: &[(1, 0, 0)],
-      fee: 50 * COIN_VALUE,
-      ..Default::default()
+
+    let rows = || {
+      let mut rows = mirror.get_inscriptions_after(None, 100).unwrap();
+      rows.sort_by_key(|row| row.inscription_id.store());
+      rows
     };
-    context.rpc_server.broadcast_tx(no_value_output);
-    context.mine_blocks(1);
 
-    let no_value_input = TransactionTemplate {
-      inputs: &[(2, 1, 0)],
-      fee: 0,
-      ..Default::default()
+    let before = rows();
+    assert_eq!(before.len(), 3);
+    assert_eq!(context.index.verify_mirror(false).unwrap(), Vec::new());
+
+    let wrong = |inscription_id, new_satpoint| MirrorInscription {
+      inscription_id,
+      old_satpoint: None,
+      new_satpoint,
+      new_address: "wrong".into(),
+      output_value: 0,
+      metadata: None,
     };
-    let txid = context.rpc_server.broadcast_tx(no_value_input);
-    context.mine_blocks(1);
 
-    assert_eq!(
-      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
-      List::Unspent(Vec::new())
-    );
+    mirror
+      .repair_inscriptions(
+        vec![
+          wrong(before[1].inscription_id, satpoint(1, 0)),
+          wrong(before[2].inscription_id, before[2].satpoint),
+          wrong(inscription_id(1), satpoint(2, 0)),
+        ],
+        vec![before[0].inscription_id],
+      )
+      .unwrap();
+
+    let location = |row: &InscriptionRow| MirrorLocation {
+      satpoint: row.satpoint,
+      address: row.address.clone(),
+      output_value: row.output_value,
+    };
+
+    let discrepancies = vec![
+      MirrorDiscrepancy::Missing {
+        inscription_id: before[0].inscription_id,
+        satpoint: before[0].satpoint,
+        genesis_height: 4,
+      },
+      MirrorDiscrepancy::Mismatched {
+        inscription_id: before[1].inscription_id,
+        index: location(&before[1]),
+        mirror: MirrorLocation {
+          satpoint: satpoint(1, 0),
+          address: "wrong".into(),
+          output_value: Some(0),
+        },
+        genesis_height: 4,
+        height: Some(4),
+      },
+      MirrorDiscrepancy::Mismatched {
+        inscription_id: before[2].inscription_id,
+        index: location(&before[2]),
+        mirror: MirrorLocation {
+          satpoint: before[2].satpoint,
+          address: "wrong".into(),
+          output_value: Some(0),
+        },
+        genesis_height: 4,
+        height: Some(4),
+      },
+      MirrorDiscrepancy::Extra {
+        inscription_id: inscription_id(1),
+        satpoint: satpoint(2, 0),
+        height: Some(4),
+      },
+    ];
+
+    let mut found = context.index.verify_mirror(false).unwrap();
+    found.sort_by_key(|discrepancy| discrepancy.to_string());
+    let mut expected = discrepancies;
+    expected.sort_by_key(|discrepancy| discrepancy.to_string());
+    assert_eq!(found, expected);
+
+    assert_eq!(context.index.verify_mirror(true).unwrap().len(), 4);
+    assert_eq!(context.index.verify_mirror(false).unwrap(), Vec::new());
+    assert_eq!(rows(), before);
   }
 
   #[test]
-  fn list_spent_output() {
-    let context = Context::builder().arg("--index-sats").build();
+  fn verify_mirror_pages_through_mirror() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(3);
+
+    for height in 1..4 {
+      context.rpc_server.broadcast_tx(TransactionTemplate {
+        inputs: &[(height, 0, 0)],
+        witness: inscription("text/plain", "hello").to_witness(),
+        ..Default::default()
+      });
+    }
     context.mine_blocks(1);
-    context.rpc_server.broadcast_tx(TransactionTemplate {
+
+    let mut pages = Vec::new();
+    let mut after = None;
+    loop {
+      let page = mirror.get_inscriptions_after(after, 2).unwrap();
+      let Some(last) = page.last() else {
+        break;
+      };
+      after = Some(last.inscription_id);
+      pages.push(
+        page
+          .iter()
+          .map(|row| row.inscription_id.to_string())
+          .collect::<Vec<String>>(),
+      );
+    }
+
+    assert_eq!(pages.len(), 2);
+    let ids = pages.concat();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(ids, sorted);
+    assert_eq!(ids.len(), 3);
+
+    let some = mirror
+      .get_inscriptions_in(&[
+        ids[0].parse().unwrap(),
+        inscription_id(1),
+        ids[2].parse().unwrap(),
+      ])
+      .unwrap();
+    assert_eq!(some.len(), 2);
+    assert_eq!(mirror.get_inscriptions_in(&[]).unwrap(), Vec::new());
+  }
+
+  #[test]
+  fn order_state_follows_transactions() {
+    let tempdir = TempDir::new().unwrap();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&tempdir.path().join("mirror.sqlite"), Network::Regtest).unwrap(),
+    );
+    let context = Context::builder()
+      .tempdir(tempdir)
+      .mirror(mirror.clone())
+      .build();
+    context.mine_blocks(2);
+
+    let commit = context.rpc_server.broadcast_tx(TransactionTemplate {
       inputs: &[(1, 0, 0)],
-      fee: 0,
       ..Default::default()
     });
-    context.mine_blocks(1);
-    let txid = context.rpc_server.tx(1, 0).txid();
-    assert_eq!(
-      context.index.list(OutPoint::new(txid, 0)).unwrap().unwrap(),
-      List::Spent,
-    );
-  }
 
-  #[test]
-  fn list_unknown_output() {
-    let context = Context::builder().arg("--index-sats").build();
+    let mut order = Order {
+      commit_txid: commit,
+      reveal_txids: vec![txid(1)],
+      reveals: vec!["0100".into()],
+      inputs: vec![OutPoint {
+        txid: context.rpc_server.tx(1, 0).txid(),
+        vout: 0,
+      }],
+      source: address(),
+      destination: recipient(),
+      service_fee: 3_000,
+      satpoint_fee: 10_000,
+      network_fee: 500,
+      commit_fee: 200,
+      created_at: 1_700_000_000,
+    };
+
+    mirror.insert_order(&order).unwrap();
+    assert_eq!(mirror.get_order(commit).unwrap().as_ref(), Some(&order));
+    assert_eq!(mirror.get_order(txid(2)).unwrap(), None);
 
     assert_eq!(
       context
         .index
-        .list(
-          "0000000000000000000000000000000000000000000000000000000000000000:0"
-            .parse()
-            .unwrap()
-        )
+        .get_order_state(&Order {
+          commit_txid: txid(2),
+          ..order.clone()
+        })
         .unwrap(),
-      None
+      OrderState::Built
     );
-  }
-
-  #[test]
-  fn find_first_sat() {
-    let context = Context::builder().arg("--index-sats").build();
     assert_eq!(
-      context.index.find(0).unwrap().unwrap(),
-      SatPoint {
-        outpoint: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0"
-          .parse()
-          .unwrap(),
-        offset: 0,
-      }
-    )
-  }
+      context.index.get_order_state(&order).unwrap(),
+      OrderState::CommitSeen
+    );
 
-  #[test]
-  fn find_second_sat() {
-    let context = Context::builder().arg("--index-sats").build();
+    context.mine_blocks(1);
     assert_eq!(
-      context.index.find(1).unwrap().unwrap(),
-      SatPoint {
-        outpoint: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0"
-          .parse()
-          .unwrap(),
-        offset: 1,
-      }
-    )
-  }
+      context.index.get_order_state(&order).unwrap(),
+      OrderState::CommitConfirmed
+    );
 
-  #[test]
-  fn find_first_sat_of_second_block() {
-    let context = Context::builder().arg("--index-sats").build();
+    order.reveal_txids = vec![context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(2, 0, 0)],
+      ..Default::default()
+    })];
     context.mine_blocks(1);
     assert_eq!(
-      context.index.find(50 * COIN_VALUE).unwrap().unwrap(),
-      SatPoint {
-        outpoint: "30f2f037629c6a21c1f40ed39b9bd6278df39762d68d07f49582b23bcb23386a:0"
-          .parse()
-          .unwrap(),
-        offset: 0,
-      }
-    )
-  }
+      context.index.get_order_state(&order).unwrap(),
+      OrderState::RevealsConfirmed
+    );
 
-  #[test]
-  fn find_unmined_sat() {
-    let context = Context::builder().arg("--index-sats").build();
-    assert_eq!(context.index.find(50 * COIN_VALUE).unwrap(), None);
+    order.reveal_txids = vec![context.rpc_server.broadcast_tx(TransactionTemplate {
+      inputs: &[(3, 0, 0)],
+      witness: inscription("text/plain", "hello").to_witness(),
+      ..Default::default()
+    })];
+    context.mine_blocks(1);
+    assert_eq!(
+      context.index.get_order_state(&order).unwrap(),
+      OrderState::InscriptionIndexed
+    );
   }
 
   #[test]
-  fn find_first_sat_spent_in_second_block() {
-    let context = Context::builder().arg("--index-sats").build();
-    context.mine_blocks(1);
-    let spend_txid = context.rpc_server.broadcast_tx(TransactionTemplate {
-      inputs: &[(1, 0, 0)],
-      fee: 0,
-      ..Default::default()
-    });
+  fn reorg_height_requires_undo_log() {
+    let context = Context::builder().build();
     context.mine_blocks(1);
+
+    let wtx = context.index.database.begin_write().unwrap();
+    wtx
+      .open_table(HEIGHT_TO_UNDO_LOG)
+      .unwrap()
+      .remove(&1)
+      .unwrap();
+    wtx.commit().unwrap();
+
     assert_eq!(
-      context.index.find(50 * COIN_VALUE).unwrap().unwrap(),
-      SatPoint {
-        outpoint: OutPoint::new(spend_txid, 0),
-        offset: 0,
-      }
-    )
+      context.index.reorg_height(0).unwrap_err().to_string(),
+      "no undo log for block 1, cannot roll back to 0",
+    );
   }
 
   #[test]
-  fn inscriptions_are_tracked_correctly() {
+  fn recover_from_reorg() {
     for context in Context::configurations() {
       context.mine_blocks(1);
 
@@ -1789,19 +3052,49 @@ mod tests {
         witness: inscription("text/plain", "hello").to_witness(),
         ..Default::default()
       });
-      let inscription_id = InscriptionId::from(txid);
+      context.mine_blocks(2);
 
-      context.mine_blocks(1);
+      context.rpc_server.invalidate_tip();
+      context.rpc_server.invalidate_tip();
+      context.rpc_server.mine_blocks(3);
 
-      context.index.assert_inscription_location(
-        inscription_id,
-        SatPoint {
-          outpoint: OutPoint { txid, vout: 0 },
-          offset: 0,
-        },
-        Some(50 * COIN_VALUE),
+      assert!(context.index.update().is_err());
+      assert!(context.index.is_reorged());
+
+      assert_eq!(context.index.recover_from_reorg().unwrap(), 1);
+      assert!(!context.index.is_reorged());
+      assert_eq!(
+        context
+          .index
+          .get_inscription_satpoint_by_id(InscriptionId::from(txid))
+          .unwrap(),
+        None
       );
+
+      context.index.update().unwrap();
+      assert_eq!(context.index.height().unwrap(), Some(Height(4)));
+    }
+  }
+
+  #[test]
+  fn recover_from_reorg_deeper_than_undo_logs() {
+    let context = Context::builder().build();
+    context.mine_blocks(UNDO_LOG_DEPTH + 2);
+
+    for _ in 0..UNDO_LOG_DEPTH + 2 {
+      context.rpc_server.invalidate_tip();
     }
+    context.rpc_server.mine_blocks(UNDO_LOG_DEPTH + 3);
+
+    assert!(context.index.update().is_err());
+    assert_eq!(
+      context.index.recover_from_reorg().unwrap_err().to_string(),
+      "none of the last 101 indexed blocks are in the active chain, the reorg is deeper than the undo logs reach and the index must be rebuilt",
+    );
+    assert_eq!(
+      context.index.height().unwrap(),
+      Some(Height(UNDO_LOG_DEPTH + 2))
+    );
   }
 
   #[test]
@@ -2698,26 +3991,111 @@ mod tests {
     }
   }
 
-  #[test]
-  fn get_unspent_outputs_by_mempool_fails() {
-    for context in Context::configurations() {
-      let mut entropy = [0; 16];
-      rand::thread_rng().fill_bytes(&mut entropy);
-      let mnemonic = Mnemonic::from_entropy(&entropy).unwrap();
-      crate::subcommand::wallet::initialize_wallet(&context.options, mnemonic.to_seed("")).unwrap();
-      context.rpc_server.mine_blocks(1);
-      let result = context
-        .index
-        .get_unspent_outputs_by_mempool(
-          "tb1phsaern0qpcpqpv2h6cmu6fgae4y0lyx2tqhmqmgvv7c9whffm3rqjmlrqs",
-          BTreeMap::new(),
+  /// Serves `utxos` as the Esplora response to every request.
+  fn esplora(utxos: Arc<Mutex<Vec<utxo_provider::Utxo>>>) -> String {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+      for mut stream in listener.incoming().flatten() {
+        let mut request = [0; 4096];
+        let _ = stream.read(&mut request);
+        let body = serde_json::to_string(
+          &utxos
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|utxo| utxo_provider::ListUnspentResultEntry {
+              txid: utxo.outpoint.txid,
+              vout: utxo.outpoint.vout,
+              status: utxo_provider::ListUnspentStatusEntry {
+                confirmed: utxo.confirmed,
+                block_height: None,
+                block_hash: None,
+                block_time: None,
+              },
+              value: utxo.value,
+            })
+            .collect::<Vec<_>>(),
         )
-        .unwrap_err()
-        .to_string();
-      assert_regex_match!(
-        result,
-        r"output in Bitcoin Core wallet but not in ord index: [[:xdigit:]]{64}:\d+"
-      );
+        .unwrap();
+        let _ = write!(
+          stream,
+          "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+          body.len()
+        );
+      }
+    });
+    url
+  }
+
+  fn utxo(outpoint: OutPoint, confirmed: bool) -> utxo_provider::Utxo {
+    utxo_provider::Utxo {
+      outpoint,
+      value: Amount::from_sat(5000),
+      confirmed,
     }
   }
+
+  #[test]
+  fn get_unspent_outputs_by_mempool_skips_unindexed_outputs() {
+    let utxos = Arc::new(Mutex::new(Vec::new()));
+    let url = esplora(utxos.clone());
+    let context = Context::builder().args(["--esplora-url", &url]).build();
+    let indexed = OutPoint::new(context.mine_blocks(1)[0].txdata[0].txid(), 0);
+
+    *utxos.lock().unwrap() = vec![
+      utxo(indexed, true),
+      utxo(outpoint(1), true),
+      utxo(outpoint(2), false),
+    ];
+
+    assert_eq!(
+      context
+        .index
+        .get_unspent_outputs_by_mempool_v1(&address().to_string(), BTreeMap::new())
+        .unwrap(),
+      [(indexed, Amount::from_sat(5000))].into(),
+    );
+  }
+
+  #[test]
+  fn get_unspent_outputs_by_mempool_keeps_unconfirmed_remain_outpoints() {
+    let utxos = Arc::new(Mutex::new(vec![utxo(outpoint(1), false)]));
+    let url = esplora(utxos);
+    let context = Context::builder().args(["--esplora-url", &url]).build();
+
+    assert_eq!(
+      context
+        .index
+        .get_unspent_outputs_by_mempool_v1(&address().to_string(), [(outpoint(1), true)].into())
+        .unwrap(),
+      [(outpoint(1), Amount::from_sat(5000))].into(),
+    );
+  }
+
+  #[test]
+  fn fee_rate_falls_back_to_estimate() {
+    let context = Context::builder().build();
+    assert_eq!(
+      context
+        .index
+        .fee_rate(Some(FeeRate::try_from(7.5).unwrap()))
+        .unwrap(),
+      FeeRate::try_from(7.5).unwrap()
+    );
+    assert_eq!(
+      context.index.fee_rate(None).unwrap(),
+      FeeRate::try_from(3.0).unwrap()
+    );
+
+    let context = Context::builder()
+      .args(["--fee-target", "3", "--fee-mode", "economical"])
+      .build();
+    assert_eq!(
+      context.index.fee_rate(None).unwrap(),
+      FeeRate::try_from(4.0).unwrap()
+    );
+  }
 }
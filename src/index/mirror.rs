@@ -0,0 +1,818 @@
+use super::*;
+
+/// An external copy of each inscription's location, keyed by address, that
+/// the updater keeps in step with the index one block at a time. Every
+/// implementation keeps a change log of the rows each block overwrote, so the
+/// mirror can be rolled back along with the index on a reorg.
+pub trait MirrorStore: Send + Sync {
+  fn network(&self) -> Network;
+
+  /// The whitelist level of `address`, or `None` if it isn't whitelisted.
+  /// Addresses whitelisted without a level are at `FeePolicy::DEFAULT_LEVEL`.
+  fn get_whitelist_level(&self, address: &str) -> Result<Option<String>>;
+
+  fn get_inscriptions_by_address(&self, address: &str) -> Result<Vec<InscriptionRow>>;
+
+  /// Up to `limit` inscription rows ordered by inscription id, starting
+  /// after `after`, to read the whole mirror a page at a time.
+  fn get_inscriptions_after(
+    &self,
+    after: Option<InscriptionId>,
+    limit: usize,
+  ) -> Result<Vec<InscriptionRow>>;
+
+  /// The rows the mirror has of `inscription_ids`, in no particular order.
+  fn get_inscriptions_in(&self, inscription_ids: &[InscriptionId]) -> Result<Vec<InscriptionRow>>;
+
+  /// Height of the last block written to the mirror, or `None` if no block
+  /// has been written yet.
+  fn get_synced_height(&self) -> Result<Option<u64>>;
+
+  /// Upserts the inscriptions moved by the block at `height`, appends each
+  /// move to the transfer history with the block's `timestamp`, and advances
+  /// the synced height to it. Blocks must be written in order: the write fails
+  /// unless the mirror is synced to exactly `height - 1`, so a block is never
+  /// applied twice or skipped. The previous row of each inscription goes to
+  /// the change log in the same transaction, so `rollback_inscriptions` can
+  /// restore it if the block is reorged out.
+  fn insert_inscriptions(
+    &self,
+    height: u64,
+    timestamp: u32,
+    data: Vec<MirrorInscription>,
+  ) -> Result;
+
+  /// Advances the synced height to `height` without writing any rows, for a
+  /// run of blocks that moved no mirrored inscription. Fails unless the
+  /// mirror is synced below `height`.
+  fn skip_blocks(&self, height: u64) -> Result;
+
+  /// Replays the change log of every block above `height` backwards,
+  /// restoring each inscription's row to where it was at `height`, drops the
+  /// transfers of those blocks, and lowers the synced height to `height`.
+  /// Returns the number of change log entries replayed.
+  fn rollback_inscriptions(&self, height: u64) -> Result<usize>;
+
+  /// Every recorded move of `inscription_id`, oldest first.
+  fn get_inscription_transfers(&self, inscription_id: InscriptionId) -> Result<Vec<TransferRow>>;
+
+  /// Every recorded move of an inscription into or out of `address`, oldest
+  /// first.
+  fn get_address_transfers(&self, address: &str) -> Result<Vec<TransferRow>>;
+
+  /// Drops change log entries at or below `height`, which can no longer be
+  /// rolled back.
+  fn prune_inscription_log(&self, height: u64) -> Result;
+
+  /// Overwrites the rows of `data` and deletes the rows of `delete` as of the
+  /// synced height, to fix rows that disagree with the index. The change log
+  /// is left alone, since the rows were wrong before any block it covers.
+  fn repair_inscriptions(&self, data: Vec<MirrorInscription>, delete: Vec<InscriptionId>)
+    -> Result;
+
+  /// Where an interrupted backfill stopped, if one is in progress.
+  fn get_backfill_progress(&self) -> Result<Option<BackfillProgress>>;
+
+  /// Starts rebuilding the mirror from the index state at `height`. Every
+  /// inscription row, the change log and the synced height are dropped, so
+  /// no block can be written until `finish_backfill`. Transfer history is
+  /// kept.
+  fn start_backfill(&self, height: u64) -> Result;
+
+  /// Upserts a batch of backfilled inscriptions and records `last`, the
+  /// batch's last inscription in index order, as the point to resume from.
+  fn backfill_inscriptions(&self, data: Vec<MirrorInscription>, last: InscriptionId) -> Result;
+
+  /// Marks the mirror as synced to the backfill height and ends the
+  /// backfill.
+  fn finish_backfill(&self) -> Result;
+
+  /// Stores the keys as plaintext WIF in `INSCRIPTION_RECOVERY_KEY`, so
+  /// anyone who can read the database can sweep unrevealed commit outputs.
+  fn insert_recovery_keys(
+    &self,
+    commit_txid: Txid,
+    source: &Address,
+    recovery_keys: &[String],
+  ) -> Result;
+
+  fn get_recovery_keys(&self, commit_txid: Txid) -> Result<(Address, Vec<String>)>;
+
+  /// Records a built mint order, replacing any order with the same commit
+  /// transaction.
+  fn insert_order(&self, order: &Order) -> Result;
+
+  fn get_order(&self, commit_txid: Txid) -> Result<Option<Order>>;
+
+  fn whitelist_level(&self, address: &str) -> Option<String> {
+    self.get_whitelist_level(address).unwrap_or_else(|err| {
+      log::warn!("{err:#}");
+      None
+    })
+  }
+
+  fn is_whitelist(&self, address: &str) -> bool {
+    self.whitelist_level(address).is_some()
+  }
+
+  fn get_inscription_by_address(&self, address: &str) -> Result<BTreeMap<SatPoint, InscriptionId>> {
+    Ok(
+      self
+        .get_inscriptions_by_address(address)?
+        .into_iter()
+        .map(|row| (row.satpoint, row.inscription_id))
+        .collect(),
+    )
+  }
+}
+
+pub struct MirrorInscription {
+  pub inscription_id: InscriptionId,
+  /// Where the inscription was before the move, or `None` if it was created.
+  pub old_satpoint: Option<SatPoint>,
+  pub new_satpoint: SatPoint,
+  pub new_address: String,
+  /// Value of the output at `new_satpoint`.
+  pub output_value: u64,
+  /// Set when the inscription is created. Metadata never changes afterwards,
+  /// so transfers leave it out and the mirror keeps what it has.
+  pub metadata: Option<InscriptionMetadata>,
+}
+
+/// Where an inscription is, as the index or a mirror row has it.
+#[derive(Debug, PartialEq)]
+pub struct MirrorLocation {
+  pub satpoint: SatPoint,
+  pub address: String,
+  pub output_value: Option<u64>,
+}
+
+impl Display for MirrorLocation {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    write!(f, "{} at `{}` worth ", self.satpoint, self.address)?;
+    match self.output_value {
+      Some(output_value) => write!(f, "{output_value} sats"),
+      None => write!(f, "unknown sats"),
+    }
+  }
+}
+
+/// An inscription whose mirror row disagrees with the index. Heights are the
+/// inscription's genesis height in the index and the height the mirror row
+/// was last written at.
+#[derive(Debug, PartialEq)]
+pub enum MirrorDiscrepancy {
+  Missing {
+    inscription_id: InscriptionId,
+    satpoint: SatPoint,
+    genesis_height: u64,
+  },
+  Extra {
+    inscription_id: InscriptionId,
+    satpoint: SatPoint,
+    height: Option<u64>,
+  },
+  Mismatched {
+    inscription_id: InscriptionId,
+    index: MirrorLocation,
+    mirror: MirrorLocation,
+    genesis_height: u64,
+    height: Option<u64>,
+  },
+}
+
+impl Display for MirrorDiscrepancy {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    fn height(height: Option<u64>) -> String {
+      height.map_or("unknown".into(), |height| height.to_string())
+    }
+
+    match self {
+      Self::Missing {
+        inscription_id,
+        satpoint,
+        genesis_height,
+      } => write!(
+        f,
+        "missing {inscription_id} (inscribed at block {genesis_height}): index has {satpoint}"
+      ),
+      Self::Extra {
+        inscription_id,
+        satpoint,
+        height: mirror_height,
+      } => write!(
+        f,
+        "extra {inscription_id}: mirror has {satpoint} from block {}",
+        height(*mirror_height)
+      ),
+      Self::Mismatched {
+        inscription_id,
+        index,
+        mirror,
+        genesis_height,
+        height: mirror_height,
+      } => write!(
+        f,
+        "mismatched {inscription_id} (inscribed at block {genesis_height}): index has {index}, mirror has {mirror} from block {}",
+        height(*mirror_height)
+      ),
+    }
+  }
+}
+
+/// A backfill of the index state at `height`, done up to and including
+/// `last`.
+#[derive(Debug, PartialEq)]
+pub struct BackfillProgress {
+  pub height: u64,
+  pub last: Option<InscriptionId>,
+}
+
+impl TryFrom<(u64, Option<String>)> for BackfillProgress {
+  type Error = Error;
+
+  fn try_from((height, last): (u64, Option<String>)) -> Result<Self> {
+    Ok(Self {
+      height,
+      last: last
+        .map(|last| parse_column("last_inscription_id", &last))
+        .transpose()?,
+    })
+  }
+}
+
+/// What the index knows about an inscription from its genesis transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InscriptionMetadata {
+  pub number: u64,
+  pub genesis_height: u64,
+  pub genesis_fee: u64,
+  pub timestamp: u32,
+  pub content_type: Option<String>,
+  pub content_length: Option<u64>,
+}
+
+/// The columns of `INSCRIPTION_ID_AND_SATPOINT`, in the order `InscriptionRow`
+/// reads them.
+pub(super) type InscriptionColumns = (
+  String,
+  String,
+  String,
+  Option<u64>,
+  Option<u64>,
+  Option<u64>,
+  Option<u64>,
+  Option<u64>,
+  Option<u32>,
+  Option<String>,
+  Option<u64>,
+);
+
+/// A row of the `INSCRIPTION_ID_AND_SATPOINT` mirror table. `output_value`
+/// and `metadata` are `None` for rows written before they were mirrored.
+#[derive(Debug, PartialEq)]
+pub struct InscriptionRow {
+  pub inscription_id: InscriptionId,
+  pub satpoint: SatPoint,
+  pub address: String,
+  pub height: Option<u64>,
+  pub output_value: Option<u64>,
+  pub metadata: Option<InscriptionMetadata>,
+}
+
+impl TryFrom<InscriptionColumns> for InscriptionRow {
+  type Error = Error;
+
+  fn try_from(
+    (
+      inscription_id,
+      satpoint,
+      address,
+      height,
+      output_value,
+      number,
+      genesis_height,
+      genesis_fee,
+      timestamp,
+      content_type,
+      content_length,
+    ): InscriptionColumns,
+  ) -> Result<Self> {
+    Ok(Self {
+      inscription_id: parse_column("inscription_id", &inscription_id)?,
+      satpoint: parse_column("new_satpoint", &satpoint)?,
+      address,
+      height,
+      output_value,
+      metadata: match (number, genesis_height, genesis_fee, timestamp) {
+        (Some(number), Some(genesis_height), Some(genesis_fee), Some(timestamp)) => {
+          Some(InscriptionMetadata {
+            number,
+            genesis_height,
+            genesis_fee,
+            timestamp,
+            content_type,
+            content_length,
+          })
+        }
+        _ => None,
+      },
+    })
+  }
+}
+
+/// A row of the append-only `INSCRIPTION_TRANSFER` history. `from_address`
+/// is the address the mirror had for the inscription before the move, so it
+/// is `None` for new inscriptions and for moves out of rows the mirror never
+/// had.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TransferRow {
+  pub inscription_id: InscriptionId,
+  pub from_satpoint: Option<SatPoint>,
+  pub to_satpoint: SatPoint,
+  pub from_address: Option<String>,
+  pub to_address: String,
+  pub txid: Txid,
+  pub height: u64,
+  pub timestamp: u32,
+}
+
+pub(super) type TransferColumns = (
+  String,
+  Option<String>,
+  String,
+  Option<String>,
+  String,
+  String,
+  u64,
+  u32,
+);
+
+impl TryFrom<TransferColumns> for TransferRow {
+  type Error = Error;
+
+  fn try_from(
+    (
+      inscription_id,
+      from_satpoint,
+      to_satpoint,
+      from_address,
+      to_address,
+      txid,
+      height,
+      timestamp,
+    ): TransferColumns,
+  ) -> Result<Self> {
+    Ok(Self {
+      inscription_id: parse_column("inscription_id", &inscription_id)?,
+      from_satpoint: from_satpoint
+        .map(|from_satpoint| parse_column("from_satpoint", &from_satpoint))
+        .transpose()?,
+      to_satpoint: parse_column("to_satpoint", &to_satpoint)?,
+      from_address,
+      to_address,
+      txid: parse_column("txid", &txid)?,
+      height,
+      timestamp,
+    })
+  }
+}
+
+/// A row of the `INSCRIPTION_ID_AND_SATPOINT_LOG` change log. The `old_*`
+/// columns are the inscription's row before the block at `height`, or `None`
+/// if the block created it. Metadata isn't logged, since a block can only set
+/// it on an inscription the block created.
+pub(super) struct ChangeLogRow {
+  pub(super) inscription_id: InscriptionId,
+  pub(super) old_satpoint: Option<SatPoint>,
+  pub(super) old_address: Option<String>,
+  pub(super) old_height: Option<u64>,
+  pub(super) old_output_value: Option<u64>,
+}
+
+pub(super) type ChangeLogColumns = (
+  String,
+  Option<String>,
+  Option<String>,
+  Option<u64>,
+  Option<u64>,
+);
+
+impl TryFrom<ChangeLogColumns> for ChangeLogRow {
+  type Error = Error;
+
+  fn try_from(
+    (inscription_id, old_satpoint, old_address, old_height, old_output_value): ChangeLogColumns,
+  ) -> Result<Self> {
+    Ok(Self {
+      inscription_id: parse_column("inscription_id", &inscription_id)?,
+      old_satpoint: old_satpoint
+        .map(|old_satpoint| parse_column("old_satpoint", &old_satpoint))
+        .transpose()?,
+      old_address,
+      old_height,
+      old_output_value,
+    })
+  }
+}
+
+/// A mint order as built by `ord_server`, kept so it can be found by its
+/// commit transaction after the response is gone. `inputs` are the outputs
+/// the commit spends, and `created_at` is when the order was built, in
+/// seconds since the epoch.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Order {
+  pub commit_txid: Txid,
+  pub reveal_txids: Vec<Txid>,
+  /// Raw hex of the signed reveal transactions, in `reveal_txids` order.
+  /// Empty for orders recorded before reveals were kept.
+  #[serde(skip)]
+  pub reveals: Vec<String>,
+  pub inputs: Vec<OutPoint>,
+  pub source: Address,
+  pub destination: Address,
+  pub service_fee: u64,
+  pub satpoint_fee: u64,
+  pub network_fee: u64,
+  pub commit_fee: u64,
+  pub created_at: u64,
+}
+
+/// The columns of `INSCRIPTION_ORDER`, in the order `Order` reads them.
+/// `reveal_txids`, `inputs` and `reveals` are comma separated.
+pub(super) type OrderColumns = (
+  String,
+  String,
+  String,
+  String,
+  String,
+  u64,
+  u64,
+  u64,
+  u64,
+  u64,
+  Option<String>,
+);
+
+impl TryFrom<OrderColumns> for Order {
+  type Error = Error;
+
+  fn try_from(
+    (
+      commit_txid,
+      reveal_txids,
+      inputs,
+      source,
+      destination,
+      service_fee,
+      satpoint_fee,
+      network_fee,
+      commit_fee,
+      created_at,
+      reveals,
+    ): OrderColumns,
+  ) -> Result<Self> {
+    fn parse_list<T: FromStr>(column: &str, value: &str) -> Result<Vec<T>>
+    where
+      T::Err: Into<Error>,
+    {
+      value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| parse_column(column, item))
+        .collect()
+    }
+
+    Ok(Self {
+      commit_txid: parse_column("commit_txid", &commit_txid)?,
+      reveal_txids: parse_list("reveal_txids", &reveal_txids)?,
+      reveals: reveals
+        .iter()
+        .flat_map(|reveals| reveals.split(','))
+        .filter(|reveal| !reveal.is_empty())
+        .map(String::from)
+        .collect(),
+      inputs: parse_list("inputs", &inputs)?,
+      source: parse_column("source", &source)?,
+      destination: parse_column("destination", &destination)?,
+      service_fee,
+      satpoint_fee,
+      network_fee,
+      commit_fee,
+      created_at,
+    })
+  }
+}
+
+impl Order {
+  /// The comma separated `reveal_txids`, `inputs` and `reveals` columns.
+  pub(super) fn list_columns(&self) -> (String, String, String) {
+    fn join<T: ToString>(items: &[T]) -> String {
+      items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join(",")
+    }
+
+    (
+      join(&self.reveal_txids),
+      join(&self.inputs),
+      self.reveals.join(","),
+    )
+  }
+}
+
+/// How far an order has got on chain. Each state implies the ones before
+/// it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+  Built,
+  CommitSeen,
+  CommitConfirmed,
+  RevealsConfirmed,
+  InscriptionIndexed,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct OrderStatus {
+  #[serde(flatten)]
+  pub order: Order,
+  pub state: OrderState,
+}
+
+pub(super) fn parse_column<T: FromStr>(column: &str, value: &str) -> Result<T>
+where
+  T::Err: Into<Error>,
+{
+  value
+    .parse()
+    .map_err(Into::into)
+    .with_context(|| format!("invalid {column} `{value}` in mirror"))
+}
+
+/// Writes the block at `height` to a mirror synced to `synced`. A block that
+/// moved no mirrored inscription isn't written, so a run of them costs a
+/// single `skip_blocks` when the next block with data, or `skip_mirror_blocks`,
+/// comes along.
+pub(super) fn write_mirror_block(
+  mirror: &dyn MirrorStore,
+  synced: &mut Option<u64>,
+  height: u64,
+  timestamp: u32,
+  data: Vec<MirrorInscription>,
+) -> Result {
+  if data.is_empty() {
+    return Ok(());
+  }
+
+  if let Some(previous) = height.checked_sub(1) {
+    skip_mirror_blocks(mirror, synced, previous)?;
+  }
+
+  mirror.insert_inscriptions(height, timestamp, data)?;
+  *synced = Some(height);
+
+  Ok(())
+}
+
+/// Brings a mirror synced to `synced` up to `height` over blocks left
+/// unwritten by `write_mirror_block`.
+pub(super) fn skip_mirror_blocks(
+  mirror: &dyn MirrorStore,
+  synced: &mut Option<u64>,
+  height: u64,
+) -> Result {
+  if *synced < Some(height) {
+    mirror.skip_blocks(height)?;
+    *synced = Some(height);
+  }
+
+  Ok(())
+}
+
+/// Fails unless a mirror synced to `synced` can take the block at `height`
+/// next.
+pub(super) fn check_next_height(synced: Option<u64>, height: u64) -> Result {
+  if synced.map(|synced| synced + 1).unwrap_or(0) != height {
+    bail!(
+      "mirror is synced to block {}, cannot apply block {height}",
+      synced.map_or("none".into(), |synced| synced.to_string())
+    );
+  }
+
+  Ok(())
+}
+
+/// Fails unless a mirror synced to `synced` can skip ahead to `height`.
+pub(super) fn check_skip_height(synced: Option<u64>, height: u64) -> Result {
+  if synced >= Some(height) {
+    bail!(
+      "mirror is synced to block {}, cannot skip to block {height}",
+      synced.map_or("none".into(), |synced| synced.to_string())
+    );
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn inscription_row_from_columns() {
+    assert_eq!(
+      InscriptionRow::try_from((
+        inscription_id(1).to_string(),
+        satpoint(1, 0).to_string(),
+        "foo".to_string(),
+        Some(7),
+        Some(10_000),
+        Some(3),
+        Some(5),
+        Some(200),
+        Some(1_000),
+        Some("text/plain".to_string()),
+        None,
+      ))
+      .unwrap(),
+      InscriptionRow {
+        inscription_id: inscription_id(1),
+        satpoint: satpoint(1, 0),
+        address: "foo".into(),
+        height: Some(7),
+        output_value: Some(10_000),
+        metadata: Some(InscriptionMetadata {
+          number: 3,
+          genesis_height: 5,
+          genesis_fee: 200,
+          timestamp: 1_000,
+          content_type: Some("text/plain".into()),
+          content_length: None,
+        }),
+      }
+    );
+  }
+
+  #[test]
+  fn inscription_row_without_metadata() {
+    assert_eq!(
+      InscriptionRow::try_from((
+        inscription_id(1).to_string(),
+        satpoint(1, 0).to_string(),
+        "foo".to_string(),
+        Some(7),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ))
+      .unwrap()
+      .metadata,
+      None,
+    );
+  }
+
+  #[test]
+  fn inscription_row_rejects_invalid_columns() {
+    assert_eq!(
+      InscriptionRow::try_from((
+        inscription_id(1).to_string(),
+        "' OR '1'='1".to_string(),
+        String::new(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+      ))
+      .unwrap_err()
+      .to_string(),
+      "invalid new_satpoint `' OR '1'='1` in mirror"
+    );
+  }
+
+  #[test]
+  fn transfer_row_from_columns() {
+    assert_eq!(
+      TransferRow::try_from((
+        inscription_id(1).to_string(),
+        Some(satpoint(1, 0).to_string()),
+        satpoint(2, 0).to_string(),
+        Some("foo".to_string()),
+        "bar".to_string(),
+        txid(2).to_string(),
+        8,
+        1_000,
+      ))
+      .unwrap(),
+      TransferRow {
+        inscription_id: inscription_id(1),
+        from_satpoint: Some(satpoint(1, 0)),
+        to_satpoint: satpoint(2, 0),
+        from_address: Some("foo".into()),
+        to_address: "bar".into(),
+        txid: txid(2),
+        height: 8,
+        timestamp: 1_000,
+      }
+    );
+  }
+
+  #[test]
+  fn order_round_trips_through_columns() {
+    let order = Order {
+      commit_txid: txid(1),
+      reveal_txids: vec![txid(2), txid(3)],
+      reveals: vec!["0200".into(), "0201".into()],
+      inputs: vec![outpoint(4)],
+      source: address(),
+      destination: recipient(),
+      service_fee: 1_000,
+      satpoint_fee: 546,
+      network_fee: 300,
+      commit_fee: 200,
+      created_at: 1_700_000_000,
+    };
+
+    let (reveal_txids, inputs, reveals) = order.list_columns();
+
+    assert_eq!(
+      Order::try_from((
+        txid(1).to_string(),
+        reveal_txids,
+        inputs,
+        address().to_string(),
+        recipient().to_string(),
+        1_000,
+        546,
+        300,
+        200,
+        1_700_000_000,
+        Some(reveals),
+      ))
+      .unwrap(),
+      order
+    );
+  }
+
+  #[test]
+  fn display_discrepancy() {
+    assert_eq!(
+      MirrorDiscrepancy::Mismatched {
+        inscription_id: inscription_id(1),
+        index: MirrorLocation {
+          satpoint: satpoint(2, 0),
+          address: "bc1q".into(),
+          output_value: Some(546),
+        },
+        mirror: MirrorLocation {
+          satpoint: satpoint(1, 0),
+          address: "bc1p".into(),
+          output_value: None,
+        },
+        genesis_height: 3,
+        height: None,
+      }
+      .to_string(),
+      format!(
+        "mismatched {} (inscribed at block 3): index has {} at `bc1q` worth 546 sats, mirror has {} at `bc1p` worth unknown sats from block unknown",
+        inscription_id(1),
+        satpoint(2, 0),
+        satpoint(1, 0)
+      ),
+    );
+  }
+
+  #[test]
+  fn next_height() {
+    check_next_height(None, 0).unwrap();
+    check_next_height(Some(4), 5).unwrap();
+    assert_eq!(
+      check_next_height(Some(4), 4).unwrap_err().to_string(),
+      "mirror is synced to block 4, cannot apply block 4"
+    );
+    assert_eq!(
+      check_next_height(None, 1).unwrap_err().to_string(),
+      "mirror is synced to block none, cannot apply block 1"
+    );
+  }
+
+  #[test]
+  fn skip_height() {
+    check_skip_height(None, 0).unwrap();
+    check_skip_height(Some(4), 9).unwrap();
+    assert_eq!(
+      check_skip_height(Some(4), 4).unwrap_err().to_string(),
+      "mirror is synced to block 4, cannot skip to block 4"
+    );
+  }
+}
@@ -0,0 +1,956 @@
+use {
+  super::{
+    mirror::{
+      check_next_height, check_skip_height, parse_column, ChangeLogColumns, ChangeLogRow,
+      InscriptionColumns, OrderColumns, TransferColumns,
+    },
+    *,
+  },
+  mysql::{params, prelude::*, Opts, OptsBuilder, PooledConn, TxOpts},
+};
+
+pub struct MysqlDatabase {
+  pool: mysql::Pool,
+  database: String,
+  network: Network,
+}
+
+/// Statements that bring the schema from one version to the next. MySQL
+/// commits each DDL statement on its own, so every migration is a single
+/// statement and the version is recorded right after it, leaving a failed
+/// migration to be retried from where it stopped. A crash between the two
+/// retries a statement that already went through, so tables are created
+/// `IF NOT EXISTS` and columns or indexes that already exist are taken as
+/// added. The schema version is the number of migrations applied, so
+/// existing migrations must never change; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_ID_AND_SATPOINT (
+     inscription_id VARCHAR(80) NOT NULL PRIMARY KEY,
+     new_satpoint VARCHAR(100) NOT NULL,
+     new_address VARCHAR(100) NOT NULL,
+     INDEX (new_address)
+   )",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_WHITELIST (
+     new_address VARCHAR(100) NOT NULL PRIMARY KEY
+   )",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_RECOVERY_KEY (
+     commit_txid CHAR(64) NOT NULL,
+     vout INT UNSIGNED NOT NULL,
+     source VARCHAR(100) NOT NULL,
+     recovery_key TEXT NOT NULL,
+     PRIMARY KEY (commit_txid, vout)
+   )",
+  "ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN height BIGINT UNSIGNED NULL",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_ID_AND_SATPOINT_LOG (
+     id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+     height BIGINT UNSIGNED NOT NULL,
+     inscription_id VARCHAR(80) NOT NULL,
+     old_satpoint VARCHAR(100) NULL,
+     old_address VARCHAR(100) NULL,
+     old_height BIGINT UNSIGNED NULL,
+     INDEX (height)
+   )",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_SYNC_HEIGHT (
+     id TINYINT UNSIGNED NOT NULL PRIMARY KEY,
+     height BIGINT UNSIGNED NOT NULL
+   )",
+  "ALTER TABLE INSCRIPTION_ID_AND_SATPOINT
+     ADD COLUMN output_value BIGINT UNSIGNED NULL,
+     ADD COLUMN number BIGINT UNSIGNED NULL,
+     ADD COLUMN genesis_height BIGINT UNSIGNED NULL,
+     ADD COLUMN genesis_fee BIGINT UNSIGNED NULL,
+     ADD COLUMN timestamp INT UNSIGNED NULL,
+     ADD COLUMN content_type TEXT NULL,
+     ADD COLUMN content_length BIGINT UNSIGNED NULL,
+     ADD INDEX (number),
+     ADD INDEX (genesis_height)",
+  "ALTER TABLE INSCRIPTION_ID_AND_SATPOINT_LOG ADD COLUMN old_output_value BIGINT UNSIGNED NULL",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_TRANSFER (
+     id BIGINT UNSIGNED NOT NULL AUTO_INCREMENT PRIMARY KEY,
+     inscription_id VARCHAR(80) NOT NULL,
+     from_satpoint VARCHAR(100) NULL,
+     to_satpoint VARCHAR(100) NOT NULL,
+     from_address VARCHAR(100) NULL,
+     to_address VARCHAR(100) NOT NULL,
+     txid CHAR(64) NOT NULL,
+     height BIGINT UNSIGNED NOT NULL,
+     timestamp INT UNSIGNED NOT NULL,
+     INDEX (inscription_id),
+     INDEX (from_address),
+     INDEX (to_address),
+     INDEX (height)
+   )",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_BACKFILL (
+     id TINYINT UNSIGNED NOT NULL PRIMARY KEY,
+     height BIGINT UNSIGNED NOT NULL,
+     last_inscription_id VARCHAR(80) NULL
+   )",
+  "CREATE TABLE IF NOT EXISTS INSCRIPTION_ORDER (
+     commit_txid CHAR(64) NOT NULL PRIMARY KEY,
+     reveal_txids TEXT NOT NULL,
+     inputs TEXT NOT NULL,
+     source VARCHAR(100) NOT NULL,
+     destination VARCHAR(100) NOT NULL,
+     service_fee BIGINT UNSIGNED NOT NULL,
+     satpoint_fee BIGINT UNSIGNED NOT NULL,
+     network_fee BIGINT UNSIGNED NOT NULL,
+     commit_fee BIGINT UNSIGNED NOT NULL,
+     created_at BIGINT UNSIGNED NOT NULL,
+     INDEX (source),
+     INDEX (destination)
+   )",
+  "ALTER TABLE INSCRIPTION_ORDER ADD COLUMN reveals LONGTEXT NULL",
+  "ALTER TABLE INSCRIPTION_WHITELIST ADD COLUMN level VARCHAR(32) NULL",
+];
+
+/// `ER_DUP_FIELDNAME` and `ER_DUP_KEYNAME`: an `ALTER TABLE` adding a column
+/// or index that is already there.
+const ALREADY_APPLIED_ERRORS: [u16; 2] = [1060, 1061];
+
+const SELECT_INSCRIPTIONS_BY_ADDRESS: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE new_address = :new_address";
+
+const SELECT_INSCRIPTIONS_AFTER: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT
+   WHERE inscription_id > :after ORDER BY inscription_id LIMIT :limit";
+
+/// Completed with a parenthesized placeholder per inscription id.
+const SELECT_INSCRIPTIONS_IN: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id IN";
+
+const SELECT_INSCRIPTION_FOR_UPDATE: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id = :inscription_id FOR UPDATE";
+
+/// Metadata is only ever set, never cleared, so moving an inscription
+/// without it keeps the metadata already mirrored.
+const UPSERT_INSCRIPTION: &str =
+  "INSERT INTO INSCRIPTION_ID_AND_SATPOINT (inscription_id, new_satpoint, new_address, height,
+     output_value, number, genesis_height, genesis_fee, timestamp, content_type, content_length)
+   VALUES (:inscription_id, :new_satpoint, :new_address, :height, :output_value, :number,
+     :genesis_height, :genesis_fee, :timestamp, :content_type, :content_length)
+   ON DUPLICATE KEY UPDATE new_satpoint = :new_satpoint, new_address = :new_address,
+     height = :height, output_value = :output_value, number = COALESCE(:number, number),
+     genesis_height = COALESCE(:genesis_height, genesis_height),
+     genesis_fee = COALESCE(:genesis_fee, genesis_fee),
+     timestamp = COALESCE(:timestamp, timestamp),
+     content_type = COALESCE(:content_type, content_type),
+     content_length = COALESCE(:content_length, content_length)";
+
+const DELETE_INSCRIPTION: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id = :inscription_id";
+
+const INSERT_CHANGE_LOG: &str =
+  "INSERT INTO INSCRIPTION_ID_AND_SATPOINT_LOG (height, inscription_id, old_satpoint, old_address,
+     old_height, old_output_value)
+   VALUES (:height, :inscription_id, :old_satpoint, :old_address, :old_height, :old_output_value)";
+
+const SELECT_CHANGE_LOG_ABOVE: &str =
+  "SELECT inscription_id, old_satpoint, old_address, old_height, old_output_value
+   FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height > :height ORDER BY id DESC";
+
+const DELETE_CHANGE_LOG_ABOVE: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height > :height";
+
+const DELETE_CHANGE_LOG_UP_TO: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height <= :height";
+
+const INSERT_TRANSFER: &str =
+  "INSERT INTO INSCRIPTION_TRANSFER (inscription_id, from_satpoint, to_satpoint, from_address,
+     to_address, txid, height, timestamp)
+   VALUES (:inscription_id, :from_satpoint, :to_satpoint, :from_address, :to_address, :txid,
+     :height, :timestamp)";
+
+const SELECT_TRANSFERS_BY_INSCRIPTION: &str =
+  "SELECT inscription_id, from_satpoint, to_satpoint, from_address, to_address, txid, height,
+     timestamp
+   FROM INSCRIPTION_TRANSFER WHERE inscription_id = :inscription_id ORDER BY id";
+
+const SELECT_TRANSFERS_BY_ADDRESS: &str =
+  "SELECT inscription_id, from_satpoint, to_satpoint, from_address, to_address, txid, height,
+     timestamp
+   FROM INSCRIPTION_TRANSFER WHERE from_address = :address OR to_address = :address ORDER BY id";
+
+const DELETE_TRANSFERS_ABOVE: &str = "DELETE FROM INSCRIPTION_TRANSFER WHERE height > :height";
+
+const SELECT_SYNCED_HEIGHT: &str =
+  "SELECT height FROM INSCRIPTION_SYNC_HEIGHT WHERE id = 0 FOR UPDATE";
+
+const SET_SYNCED_HEIGHT: &str =
+  "INSERT INTO INSCRIPTION_SYNC_HEIGHT (id, height) VALUES (0, :height)
+   ON DUPLICATE KEY UPDATE height = :height";
+
+const LOWER_SYNCED_HEIGHT: &str =
+  "UPDATE INSCRIPTION_SYNC_HEIGHT SET height = :height WHERE height > :height";
+
+const SELECT_BACKFILL: &str =
+  "SELECT height, last_inscription_id FROM INSCRIPTION_BACKFILL WHERE id = 0 FOR UPDATE";
+
+const START_BACKFILL: &str =
+  "INSERT INTO INSCRIPTION_BACKFILL (id, height, last_inscription_id) VALUES (0, :height, NULL)
+   ON DUPLICATE KEY UPDATE height = :height, last_inscription_id = NULL";
+
+const ADVANCE_BACKFILL: &str =
+  "UPDATE INSCRIPTION_BACKFILL SET last_inscription_id = :last_inscription_id WHERE id = 0";
+
+const DELETE_BACKFILL: &str = "DELETE FROM INSCRIPTION_BACKFILL";
+
+const DELETE_ALL_INSCRIPTIONS: &str = "DELETE FROM INSCRIPTION_ID_AND_SATPOINT";
+
+const DELETE_ALL_CHANGE_LOG: &str = "DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG";
+
+const DELETE_SYNCED_HEIGHT: &str = "DELETE FROM INSCRIPTION_SYNC_HEIGHT";
+
+const SELECT_WHITELIST_LEVEL: &str =
+  "SELECT level FROM INSCRIPTION_WHITELIST WHERE new_address = :new_address LIMIT 1";
+
+/// `recovery_key` holds plaintext WIF, readable by anyone with access to the
+/// database.
+const UPSERT_RECOVERY_KEY: &str =
+  "INSERT INTO INSCRIPTION_RECOVERY_KEY (commit_txid, vout, source, recovery_key)
+   VALUES (:commit_txid, :vout, :source, :recovery_key)
+   ON DUPLICATE KEY UPDATE source = :source, recovery_key = :recovery_key";
+
+const SELECT_RECOVERY_KEYS: &str = "SELECT source, recovery_key FROM INSCRIPTION_RECOVERY_KEY
+   WHERE commit_txid = :commit_txid ORDER BY vout";
+
+const UPSERT_ORDER: &str =
+  "INSERT INTO INSCRIPTION_ORDER (commit_txid, reveal_txids, inputs, source, destination,
+     service_fee, satpoint_fee, network_fee, commit_fee, created_at, reveals)
+   VALUES (:commit_txid, :reveal_txids, :inputs, :source, :destination, :service_fee,
+     :satpoint_fee, :network_fee, :commit_fee, :created_at, :reveals)
+   ON DUPLICATE KEY UPDATE reveal_txids = :reveal_txids, inputs = :inputs, source = :source,
+     destination = :destination, service_fee = :service_fee, satpoint_fee = :satpoint_fee,
+     network_fee = :network_fee, commit_fee = :commit_fee, created_at = :created_at,
+     reveals = :reveals";
+
+const SELECT_ORDER: &str =
+  "SELECT commit_txid, reveal_txids, inputs, source, destination, service_fee, satpoint_fee,
+     network_fee, commit_fee, created_at, reveals
+   FROM INSCRIPTION_ORDER WHERE commit_txid = :commit_txid";
+
+const CREATE_SCHEMA_VERSION: &str = "CREATE TABLE IF NOT EXISTS SCHEMA_VERSION (
+   id TINYINT UNSIGNED NOT NULL PRIMARY KEY,
+   version BIGINT UNSIGNED NOT NULL
+ )";
+
+const SELECT_SCHEMA_VERSION: &str = "SELECT version FROM SCHEMA_VERSION WHERE id = 0";
+
+const SET_SCHEMA_VERSION: &str = "INSERT INTO SCHEMA_VERSION (id, version) VALUES (0, :version)
+   ON DUPLICATE KEY UPDATE version = :version";
+
+/// Access to the mysql mirror. Every statement is a constant prepared with
+/// named parameters, so values never become part of the SQL text.
+impl MysqlDatabase {
+  /// Connects to `database`, or the default database for `network`, creating
+  /// it if needed and migrating its schema to the latest version.
+  pub fn new(
+    host: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<String>,
+    network: Network,
+  ) -> Result<MysqlDatabase> {
+    let database = database.unwrap_or_else(|| Self::get_database(network));
+
+    if database.is_empty()
+      || !database
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    {
+      bail!("invalid mysql database name `{database}`");
+    }
+
+    let opts: Opts = OptsBuilder::new()
+      .ip_or_hostname(host)
+      .user(username)
+      .pass(password)
+      .into();
+
+    mysql::Conn::new(opts.clone())
+      .and_then(|mut conn| conn.query_drop(format!("CREATE DATABASE IF NOT EXISTS `{database}`")))
+      .with_context(|| format!("failed to create mysql database `{database}`"))?;
+
+    let pool = mysql::Pool::new(OptsBuilder::from_opts(opts).db_name(Some(database.clone())))
+      .with_context(|| format!("failed to connect to mysql database `{database}`"))?;
+
+    let mysql = MysqlDatabase {
+      pool,
+      database,
+      network,
+    };
+    mysql.migrate()?;
+    Ok(mysql)
+  }
+
+  /// Applies the migrations the database is missing. Concurrent startups are
+  /// serialized with a named lock so each migration runs once.
+  fn migrate(&self) -> Result {
+    let mut conn = self.get_conn()?;
+
+    let locked: Option<Option<i64>> = conn
+      .query_first("SELECT GET_LOCK('ord_schema_migration', 60)")
+      .context("failed to lock mysql schema")?;
+    if locked.flatten() != Some(1) {
+      bail!("timed out waiting for another process to migrate the mysql schema");
+    }
+
+    let result = self.migrate_locked(&mut conn);
+
+    conn
+      .query_drop("DO RELEASE_LOCK('ord_schema_migration')")
+      .context("failed to unlock mysql schema")?;
+
+    result
+  }
+
+  fn migrate_locked(&self, conn: &mut PooledConn) -> Result {
+    let database = &self.database;
+
+    conn
+      .query_drop(CREATE_SCHEMA_VERSION)
+      .context("failed to create mysql schema version table")?;
+
+    let version: u64 = conn
+      .query_first(SELECT_SCHEMA_VERSION)
+      .context("failed to read mysql schema version")?
+      .unwrap_or(0);
+
+    for (version, statement) in Self::pending_migrations(database, version)? {
+      match conn.query_drop(statement) {
+        Ok(()) => {}
+        Err(err) if Self::already_applied(&err) => {
+          log::info!("Mysql migration {version} was already applied to `{database}`: {err}");
+        }
+        Err(err) => {
+          return Err(err).with_context(|| format!("failed to apply mysql migration {version}"))
+        }
+      }
+
+      conn
+        .exec_drop(SET_SCHEMA_VERSION, params! { "version" => version })
+        .with_context(|| format!("failed to record mysql migration {version}"))?;
+
+      log::info!("Applied mysql migration {version} to `{database}`");
+    }
+
+    Ok(())
+  }
+
+  /// The migrations a schema at `version` is missing, each with the version
+  /// it brings the schema to.
+  fn pending_migrations(
+    database: &str,
+    version: u64,
+  ) -> Result<impl Iterator<Item = (u64, &'static str)>> {
+    let latest = u64::try_from(MIGRATIONS.len()).unwrap();
+
+    if version > latest {
+      bail!(
+        "mysql database `{database}` appears to have been migrated by a newer, incompatible version of ord, consider updating ord: database schema {version}, ord schema {latest}"
+      );
+    }
+
+    Ok(
+      (1..)
+        .zip(MIGRATIONS.iter().copied())
+        .skip(version.try_into().unwrap()),
+    )
+  }
+
+  /// Whether `err` means a migration went through before its version was
+  /// recorded. `ALTER TABLE` is atomic, so a duplicate column or index means
+  /// the whole statement did.
+  fn already_applied(err: &mysql::Error) -> bool {
+    matches!(err, mysql::Error::MySqlError(err) if ALREADY_APPLIED_ERRORS.contains(&err.code))
+  }
+
+  pub fn get_conn(&self) -> Result<PooledConn> {
+    self
+      .pool
+      .get_conn()
+      .context("failed to get mysql connection")
+  }
+
+  pub fn get_database(network: Network) -> String {
+    match network {
+      Network::Bitcoin => "ord_mainnet".to_owned(),
+      Network::Testnet => "ord_testnet".to_owned(),
+      Network::Signet => "ord_signet".to_owned(),
+      Network::Regtest => "ord_regtest".to_owned(),
+    }
+  }
+
+  /// Writes `data` at `height`, adding it to the transfer history if
+  /// `timestamp` is given.
+  fn write_inscriptions(
+    tx: &mut mysql::Transaction,
+    height: u64,
+    timestamp: u32,
+    data: &[MirrorInscription],
+  ) -> Result {
+    for item in data {
+      let inscription_id = item.inscription_id.to_string();
+
+      let old = tx
+        .exec_first::<InscriptionColumns, _, _>(
+          SELECT_INSCRIPTION_FOR_UPDATE,
+          params! { "inscription_id" => &inscription_id },
+        )
+        .with_context(|| format!("failed to query inscription {inscription_id}"))?
+        .map(InscriptionRow::try_from)
+        .transpose()?;
+
+      tx.exec_drop(
+        INSERT_CHANGE_LOG,
+        params! {
+          "height" => height,
+          "inscription_id" => &inscription_id,
+          "old_satpoint" => old.as_ref().map(|old| old.satpoint.to_string()),
+          "old_address" => old.as_ref().map(|old| old.address.clone()),
+          "old_height" => old.as_ref().and_then(|old| old.height),
+          "old_output_value" => old.as_ref().and_then(|old| old.output_value),
+        },
+      )
+      .with_context(|| format!("failed to log change of inscription {inscription_id}"))?;
+
+      tx.exec_drop(
+        INSERT_TRANSFER,
+        params! {
+          "inscription_id" => &inscription_id,
+          "from_satpoint" => item.old_satpoint.map(|old_satpoint| old_satpoint.to_string()),
+          "to_satpoint" => item.new_satpoint.to_string(),
+          "from_address" => old.map(|old| old.address),
+          "to_address" => &item.new_address,
+          "txid" => item.new_satpoint.outpoint.txid.to_string(),
+          "height" => height,
+          "timestamp" => timestamp,
+        },
+      )
+      .with_context(|| format!("failed to record transfer of inscription {inscription_id}"))?;
+
+      Self::upsert_inscription(
+        tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        Some(height),
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    tx.exec_drop(SET_SYNCED_HEIGHT, params! { "height" => height })
+      .context("failed to set mysql synced height")
+  }
+
+  fn start_transaction(conn: &mut PooledConn) -> Result<mysql::Transaction<'_>> {
+    conn
+      .start_transaction(TxOpts::default())
+      .context("failed to start mysql transaction")
+  }
+
+  fn backfill_progress(conn: &mut impl Queryable) -> Result<Option<BackfillProgress>> {
+    conn
+      .exec_first::<(u64, Option<String>), _, _>(SELECT_BACKFILL, ())
+      .context("failed to read mysql backfill progress")?
+      .map(BackfillProgress::try_from)
+      .transpose()
+  }
+
+  fn synced_height(conn: &mut impl Queryable) -> Result<Option<u64>> {
+    conn
+      .exec_first(SELECT_SYNCED_HEIGHT, ())
+      .context("failed to read mysql synced height")
+  }
+
+  fn upsert_inscription(
+    conn: &mut impl Queryable,
+    inscription_id: InscriptionId,
+    satpoint: SatPoint,
+    address: &str,
+    height: Option<u64>,
+    output_value: Option<u64>,
+    metadata: Option<&InscriptionMetadata>,
+  ) -> Result {
+    conn
+      .exec_drop(
+        UPSERT_INSCRIPTION,
+        params! {
+          "inscription_id" => inscription_id.to_string(),
+          "new_satpoint" => satpoint.to_string(),
+          "new_address" => address,
+          "height" => height,
+          "output_value" => output_value,
+          "number" => metadata.map(|metadata| metadata.number),
+          "genesis_height" => metadata.map(|metadata| metadata.genesis_height),
+          "genesis_fee" => metadata.map(|metadata| metadata.genesis_fee),
+          "timestamp" => metadata.map(|metadata| metadata.timestamp),
+          "content_type" => metadata.and_then(|metadata| metadata.content_type.as_deref()),
+          "content_length" => metadata.and_then(|metadata| metadata.content_length),
+        },
+      )
+      .with_context(|| format!("failed to write inscription {inscription_id}"))
+  }
+}
+
+impl MirrorStore for MysqlDatabase {
+  fn network(&self) -> Network {
+    self.network
+  }
+
+  fn get_whitelist_level(&self, address: &str) -> Result<Option<String>> {
+    let level: Option<Option<String>> = self
+      .get_conn()?
+      .exec_first(SELECT_WHITELIST_LEVEL, params! { "new_address" => address })
+      .with_context(|| format!("failed to query whitelist for {address}"))?;
+    Ok(level.map(|level| level.unwrap_or_else(|| FeePolicy::DEFAULT_LEVEL.into())))
+  }
+
+  fn get_inscriptions_by_address(&self, address: &str) -> Result<Vec<InscriptionRow>> {
+    let rows: Vec<InscriptionColumns> = self
+      .get_conn()?
+      .exec(
+        SELECT_INSCRIPTIONS_BY_ADDRESS,
+        params! { "new_address" => address },
+      )
+      .with_context(|| format!("failed to query inscriptions of {address}"))?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_inscriptions_after(
+    &self,
+    after: Option<InscriptionId>,
+    limit: usize,
+  ) -> Result<Vec<InscriptionRow>> {
+    let rows: Vec<InscriptionColumns> = self
+      .get_conn()?
+      .exec(
+        SELECT_INSCRIPTIONS_AFTER,
+        params! {
+          "after" => after.map(|after| after.to_string()).unwrap_or_default(),
+          "limit" => limit,
+        },
+      )
+      .context("failed to query mysql inscriptions")?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_inscriptions_in(&self, inscription_ids: &[InscriptionId]) -> Result<Vec<InscriptionRow>> {
+    if inscription_ids.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let rows: Vec<InscriptionColumns> = self
+      .get_conn()?
+      .exec(
+        format!(
+          "{SELECT_INSCRIPTIONS_IN} ({})",
+          vec!["?"; inscription_ids.len()].join(", ")
+        ),
+        inscription_ids
+          .iter()
+          .map(InscriptionId::to_string)
+          .collect::<Vec<String>>(),
+      )
+      .context("failed to query mysql inscriptions")?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_synced_height(&self) -> Result<Option<u64>> {
+    Self::synced_height(&mut self.get_conn()?)
+  }
+
+  fn insert_inscriptions(
+    &self,
+    height: u64,
+    timestamp: u32,
+    data: Vec<MirrorInscription>,
+  ) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    check_next_height(Self::synced_height(&mut tx)?, height)?;
+
+    Self::write_inscriptions(&mut tx, height, timestamp, &data)?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit block {height} to mysql"))
+  }
+
+  fn skip_blocks(&self, height: u64) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    check_skip_height(Self::synced_height(&mut tx)?, height)?;
+
+    tx.exec_drop(SET_SYNCED_HEIGHT, params! { "height" => height })
+      .context("failed to set mysql synced height")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit skip to block {height} to mysql"))
+  }
+
+  fn rollback_inscriptions(&self, height: u64) -> Result<usize> {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    let changes = tx
+      .exec::<ChangeLogColumns, _, _>(SELECT_CHANGE_LOG_ABOVE, params! { "height" => height })
+      .context("failed to query mysql change log")?
+      .into_iter()
+      .map(ChangeLogRow::try_from)
+      .collect::<Result<Vec<_>>>()?;
+
+    for change in &changes {
+      match change.old_satpoint {
+        Some(old_satpoint) => Self::upsert_inscription(
+          &mut tx,
+          change.inscription_id,
+          old_satpoint,
+          change.old_address.as_deref().unwrap_or_default(),
+          change.old_height,
+          change.old_output_value,
+          None,
+        )?,
+        None => tx
+          .exec_drop(
+            DELETE_INSCRIPTION,
+            params! { "inscription_id" => change.inscription_id.to_string() },
+          )
+          .with_context(|| format!("failed to delete inscription {}", change.inscription_id))?,
+      }
+    }
+
+    tx.exec_drop(DELETE_CHANGE_LOG_ABOVE, params! { "height" => height })
+      .context("failed to clear mysql change log")?;
+    tx.exec_drop(DELETE_TRANSFERS_ABOVE, params! { "height" => height })
+      .context("failed to clear mysql transfers")?;
+    tx.exec_drop(LOWER_SYNCED_HEIGHT, params! { "height" => height })
+      .context("failed to set mysql synced height")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit mysql rollback to block {height}"))?;
+    Ok(changes.len())
+  }
+
+  fn get_inscription_transfers(&self, inscription_id: InscriptionId) -> Result<Vec<TransferRow>> {
+    let rows: Vec<TransferColumns> = self
+      .get_conn()?
+      .exec(
+        SELECT_TRANSFERS_BY_INSCRIPTION,
+        params! { "inscription_id" => inscription_id.to_string() },
+      )
+      .with_context(|| format!("failed to query transfers of inscription {inscription_id}"))?;
+    rows.into_iter().map(TransferRow::try_from).collect()
+  }
+
+  fn get_address_transfers(&self, address: &str) -> Result<Vec<TransferRow>> {
+    let rows: Vec<TransferColumns> = self
+      .get_conn()?
+      .exec(
+        SELECT_TRANSFERS_BY_ADDRESS,
+        params! { "address" => address },
+      )
+      .with_context(|| format!("failed to query transfers of {address}"))?;
+    rows.into_iter().map(TransferRow::try_from).collect()
+  }
+
+  fn prune_inscription_log(&self, height: u64) -> Result {
+    self
+      .get_conn()?
+      .exec_drop(DELETE_CHANGE_LOG_UP_TO, params! { "height" => height })
+      .context("failed to prune mysql change log")
+  }
+
+  fn repair_inscriptions(
+    &self,
+    data: Vec<MirrorInscription>,
+    delete: Vec<InscriptionId>,
+  ) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    let height = Self::synced_height(&mut tx)?;
+
+    for item in &data {
+      Self::upsert_inscription(
+        &mut tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        height,
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    for inscription_id in delete {
+      tx.exec_drop(
+        DELETE_INSCRIPTION,
+        params! { "inscription_id" => inscription_id.to_string() },
+      )
+      .with_context(|| format!("failed to delete inscription {inscription_id}"))?;
+    }
+
+    tx.commit().context("failed to commit mysql repair")
+  }
+
+  fn get_backfill_progress(&self) -> Result<Option<BackfillProgress>> {
+    Self::backfill_progress(&mut self.get_conn()?)
+  }
+
+  fn start_backfill(&self, height: u64) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    for statement in [
+      DELETE_ALL_INSCRIPTIONS,
+      DELETE_ALL_CHANGE_LOG,
+      DELETE_SYNCED_HEIGHT,
+    ] {
+      tx.query_drop(statement)
+        .context("failed to clear mysql mirror")?;
+    }
+    tx.exec_drop(START_BACKFILL, params! { "height" => height })
+      .context("failed to start mysql backfill")?;
+
+    tx.commit()
+      .context("failed to commit start of mysql backfill")
+  }
+
+  fn backfill_inscriptions(&self, data: Vec<MirrorInscription>, last: InscriptionId) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    let progress =
+      Self::backfill_progress(&mut tx)?.ok_or_else(|| anyhow!("no mysql backfill in progress"))?;
+
+    for item in &data {
+      Self::upsert_inscription(
+        &mut tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        Some(progress.height),
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    tx.exec_drop(
+      ADVANCE_BACKFILL,
+      params! { "last_inscription_id" => last.to_string() },
+    )
+    .context("failed to record mysql backfill progress")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit mysql backfill up to {last}"))
+  }
+
+  fn finish_backfill(&self) -> Result {
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    let progress =
+      Self::backfill_progress(&mut tx)?.ok_or_else(|| anyhow!("no mysql backfill in progress"))?;
+
+    tx.exec_drop(SET_SYNCED_HEIGHT, params! { "height" => progress.height })
+      .context("failed to set mysql synced height")?;
+    tx.query_drop(DELETE_BACKFILL)
+      .context("failed to finish mysql backfill")?;
+
+    tx.commit()
+      .context("failed to commit end of mysql backfill")
+  }
+
+  fn insert_recovery_keys(
+    &self,
+    commit_txid: Txid,
+    source: &Address,
+    recovery_keys: &[String],
+  ) -> Result {
+    if recovery_keys.is_empty() {
+      return Ok(());
+    };
+
+    let mut conn = self.get_conn()?;
+    let mut tx = Self::start_transaction(&mut conn)?;
+
+    tx.exec_batch(
+      UPSERT_RECOVERY_KEY,
+      recovery_keys
+        .iter()
+        .enumerate()
+        .map(|(vout, recovery_key)| {
+          params! {
+            "commit_txid" => commit_txid.to_string(),
+            "vout" => vout,
+            "source" => source.to_string(),
+            "recovery_key" => recovery_key,
+          }
+        }),
+    )
+    .with_context(|| format!("failed to write recovery keys of commit {commit_txid}"))?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit recovery keys of commit {commit_txid}"))
+  }
+
+  fn get_recovery_keys(&self, commit_txid: Txid) -> Result<(Address, Vec<String>)> {
+    let rows: Vec<(String, String)> = self
+      .get_conn()?
+      .exec(
+        SELECT_RECOVERY_KEYS,
+        params! { "commit_txid" => commit_txid.to_string() },
+      )
+      .with_context(|| format!("failed to query recovery keys of commit {commit_txid}"))?;
+
+    let source = parse_column(
+      "source",
+      &rows
+        .first()
+        .ok_or_else(|| anyhow!("Recovery key for commit {commit_txid} not found"))?
+        .0,
+    )?;
+
+    Ok((source, rows.into_iter().map(|(_, key)| key).collect()))
+  }
+
+  fn insert_order(&self, order: &Order) -> Result {
+    let (reveal_txids, inputs, reveals) = order.list_columns();
+
+    self
+      .get_conn()?
+      .exec_drop(
+        UPSERT_ORDER,
+        params! {
+          "commit_txid" => order.commit_txid.to_string(),
+          "reveal_txids" => reveal_txids,
+          "inputs" => inputs,
+          "source" => order.source.to_string(),
+          "destination" => order.destination.to_string(),
+          "service_fee" => order.service_fee,
+          "satpoint_fee" => order.satpoint_fee,
+          "network_fee" => order.network_fee,
+          "commit_fee" => order.commit_fee,
+          "created_at" => order.created_at,
+          "reveals" => reveals,
+        },
+      )
+      .with_context(|| format!("failed to write order of commit {}", order.commit_txid))
+  }
+
+  fn get_order(&self, commit_txid: Txid) -> Result<Option<Order>> {
+    self
+      .get_conn()?
+      .exec_first::<OrderColumns, _, _>(
+        SELECT_ORDER,
+        params! { "commit_txid" => commit_txid.to_string() },
+      )
+      .with_context(|| format!("failed to query order of commit {commit_txid}"))?
+      .map(Order::try_from)
+      .transpose()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_database_names() {
+    assert_eq!(MysqlDatabase::get_database(Network::Bitcoin), "ord_mainnet");
+    assert_eq!(MysqlDatabase::get_database(Network::Signet), "ord_signet");
+  }
+
+  #[test]
+  fn invalid_database_name() {
+    assert_eq!(
+      MysqlDatabase::new(None, None, None, Some("ord`; DROP".into()), Network::Signet)
+        .err()
+        .unwrap()
+        .to_string(),
+      "invalid mysql database name `ord`; DROP`"
+    );
+  }
+  #[test]
+  fn migrations_are_single_statements() {
+    for statement in MIGRATIONS {
+      assert!(!statement.contains(';'), "{statement}");
+    }
+  }
+
+  #[test]
+  fn migrations_can_be_retried() {
+    for statement in MIGRATIONS {
+      assert!(
+        !statement.starts_with("CREATE TABLE")
+          || statement.starts_with("CREATE TABLE IF NOT EXISTS"),
+        "{statement}"
+      );
+    }
+
+    let error = |code| {
+      mysql::Error::MySqlError(mysql::MySqlError {
+        state: "42S21".into(),
+        message: "Duplicate".into(),
+        code,
+      })
+    };
+    assert!(MysqlDatabase::already_applied(&error(1060)));
+    assert!(MysqlDatabase::already_applied(&error(1061)));
+    assert!(!MysqlDatabase::already_applied(&error(1054)));
+  }
+
+  #[test]
+  fn pending_migrations() {
+    let latest = u64::try_from(MIGRATIONS.len()).unwrap();
+
+    assert_eq!(
+      MysqlDatabase::pending_migrations("ord", 0)
+        .unwrap()
+        .collect::<Vec<(u64, &str)>>(),
+      (1..=latest)
+        .zip(MIGRATIONS.iter().copied())
+        .collect::<Vec<(u64, &str)>>(),
+    );
+
+    assert_eq!(
+      MysqlDatabase::pending_migrations("ord", latest - 1)
+        .unwrap()
+        .collect::<Vec<(u64, &str)>>(),
+      [(latest, MIGRATIONS[MIGRATIONS.len() - 1])],
+    );
+
+    assert_eq!(
+      MysqlDatabase::pending_migrations("ord", latest)
+        .unwrap()
+        .count(),
+      0
+    );
+  }
+
+  #[test]
+  fn newer_schema_is_rejected() {
+    let latest = MIGRATIONS.len();
+
+    assert_eq!(
+      MysqlDatabase::pending_migrations("ord", u64::try_from(latest).unwrap() + 1)
+        .err()
+        .unwrap()
+        .to_string(),
+      format!(
+        "mysql database `ord` appears to have been migrated by a newer, incompatible version of ord, consider updating ord: database schema {}, ord schema {latest}",
+        latest + 1
+      )
+    );
+  }
+}
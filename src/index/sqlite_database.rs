@@ -0,0 +1,842 @@
+use {
+  super::{
+    mirror::{
+      check_next_height, check_skip_height, parse_column, ChangeLogRow, InscriptionColumns,
+      OrderColumns, TransferColumns,
+    },
+    *,
+  },
+  rusqlite::{named_params, Connection, OptionalExtension, Transaction},
+  std::sync::Mutex,
+};
+
+/// A mirror in a local SQLite file, for deployments without a MySQL server.
+/// The schema matches the MySQL mirror's.
+pub struct SqliteDatabase {
+  connection: Mutex<Connection>,
+  network: Network,
+}
+
+/// Statements that bring the schema from one version to the next. The schema
+/// version is the number of migrations applied and is kept in SQLite's
+/// `user_version`, so existing migrations must never change; add a new one
+/// instead.
+const MIGRATIONS: &[&str] = &["
+  CREATE TABLE INSCRIPTION_ID_AND_SATPOINT (
+    inscription_id TEXT NOT NULL PRIMARY KEY,
+    new_satpoint TEXT NOT NULL,
+    new_address TEXT NOT NULL,
+    height INTEGER
+  );
+  CREATE INDEX INSCRIPTION_ID_AND_SATPOINT_NEW_ADDRESS ON INSCRIPTION_ID_AND_SATPOINT (new_address);
+  CREATE TABLE INSCRIPTION_WHITELIST (
+    new_address TEXT NOT NULL PRIMARY KEY
+  );
+  CREATE TABLE INSCRIPTION_RECOVERY_KEY (
+    commit_txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    recovery_key TEXT NOT NULL,
+    PRIMARY KEY (commit_txid, vout)
+  );
+  CREATE TABLE INSCRIPTION_ID_AND_SATPOINT_LOG (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    height INTEGER NOT NULL,
+    inscription_id TEXT NOT NULL,
+    old_satpoint TEXT,
+    old_address TEXT,
+    old_height INTEGER
+  );
+  CREATE INDEX INSCRIPTION_ID_AND_SATPOINT_LOG_HEIGHT ON INSCRIPTION_ID_AND_SATPOINT_LOG (height);
+  CREATE TABLE INSCRIPTION_SYNC_HEIGHT (
+    id INTEGER NOT NULL PRIMARY KEY,
+    height INTEGER NOT NULL
+  );
+", "
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN output_value INTEGER;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN number INTEGER;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN genesis_height INTEGER;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN genesis_fee INTEGER;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN timestamp INTEGER;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN content_type TEXT;
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT ADD COLUMN content_length INTEGER;
+  CREATE INDEX INSCRIPTION_ID_AND_SATPOINT_NUMBER ON INSCRIPTION_ID_AND_SATPOINT (number);
+  CREATE INDEX INSCRIPTION_ID_AND_SATPOINT_GENESIS_HEIGHT ON INSCRIPTION_ID_AND_SATPOINT (genesis_height);
+  ALTER TABLE INSCRIPTION_ID_AND_SATPOINT_LOG ADD COLUMN old_output_value INTEGER;
+", "
+  CREATE TABLE INSCRIPTION_TRANSFER (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    inscription_id TEXT NOT NULL,
+    from_satpoint TEXT,
+    to_satpoint TEXT NOT NULL,
+    from_address TEXT,
+    to_address TEXT NOT NULL,
+    txid TEXT NOT NULL,
+    height INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL
+  );
+  CREATE INDEX INSCRIPTION_TRANSFER_INSCRIPTION_ID ON INSCRIPTION_TRANSFER (inscription_id);
+  CREATE INDEX INSCRIPTION_TRANSFER_FROM_ADDRESS ON INSCRIPTION_TRANSFER (from_address);
+  CREATE INDEX INSCRIPTION_TRANSFER_TO_ADDRESS ON INSCRIPTION_TRANSFER (to_address);
+  CREATE INDEX INSCRIPTION_TRANSFER_HEIGHT ON INSCRIPTION_TRANSFER (height);
+", "
+  CREATE TABLE INSCRIPTION_BACKFILL (
+    id INTEGER NOT NULL PRIMARY KEY,
+    height INTEGER NOT NULL,
+    last_inscription_id TEXT
+  );
+", "
+  CREATE TABLE INSCRIPTION_ORDER (
+    commit_txid TEXT NOT NULL PRIMARY KEY,
+    reveal_txids TEXT NOT NULL,
+    inputs TEXT NOT NULL,
+    source TEXT NOT NULL,
+    destination TEXT NOT NULL,
+    service_fee INTEGER NOT NULL,
+    satpoint_fee INTEGER NOT NULL,
+    network_fee INTEGER NOT NULL,
+    commit_fee INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+  );
+  CREATE INDEX INSCRIPTION_ORDER_SOURCE ON INSCRIPTION_ORDER (source);
+  CREATE INDEX INSCRIPTION_ORDER_DESTINATION ON INSCRIPTION_ORDER (destination);
+", "
+  ALTER TABLE INSCRIPTION_ORDER ADD COLUMN reveals TEXT;
+", "
+  ALTER TABLE INSCRIPTION_WHITELIST ADD COLUMN level TEXT;
+"];
+
+const SELECT_INSCRIPTIONS_BY_ADDRESS: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE new_address = :new_address";
+
+const SELECT_INSCRIPTIONS_AFTER: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT
+   WHERE inscription_id > :after ORDER BY inscription_id LIMIT :limit";
+
+/// Completed with a parenthesized placeholder per inscription id.
+const SELECT_INSCRIPTIONS_IN: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id IN";
+
+const SELECT_INSCRIPTION: &str =
+  "SELECT inscription_id, new_satpoint, new_address, height, output_value, number, genesis_height,
+     genesis_fee, timestamp, content_type, content_length
+   FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id = :inscription_id";
+
+/// Metadata is only ever set, never cleared, so moving an inscription
+/// without it keeps the metadata already mirrored.
+const UPSERT_INSCRIPTION: &str =
+  "INSERT INTO INSCRIPTION_ID_AND_SATPOINT (inscription_id, new_satpoint, new_address, height,
+     output_value, number, genesis_height, genesis_fee, timestamp, content_type, content_length)
+   VALUES (:inscription_id, :new_satpoint, :new_address, :height, :output_value, :number,
+     :genesis_height, :genesis_fee, :timestamp, :content_type, :content_length)
+   ON CONFLICT (inscription_id) DO UPDATE
+   SET new_satpoint = excluded.new_satpoint, new_address = excluded.new_address,
+     height = excluded.height, output_value = excluded.output_value,
+     number = COALESCE(excluded.number, number),
+     genesis_height = COALESCE(excluded.genesis_height, genesis_height),
+     genesis_fee = COALESCE(excluded.genesis_fee, genesis_fee),
+     timestamp = COALESCE(excluded.timestamp, timestamp),
+     content_type = COALESCE(excluded.content_type, content_type),
+     content_length = COALESCE(excluded.content_length, content_length)";
+
+const DELETE_INSCRIPTION: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT WHERE inscription_id = :inscription_id";
+
+const INSERT_CHANGE_LOG: &str =
+  "INSERT INTO INSCRIPTION_ID_AND_SATPOINT_LOG (height, inscription_id, old_satpoint, old_address,
+     old_height, old_output_value)
+   VALUES (:height, :inscription_id, :old_satpoint, :old_address, :old_height, :old_output_value)";
+
+const SELECT_CHANGE_LOG_ABOVE: &str =
+  "SELECT inscription_id, old_satpoint, old_address, old_height, old_output_value
+   FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height > :height ORDER BY id DESC";
+
+const DELETE_CHANGE_LOG_ABOVE: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height > :height";
+
+const DELETE_CHANGE_LOG_UP_TO: &str =
+  "DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG WHERE height <= :height";
+
+const INSERT_TRANSFER: &str =
+  "INSERT INTO INSCRIPTION_TRANSFER (inscription_id, from_satpoint, to_satpoint, from_address,
+     to_address, txid, height, timestamp)
+   VALUES (:inscription_id, :from_satpoint, :to_satpoint, :from_address, :to_address, :txid,
+     :height, :timestamp)";
+
+const SELECT_TRANSFERS_BY_INSCRIPTION: &str =
+  "SELECT inscription_id, from_satpoint, to_satpoint, from_address, to_address, txid, height,
+     timestamp
+   FROM INSCRIPTION_TRANSFER WHERE inscription_id = :inscription_id ORDER BY id";
+
+const SELECT_TRANSFERS_BY_ADDRESS: &str =
+  "SELECT inscription_id, from_satpoint, to_satpoint, from_address, to_address, txid, height,
+     timestamp
+   FROM INSCRIPTION_TRANSFER WHERE from_address = :address OR to_address = :address ORDER BY id";
+
+const DELETE_TRANSFERS_ABOVE: &str = "DELETE FROM INSCRIPTION_TRANSFER WHERE height > :height";
+
+const SELECT_SYNCED_HEIGHT: &str = "SELECT height FROM INSCRIPTION_SYNC_HEIGHT WHERE id = 0";
+
+const SET_SYNCED_HEIGHT: &str =
+  "INSERT INTO INSCRIPTION_SYNC_HEIGHT (id, height) VALUES (0, :height)
+   ON CONFLICT (id) DO UPDATE SET height = excluded.height";
+
+const LOWER_SYNCED_HEIGHT: &str =
+  "UPDATE INSCRIPTION_SYNC_HEIGHT SET height = :height WHERE height > :height";
+
+const SELECT_BACKFILL: &str =
+  "SELECT height, last_inscription_id FROM INSCRIPTION_BACKFILL WHERE id = 0";
+
+const START_BACKFILL: &str =
+  "INSERT INTO INSCRIPTION_BACKFILL (id, height, last_inscription_id) VALUES (0, :height, NULL)
+   ON CONFLICT (id) DO UPDATE SET height = excluded.height, last_inscription_id = NULL";
+
+const ADVANCE_BACKFILL: &str =
+  "UPDATE INSCRIPTION_BACKFILL SET last_inscription_id = :last_inscription_id WHERE id = 0";
+
+const DELETE_BACKFILL: &str = "DELETE FROM INSCRIPTION_BACKFILL";
+
+const CLEAR_MIRROR: &str = "
+  DELETE FROM INSCRIPTION_ID_AND_SATPOINT;
+  DELETE FROM INSCRIPTION_ID_AND_SATPOINT_LOG;
+  DELETE FROM INSCRIPTION_SYNC_HEIGHT;
+";
+
+const SELECT_WHITELIST_LEVEL: &str =
+  "SELECT level FROM INSCRIPTION_WHITELIST WHERE new_address = :new_address LIMIT 1";
+
+/// `recovery_key` holds plaintext WIF, readable by anyone with access to the
+/// database.
+const UPSERT_RECOVERY_KEY: &str =
+  "INSERT INTO INSCRIPTION_RECOVERY_KEY (commit_txid, vout, source, recovery_key)
+   VALUES (:commit_txid, :vout, :source, :recovery_key)
+   ON CONFLICT (commit_txid, vout) DO UPDATE
+   SET source = excluded.source, recovery_key = excluded.recovery_key";
+
+const SELECT_RECOVERY_KEYS: &str = "SELECT source, recovery_key FROM INSCRIPTION_RECOVERY_KEY
+   WHERE commit_txid = :commit_txid ORDER BY vout";
+
+const UPSERT_ORDER: &str =
+  "INSERT INTO INSCRIPTION_ORDER (commit_txid, reveal_txids, inputs, source, destination,
+     service_fee, satpoint_fee, network_fee, commit_fee, created_at, reveals)
+   VALUES (:commit_txid, :reveal_txids, :inputs, :source, :destination, :service_fee,
+     :satpoint_fee, :network_fee, :commit_fee, :created_at, :reveals)
+   ON CONFLICT (commit_txid) DO UPDATE
+   SET reveal_txids = excluded.reveal_txids, inputs = excluded.inputs, source = excluded.source,
+     destination = excluded.destination, service_fee = excluded.service_fee,
+     satpoint_fee = excluded.satpoint_fee, network_fee = excluded.network_fee,
+     commit_fee = excluded.commit_fee, created_at = excluded.created_at,
+     reveals = excluded.reveals";
+
+const SELECT_ORDER: &str =
+  "SELECT commit_txid, reveal_txids, inputs, source, destination, service_fee, satpoint_fee,
+     network_fee, commit_fee, created_at, reveals
+   FROM INSCRIPTION_ORDER WHERE commit_txid = :commit_txid";
+
+impl SqliteDatabase {
+  /// Opens the SQLite mirror at `path`, creating it if needed and migrating
+  /// its schema to the latest version.
+  pub fn open(path: &Path, network: Network) -> Result<SqliteDatabase> {
+    let mut connection = Connection::open(path)
+      .with_context(|| format!("failed to open sqlite database `{}`", path.display()))?;
+
+    // ord_index and ord_server share the file, so wait out each other's
+    // write locks instead of failing.
+    connection.busy_timeout(Duration::from_secs(30))?;
+    connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+    Self::migrate(&mut connection, path)?;
+
+    Ok(SqliteDatabase {
+      connection: Mutex::new(connection),
+      network,
+    })
+  }
+
+  fn migrate(connection: &mut Connection, path: &Path) -> Result {
+    let tx = connection.transaction()?;
+
+    let version: u64 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let latest = u64::try_from(MIGRATIONS.len()).unwrap();
+
+    if version > latest {
+      bail!(
+        "sqlite database `{}` appears to have been migrated by a newer, incompatible version of ord, consider updating ord: database schema {version}, ord schema {latest}",
+        path.display()
+      );
+    }
+
+    for (version, statements) in (1..).zip(MIGRATIONS).skip(version.try_into().unwrap()) {
+      tx.execute_batch(statements)
+        .with_context(|| format!("failed to apply sqlite migration {version}"))?;
+      tx.pragma_update(None, "user_version", version)?;
+      log::info!("Applied sqlite migration {version} to `{}`", path.display());
+    }
+
+    tx.commit()?;
+
+    Ok(())
+  }
+
+  /// Writes `data` at `height`, adding it to the transfer history if
+  /// `timestamp` is given.
+  fn write_inscriptions(
+    tx: &Transaction,
+    height: u64,
+    timestamp: u32,
+    data: &[MirrorInscription],
+  ) -> Result {
+    for item in data {
+      let inscription_id = item.inscription_id.to_string();
+
+      let old = tx
+        .query_row(
+          SELECT_INSCRIPTION,
+          named_params! { ":inscription_id": inscription_id },
+          Self::inscription_columns,
+        )
+        .optional()
+        .with_context(|| format!("failed to query inscription {inscription_id}"))?
+        .map(InscriptionRow::try_from)
+        .transpose()?;
+
+      tx.execute(
+        INSERT_CHANGE_LOG,
+        named_params! {
+          ":height": height,
+          ":inscription_id": inscription_id,
+          ":old_satpoint": old.as_ref().map(|old| old.satpoint.to_string()),
+          ":old_address": old.as_ref().map(|old| old.address.clone()),
+          ":old_height": old.as_ref().and_then(|old| old.height),
+          ":old_output_value": old.as_ref().and_then(|old| old.output_value),
+        },
+      )
+      .with_context(|| format!("failed to log change of inscription {inscription_id}"))?;
+
+      tx.execute(
+        INSERT_TRANSFER,
+        named_params! {
+          ":inscription_id": inscription_id,
+          ":from_satpoint": item.old_satpoint.map(|old_satpoint| old_satpoint.to_string()),
+          ":to_satpoint": item.new_satpoint.to_string(),
+          ":from_address": old.map(|old| old.address),
+          ":to_address": item.new_address,
+          ":txid": item.new_satpoint.outpoint.txid.to_string(),
+          ":height": height,
+          ":timestamp": timestamp,
+        },
+      )
+      .with_context(|| format!("failed to record transfer of inscription {inscription_id}"))?;
+
+      Self::upsert_inscription(
+        tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        Some(height),
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    tx.execute(SET_SYNCED_HEIGHT, named_params! { ":height": height })
+      .context("failed to set sqlite synced height")?;
+
+    Ok(())
+  }
+
+  fn inscription_columns(row: &rusqlite::Row) -> rusqlite::Result<InscriptionColumns> {
+    Ok((
+      row.get(0)?,
+      row.get(1)?,
+      row.get(2)?,
+      row.get(3)?,
+      row.get(4)?,
+      row.get(5)?,
+      row.get(6)?,
+      row.get(7)?,
+      row.get(8)?,
+      row.get(9)?,
+      row.get(10)?,
+    ))
+  }
+
+  fn transfers(
+    &self,
+    sql: &str,
+    params: &[(&str, &dyn rusqlite::ToSql)],
+  ) -> rusqlite::Result<Vec<TransferColumns>> {
+    self
+      .connection
+      .lock()
+      .unwrap()
+      .prepare_cached(sql)?
+      .query_map(params, |row| {
+        Ok((
+          row.get(0)?,
+          row.get(1)?,
+          row.get(2)?,
+          row.get(3)?,
+          row.get(4)?,
+          row.get(5)?,
+          row.get(6)?,
+          row.get(7)?,
+        ))
+      })?
+      .collect()
+  }
+
+  fn backfill_progress(connection: &Connection) -> Result<Option<BackfillProgress>> {
+    connection
+      .query_row(SELECT_BACKFILL, [], |row| {
+        Ok((row.get::<_, u64>(0)?, row.get::<_, Option<String>>(1)?))
+      })
+      .optional()
+      .context("failed to read sqlite backfill progress")?
+      .map(BackfillProgress::try_from)
+      .transpose()
+  }
+
+  fn synced_height(connection: &Connection) -> Result<Option<u64>> {
+    connection
+      .query_row(SELECT_SYNCED_HEIGHT, [], |row| row.get(0))
+      .optional()
+      .context("failed to read sqlite synced height")
+  }
+
+  fn upsert_inscription(
+    connection: &Connection,
+    inscription_id: InscriptionId,
+    satpoint: SatPoint,
+    address: &str,
+    height: Option<u64>,
+    output_value: Option<u64>,
+    metadata: Option<&InscriptionMetadata>,
+  ) -> Result {
+    connection
+      .execute(
+        UPSERT_INSCRIPTION,
+        named_params! {
+          ":inscription_id": inscription_id.to_string(),
+          ":new_satpoint": satpoint.to_string(),
+          ":new_address": address,
+          ":height": height,
+          ":output_value": output_value,
+          ":number": metadata.map(|metadata| metadata.number),
+          ":genesis_height": metadata.map(|metadata| metadata.genesis_height),
+          ":genesis_fee": metadata.map(|metadata| metadata.genesis_fee),
+          ":timestamp": metadata.map(|metadata| metadata.timestamp),
+          ":content_type": metadata.and_then(|metadata| metadata.content_type.as_deref()),
+          ":content_length": metadata.and_then(|metadata| metadata.content_length),
+        },
+      )
+      .with_context(|| format!("failed to write inscription {inscription_id}"))?;
+    Ok(())
+  }
+}
+
+impl MirrorStore for SqliteDatabase {
+  fn network(&self) -> Network {
+    self.network
+  }
+
+  fn get_whitelist_level(&self, address: &str) -> Result<Option<String>> {
+    let level: Option<Option<String>> = self
+      .connection
+      .lock()
+      .unwrap()
+      .query_row(
+        SELECT_WHITELIST_LEVEL,
+        named_params! { ":new_address": address },
+        |row| row.get(0),
+      )
+      .optional()
+      .with_context(|| format!("failed to query whitelist for {address}"))?;
+    Ok(level.map(|level| level.unwrap_or_else(|| FeePolicy::DEFAULT_LEVEL.into())))
+  }
+
+  fn get_inscriptions_by_address(&self, address: &str) -> Result<Vec<InscriptionRow>> {
+    let connection = self.connection.lock().unwrap();
+    let mut statement = connection.prepare_cached(SELECT_INSCRIPTIONS_BY_ADDRESS)?;
+    let rows = statement
+      .query_map(
+        named_params! { ":new_address": address },
+        Self::inscription_columns,
+      )?
+      .collect::<rusqlite::Result<Vec<_>>>()
+      .with_context(|| format!("failed to query inscriptions of {address}"))?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_inscriptions_after(
+    &self,
+    after: Option<InscriptionId>,
+    limit: usize,
+  ) -> Result<Vec<InscriptionRow>> {
+    let connection = self.connection.lock().unwrap();
+    let mut statement = connection.prepare_cached(SELECT_INSCRIPTIONS_AFTER)?;
+    let rows = statement
+      .query_map(
+        named_params! {
+          ":after": after.map(|after| after.to_string()).unwrap_or_default(),
+          ":limit": limit,
+        },
+        Self::inscription_columns,
+      )?
+      .collect::<rusqlite::Result<Vec<_>>>()
+      .context("failed to query sqlite inscriptions")?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_inscriptions_in(&self, inscription_ids: &[InscriptionId]) -> Result<Vec<InscriptionRow>> {
+    if inscription_ids.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let connection = self.connection.lock().unwrap();
+    let mut statement = connection.prepare(&format!(
+      "{SELECT_INSCRIPTIONS_IN} ({})",
+      vec!["?"; inscription_ids.len()].join(", ")
+    ))?;
+    let rows = statement
+      .query_map(
+        rusqlite::params_from_iter(inscription_ids.iter().map(InscriptionId::to_string)),
+        Self::inscription_columns,
+      )?
+      .collect::<rusqlite::Result<Vec<_>>>()
+      .context("failed to query sqlite inscriptions")?;
+    rows.into_iter().map(InscriptionRow::try_from).collect()
+  }
+
+  fn get_synced_height(&self) -> Result<Option<u64>> {
+    Self::synced_height(&self.connection.lock().unwrap())
+  }
+
+  fn insert_inscriptions(
+    &self,
+    height: u64,
+    timestamp: u32,
+    data: Vec<MirrorInscription>,
+  ) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    check_next_height(Self::synced_height(&tx)?, height)?;
+
+    Self::write_inscriptions(&tx, height, timestamp, &data)?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit block {height} to sqlite"))
+  }
+
+  fn skip_blocks(&self, height: u64) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    check_skip_height(Self::synced_height(&tx)?, height)?;
+
+    tx.execute(SET_SYNCED_HEIGHT, named_params! { ":height": height })
+      .context("failed to set sqlite synced height")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit skip to block {height} to sqlite"))
+  }
+
+  fn rollback_inscriptions(&self, height: u64) -> Result<usize> {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    let changes = tx
+      .prepare(SELECT_CHANGE_LOG_ABOVE)?
+      .query_map(named_params! { ":height": height }, |row| {
+        Ok((
+          row.get(0)?,
+          row.get(1)?,
+          row.get(2)?,
+          row.get(3)?,
+          row.get(4)?,
+        ))
+      })?
+      .collect::<rusqlite::Result<Vec<_>>>()
+      .context("failed to query sqlite change log")?
+      .into_iter()
+      .map(ChangeLogRow::try_from)
+      .collect::<Result<Vec<_>>>()?;
+
+    for change in &changes {
+      match change.old_satpoint {
+        Some(old_satpoint) => Self::upsert_inscription(
+          &tx,
+          change.inscription_id,
+          old_satpoint,
+          change.old_address.as_deref().unwrap_or_default(),
+          change.old_height,
+          change.old_output_value,
+          None,
+        )?,
+        None => {
+          tx.execute(
+            DELETE_INSCRIPTION,
+            named_params! { ":inscription_id": change.inscription_id.to_string() },
+          )
+          .with_context(|| format!("failed to delete inscription {}", change.inscription_id))?;
+        }
+      }
+    }
+
+    tx.execute(DELETE_CHANGE_LOG_ABOVE, named_params! { ":height": height })
+      .context("failed to clear sqlite change log")?;
+    tx.execute(DELETE_TRANSFERS_ABOVE, named_params! { ":height": height })
+      .context("failed to clear sqlite transfers")?;
+    tx.execute(LOWER_SYNCED_HEIGHT, named_params! { ":height": height })
+      .context("failed to set sqlite synced height")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit sqlite rollback to block {height}"))?;
+    Ok(changes.len())
+  }
+
+  fn get_inscription_transfers(&self, inscription_id: InscriptionId) -> Result<Vec<TransferRow>> {
+    self
+      .transfers(
+        SELECT_TRANSFERS_BY_INSCRIPTION,
+        named_params! { ":inscription_id": inscription_id.to_string() },
+      )
+      .with_context(|| format!("failed to query transfers of inscription {inscription_id}"))?
+      .into_iter()
+      .map(TransferRow::try_from)
+      .collect()
+  }
+
+  fn get_address_transfers(&self, address: &str) -> Result<Vec<TransferRow>> {
+    self
+      .transfers(
+        SELECT_TRANSFERS_BY_ADDRESS,
+        named_params! { ":address": address },
+      )
+      .with_context(|| format!("failed to query transfers of {address}"))?
+      .into_iter()
+      .map(TransferRow::try_from)
+      .collect()
+  }
+
+  fn prune_inscription_log(&self, height: u64) -> Result {
+    self
+      .connection
+      .lock()
+      .unwrap()
+      .execute(DELETE_CHANGE_LOG_UP_TO, named_params! { ":height": height })
+      .context("failed to prune sqlite change log")?;
+    Ok(())
+  }
+
+  fn repair_inscriptions(
+    &self,
+    data: Vec<MirrorInscription>,
+    delete: Vec<InscriptionId>,
+  ) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    let height = Self::synced_height(&tx)?;
+
+    for item in &data {
+      Self::upsert_inscription(
+        &tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        height,
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    for inscription_id in delete {
+      tx.execute(
+        DELETE_INSCRIPTION,
+        named_params! { ":inscription_id": inscription_id.to_string() },
+      )
+      .with_context(|| format!("failed to delete inscription {inscription_id}"))?;
+    }
+
+    tx.commit().context("failed to commit sqlite repair")
+  }
+
+  fn get_backfill_progress(&self) -> Result<Option<BackfillProgress>> {
+    Self::backfill_progress(&self.connection.lock().unwrap())
+  }
+
+  fn start_backfill(&self, height: u64) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    tx.execute_batch(CLEAR_MIRROR)
+      .context("failed to clear sqlite mirror")?;
+    tx.execute(START_BACKFILL, named_params! { ":height": height })
+      .context("failed to start sqlite backfill")?;
+
+    tx.commit()
+      .context("failed to commit start of sqlite backfill")
+  }
+
+  fn backfill_inscriptions(&self, data: Vec<MirrorInscription>, last: InscriptionId) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    let progress =
+      Self::backfill_progress(&tx)?.ok_or_else(|| anyhow!("no sqlite backfill in progress"))?;
+
+    for item in &data {
+      Self::upsert_inscription(
+        &tx,
+        item.inscription_id,
+        item.new_satpoint,
+        &item.new_address,
+        Some(progress.height),
+        Some(item.output_value),
+        item.metadata.as_ref(),
+      )?;
+    }
+
+    tx.execute(
+      ADVANCE_BACKFILL,
+      named_params! { ":last_inscription_id": last.to_string() },
+    )
+    .context("failed to record sqlite backfill progress")?;
+
+    tx.commit()
+      .with_context(|| format!("failed to commit sqlite backfill up to {last}"))
+  }
+
+  fn finish_backfill(&self) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    let progress =
+      Self::backfill_progress(&tx)?.ok_or_else(|| anyhow!("no sqlite backfill in progress"))?;
+
+    tx.execute(
+      SET_SYNCED_HEIGHT,
+      named_params! { ":height": progress.height },
+    )
+    .context("failed to set sqlite synced height")?;
+    tx.execute(DELETE_BACKFILL, [])
+      .context("failed to finish sqlite backfill")?;
+
+    tx.commit()
+      .context("failed to commit end of sqlite backfill")
+  }
+
+  fn insert_recovery_keys(
+    &self,
+    commit_txid: Txid,
+    source: &Address,
+    recovery_keys: &[String],
+  ) -> Result {
+    let mut connection = self.connection.lock().unwrap();
+    let tx = connection.transaction()?;
+
+    for (vout, recovery_key) in recovery_keys.iter().enumerate() {
+      tx.execute(
+        UPSERT_RECOVERY_KEY,
+        named_params! {
+          ":commit_txid": commit_txid.to_string(),
+          ":vout": vout,
+          ":source": source.to_string(),
+          ":recovery_key": recovery_key,
+        },
+      )
+      .with_context(|| format!("failed to write recovery keys of commit {commit_txid}"))?;
+    }
+
+    tx.commit()
+      .with_context(|| format!("failed to commit recovery keys of commit {commit_txid}"))
+  }
+
+  fn get_recovery_keys(&self, commit_txid: Txid) -> Result<(Address, Vec<String>)> {
+    let connection = self.connection.lock().unwrap();
+    let rows = connection
+      .prepare_cached(SELECT_RECOVERY_KEYS)?
+      .query_map(
+        named_params! { ":commit_txid": commit_txid.to_string() },
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+      )?
+      .collect::<rusqlite::Result<Vec<_>>>()
+      .with_context(|| format!("failed to query recovery keys of commit {commit_txid}"))?;
+
+    let source = parse_column(
+      "source",
+      &rows
+        .first()
+        .ok_or_else(|| anyhow!("Recovery key for commit {commit_txid} not found"))?
+        .0,
+    )?;
+
+    Ok((source, rows.into_iter().map(|(_, key)| key).collect()))
+  }
+
+  fn insert_order(&self, order: &Order) -> Result {
+    let (reveal_txids, inputs, reveals) = order.list_columns();
+
+    self
+      .connection
+      .lock()
+      .unwrap()
+      .execute(
+        UPSERT_ORDER,
+        named_params! {
+          ":commit_txid": order.commit_txid.to_string(),
+          ":reveal_txids": reveal_txids,
+          ":inputs": inputs,
+          ":source": order.source.to_string(),
+          ":destination": order.destination.to_string(),
+          ":service_fee": order.service_fee,
+          ":satpoint_fee": order.satpoint_fee,
+          ":network_fee": order.network_fee,
+          ":commit_fee": order.commit_fee,
+          ":created_at": order.created_at,
+          ":reveals": reveals,
+        },
+      )
+      .with_context(|| format!("failed to write order of commit {}", order.commit_txid))?;
+
+    Ok(())
+  }
+
+  fn get_order(&self, commit_txid: Txid) -> Result<Option<Order>> {
+    self
+      .connection
+      .lock()
+      .unwrap()
+      .query_row(
+        SELECT_ORDER,
+        named_params! { ":commit_txid": commit_txid.to_string() },
+        |row| {
+          Ok::<OrderColumns, _>((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get(7)?,
+            row.get(8)?,
+            row.get(9)?,
+            row.get(10)?,
+          ))
+        },
+      )
+      .optional()
+      .with_context(|| format!("failed to query order of commit {commit_txid}"))?
+      .map(Order::try_from)
+      .transpose()
+  }
+}
@@ -0,0 +1,253 @@
+use {super::*, redb::AccessGuard};
+
+/// Tables written by `Updater::index_block`, as tagged in undo logs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum UndoTable {
+  HeightToBlockHash,
+  InscriptionIdToInscriptionEntry,
+  InscriptionIdToSatpoint,
+  InscriptionNumberToInscriptionId,
+  OutpointToSatRanges,
+  OutpointToTxout,
+  OutpointToValue,
+  SatToInscriptionId,
+  SatToSatpoint,
+  SatpointToInscriptionId,
+  ScriptPubkeyToOutpoint,
+  StatisticToCount,
+  /// A statistic the block added to. Rolling back subtracts the amount
+  /// instead of restoring an old value, since later blocks add to it too.
+  StatisticIncrement,
+}
+
+impl UndoTable {
+  const ALL: [Self; 13] = [
+    Self::HeightToBlockHash,
+    Self::InscriptionIdToInscriptionEntry,
+    Self::InscriptionIdToSatpoint,
+    Self::InscriptionNumberToInscriptionId,
+    Self::OutpointToSatRanges,
+    Self::OutpointToTxout,
+    Self::OutpointToValue,
+    Self::SatToInscriptionId,
+    Self::SatToSatpoint,
+    Self::SatpointToInscriptionId,
+    Self::ScriptPubkeyToOutpoint,
+    Self::StatisticToCount,
+    Self::StatisticIncrement,
+  ];
+
+  fn from_u8(n: u8) -> Result<Self> {
+    Self::ALL
+      .get(usize::from(n))
+      .copied()
+      .ok_or_else(|| anyhow!("invalid undo log table {n}"))
+  }
+}
+
+/// The previous value of every entry a block changed, in the order the block
+/// changed them. Replaying the log backwards restores the tables to their
+/// state before the block. Multimap entries are keyed by value followed by
+/// key and only record whether the pair was present.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct UndoLog {
+  entries: Vec<(UndoTable, Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl UndoLog {
+  pub(super) fn record(&mut self, table: UndoTable, key: &[u8], old: Option<&[u8]>) {
+    self
+      .entries
+      .push((table, key.to_vec(), old.map(|old| old.to_vec())));
+  }
+
+  /// Records the old value returned by a table `insert` or `remove`.
+  pub(super) fn record_guard<V: RedbValue + 'static>(
+    &mut self,
+    table: UndoTable,
+    key: &[u8],
+    old: Option<AccessGuard<V>>,
+  ) {
+    let old = old.map(|old| V::as_bytes(&old.value()).as_ref().to_vec());
+    self.entries.push((table, key.to_vec(), old));
+  }
+
+  /// Records that `statistic` grew by `n`.
+  pub(super) fn record_increment(&mut self, statistic: Statistic, n: u64) {
+    if n > 0 {
+      self.record(
+        UndoTable::StatisticIncrement,
+        &statistic.key().to_le_bytes(),
+        Some(&n.to_le_bytes()),
+      );
+    }
+  }
+
+  /// Each change the block made to an inscription's location, with the
+  /// location before the change.
+  pub(super) fn inscription_satpoints(
+    &self,
+  ) -> impl DoubleEndedIterator<Item = (InscriptionId, Option<SatPoint>)> + '_ {
+    self
+      .entries
+      .iter()
+      .filter(|(table, _, _)| *table == UndoTable::InscriptionIdToSatpoint)
+      .map(|(_, key, old)| {
+        (
+          InscriptionId::load(key.as_slice().try_into().unwrap()),
+          old
+            .as_deref()
+            .map(|old| SatPoint::load(old.try_into().unwrap())),
+        )
+      })
+  }
+
+  pub(super) fn store(&self) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (table, key, old) in &self.entries {
+      bytes.push(*table as u8);
+      bytes.extend_from_slice(&u32::try_from(key.len()).unwrap().to_le_bytes());
+      bytes.extend_from_slice(key);
+      match old {
+        Some(old) => {
+          bytes.push(1);
+          bytes.extend_from_slice(&u32::try_from(old.len()).unwrap().to_le_bytes());
+          bytes.extend_from_slice(old);
+        }
+        None => bytes.push(0),
+      }
+    }
+    bytes
+  }
+
+  pub(super) fn load(mut bytes: &[u8]) -> Result<Self> {
+    fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+      if bytes.len() < n {
+        bail!("truncated undo log");
+      }
+      let (head, tail) = bytes.split_at(n);
+      *bytes = tail;
+      Ok(head)
+    }
+
+    fn take_vec(bytes: &mut &[u8]) -> Result<Vec<u8>> {
+      let len = u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap());
+      Ok(take(bytes, len.try_into().unwrap())?.to_vec())
+    }
+
+    let mut entries = Vec::new();
+    while !bytes.is_empty() {
+      let table = UndoTable::from_u8(take(&mut bytes, 1)?[0])?;
+      let key = take_vec(&mut bytes)?;
+      let old = match take(&mut bytes, 1)?[0] {
+        0 => None,
+        1 => Some(take_vec(&mut bytes)?),
+        tag => bail!("invalid undo log value tag {tag}"),
+      };
+      entries.push((table, key, old));
+    }
+
+    Ok(Self { entries })
+  }
+
+  pub(super) fn rollback(self, wtx: &WriteTransaction) -> Result {
+    for (table, key, old) in self.entries.into_iter().rev() {
+      let old = old.as_deref();
+      match table {
+        UndoTable::HeightToBlockHash => restore(wtx, HEIGHT_TO_BLOCK_HASH, &key, old)?,
+        UndoTable::InscriptionIdToInscriptionEntry => {
+          restore(wtx, INSCRIPTION_ID_TO_INSCRIPTION_ENTRY, &key, old)?
+        }
+        UndoTable::InscriptionIdToSatpoint => restore(wtx, INSCRIPTION_ID_TO_SATPOINT, &key, old)?,
+        UndoTable::InscriptionNumberToInscriptionId => {
+          restore(wtx, INSCRIPTION_NUMBER_TO_INSCRIPTION_ID, &key, old)?
+        }
+        UndoTable::OutpointToSatRanges => restore(wtx, OUTPOINT_TO_SAT_RANGES, &key, old)?,
+        UndoTable::OutpointToTxout => restore(wtx, OUTPOINT_TO_TXOUT, &key, old)?,
+        UndoTable::OutpointToValue => restore(wtx, OUTPOINT_TO_VALUE, &key, old)?,
+        UndoTable::SatToInscriptionId => restore(wtx, SAT_TO_INSCRIPTION_ID, &key, old)?,
+        UndoTable::SatToSatpoint => restore(wtx, SAT_TO_SATPOINT, &key, old)?,
+        UndoTable::SatpointToInscriptionId => restore(wtx, SATPOINT_TO_INSCRIPTION_ID, &key, old)?,
+        UndoTable::ScriptPubkeyToOutpoint => {
+          if key.len() < 36 {
+            bail!("invalid undo log multimap entry");
+          }
+          let (outpoint, script_pubkey) = key.split_at(36);
+          let outpoint: &OutPointValue = outpoint.try_into().unwrap();
+          let mut table = wtx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
+          if old.is_some() {
+            table.insert(script_pubkey, outpoint)?;
+          } else {
+            table.remove(script_pubkey, outpoint)?;
+          }
+        }
+        UndoTable::StatisticToCount => restore(wtx, STATISTIC_TO_COUNT, &key, old)?,
+        UndoTable::StatisticIncrement => {
+          let (Ok(key), Some(Ok(n))) = (
+            <[u8; 8]>::try_from(key.as_slice()),
+            old.map(<[u8; 8]>::try_from),
+          ) else {
+            bail!("invalid undo log statistic increment");
+          };
+          let key = u64::from_le_bytes(key);
+          let mut table = wtx.open_table(STATISTIC_TO_COUNT)?;
+          let count = table.get(&key)?.map(|count| count.value()).unwrap_or(0);
+          table.insert(&key, &count.saturating_sub(u64::from_le_bytes(n)))?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+}
+
+fn restore<K: RedbKey + 'static, V: RedbValue + 'static>(
+  wtx: &WriteTransaction,
+  definition: TableDefinition<K, V>,
+  key: &[u8],
+  old: Option<&[u8]>,
+) -> Result {
+  let mut table = wtx.open_table(definition)?;
+  match old {
+    Some(old) => {
+      table.insert(K::from_bytes(key), V::from_bytes(old))?;
+    }
+    None => {
+      table.remove(K::from_bytes(key))?;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn store_and_load() {
+    let mut undo_log = UndoLog::default();
+    undo_log.record(UndoTable::HeightToBlockHash, &1u64.to_le_bytes(), None);
+    undo_log.record(
+      UndoTable::OutpointToSatRanges,
+      &OutPoint::null().store(),
+      Some(&[1, 2, 3]),
+    );
+    undo_log.record(UndoTable::StatisticToCount, &[], Some(&[]));
+
+    assert_eq!(UndoLog::load(&undo_log.store()).unwrap(), undo_log);
+  }
+
+  #[test]
+  fn load_rejects_truncated_log() {
+    let mut undo_log = UndoLog::default();
+    undo_log.record(UndoTable::OutpointToValue, &[0; 36], Some(&[0; 8]));
+    let bytes = undo_log.store();
+
+    assert_eq!(
+      UndoLog::load(&bytes[..bytes.len() - 1])
+        .unwrap_err()
+        .to_string(),
+      "truncated undo log"
+    );
+  }
+}
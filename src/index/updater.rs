@@ -1,6 +1,10 @@
 use {
   self::inscription_updater::InscriptionUpdater,
-  super::{fetcher::Fetcher, *},
+  super::{
+    fetcher::Fetcher,
+    mirror::{skip_mirror_blocks, write_mirror_block},
+    *,
+  },
   futures::future::try_join_all,
   std::sync::mpsc,
   tokio::sync::mpsc::{error::TryRecvError, Receiver, Sender},
@@ -8,6 +12,9 @@ use {
 
 mod inscription_updater;
 
+/// Number of most recent blocks that keep an undo log and can be rolled back.
+pub(super) const UNDO_LOG_DEPTH: u64 = 100;
+
 struct BlockData {
   header: BlockHeader,
   txdata: Vec<(Transaction, Txid)>,
@@ -32,11 +39,13 @@ impl From<Block> for BlockData {
 pub(crate) struct Updater {
   range_cache: HashMap<OutPointValue, Vec<u8>>,
   height: u64,
+  index_addresses: bool,
   index_sats: bool,
   sat_ranges_since_flush: u64,
   outputs_cached: u64,
   outputs_inserted_since_flush: u64,
   outputs_traversed: u64,
+  mirror_synced: Option<u64>,
 }
 
 impl Updater {
@@ -50,16 +59,28 @@ impl Updater {
       .map(|(height, _hash)| height.value())
       .unwrap_or(0);
 
-    {
-      let mut height_to_block_hash = wtx.open_table(HEIGHT_TO_BLOCK_HASH)?;
-
-      while height > target_height {
-        log::info!("Reorg height to {height}");
-        height_to_block_hash.remove(height)?;
-        height = height
-          .checked_sub(1)
-          .ok_or_else(|| anyhow!("Check sub fail"))?;
-      }
+    while height > target_height {
+      log::info!("Reorg height to {height}");
+
+      let undo_log = wtx
+        .open_table(HEIGHT_TO_UNDO_LOG)?
+        .remove(&height)?
+        .map(|undo_log| UndoLog::load(undo_log.value()))
+        .transpose()?
+        .ok_or_else(|| {
+          anyhow!("no undo log for block {height}, cannot roll back to {target_height}")
+        })?;
+
+      undo_log.rollback(&wtx)?;
+
+      height = height
+        .checked_sub(1)
+        .ok_or_else(|| anyhow!("Check sub fail"))?;
+    }
+
+    if let Some(mirror) = &index.mirror {
+      let changes = mirror.rollback_inscriptions(target_height)?;
+      log::info!("Roll back {changes} inscription changes in mirror");
     }
 
     wtx.commit()?;
@@ -87,19 +108,51 @@ impl Updater {
           .unwrap_or(0),
       )?;
 
+    if let Some(mirror) = index.mirror.as_deref() {
+      Self::reconcile_mirror(mirror, height.checked_sub(1))?;
+    }
+
     let mut updater = Self {
       range_cache: HashMap::new(),
       height,
+      index_addresses: index.has_address_index()?,
       index_sats: index.has_sat_index()?,
       sat_ranges_since_flush: 0,
       outputs_cached: 0,
       outputs_inserted_since_flush: 0,
       outputs_traversed: 0,
+      mirror_synced: height.checked_sub(1),
     };
 
     updater.update_index(index, wtx)
   }
 
+  /// Brings the mirror back to the last committed block. The mirror
+  /// can be ahead if blocks were written to it but the redb transaction
+  /// holding them was never committed. A mirror that is behind needs
+  /// `Index::catch_up_mirror` first, since new blocks can't be written past a
+  /// gap.
+  fn reconcile_mirror(mirror: &dyn MirrorStore, indexed: Option<u64>) -> Result {
+    let synced = mirror.get_synced_height()?;
+
+    match (synced, indexed) {
+      (Some(synced), Some(indexed)) if synced > indexed => {
+        log::info!("Roll back mirror from block {synced} to {indexed}");
+        mirror.rollback_inscriptions(indexed)?;
+      }
+      (Some(synced), None) => {
+        bail!("mirror is synced to block {synced} but the index is empty")
+      }
+      (synced, Some(indexed)) if synced < Some(indexed) => bail!(
+        "mirror is synced to block {} but the index is at block {indexed}, catch up mirror first",
+        synced.map_or("none".into(), |synced| synced.to_string())
+      ),
+      _ => {}
+    }
+
+    Ok(())
+  }
+
   fn update_index<'index>(
     &mut self,
     index: &'index Index,
@@ -122,7 +175,7 @@ impl Updater {
       Some(progress_bar)
     };
 
-    let rx = Self::fetch_blocks_from(index, self.height, self.index_sats)?;
+    let rx = Self::fetch_blocks_from(index, self.height, self.index_sats || self.index_addresses)?;
 
     let (mut outpoint_sender, mut value_receiver) = Self::spawn_fetcher(index)?;
 
@@ -158,7 +211,7 @@ impl Updater {
       uncommitted += 1;
 
       if uncommitted == 5000 {
-        self.commit(wtx, value_cache)?;
+        self.commit(index, wtx, value_cache)?;
         value_cache = HashMap::new();
         uncommitted = 0;
         wtx = index.begin_write()?;
@@ -191,7 +244,7 @@ impl Updater {
     }
 
     if uncommitted > 0 {
-      self.commit(wtx, value_cache)?;
+      self.commit(index, wtx, value_cache)?;
     }
 
     if let Some(progress_bar) = &mut progress_bar {
@@ -204,7 +257,7 @@ impl Updater {
   fn fetch_blocks_from(
     index: &Index,
     mut height: u64,
-    index_sats: bool,
+    full_blocks: bool,
   ) -> Result<mpsc::Receiver<BlockData>> {
     let (tx, rx) = mpsc::sync_channel(32);
 
@@ -221,7 +274,7 @@ impl Updater {
         }
       }
 
-      match Self::get_block_with_retries(&client, height, index_sats, first_inscription_height) {
+      match Self::get_block_with_retries(&client, height, full_blocks, first_inscription_height) {
         Ok(Some(block)) => {
           if let Err(err) = tx.send(block.into()) {
             log::info!("Block receiver disconnected: {err}");
@@ -243,7 +296,7 @@ impl Updater {
   fn get_block_with_retries(
     client: &Client,
     height: u64,
-    index_sats: bool,
+    full_blocks: bool,
     first_inscription_height: u64,
   ) -> Result<Option<Block>> {
     let mut errors = 0;
@@ -254,7 +307,7 @@ impl Updater {
         .and_then(|option| {
           option
             .map(|hash| {
-              if index_sats || height >= first_inscription_height {
+              if full_blocks || height >= first_inscription_height {
                 Ok(client.get_block(&hash)?)
               } else {
                 Ok(Block {
@@ -418,6 +471,7 @@ impl Updater {
     let start = Instant::now();
     let mut sat_ranges_written = 0;
     let mut outputs_in_block = 0;
+    let sat_ranges_before_block = self.sat_ranges_since_flush;
 
     let time = timestamp(block.header.time);
 
@@ -469,10 +523,10 @@ impl Updater {
       block.header.time,
       unbound_inscriptions,
       value_cache,
-      index.mysql_database.clone(),
+      index.mirror.clone(),
     )?;
 
-    let mut mysql_data: Vec<MysqlInscription> = vec![];
+    let mut mirror_data: Vec<MirrorInscription> = vec![];
     if self.index_sats {
       let mut sat_to_satpoint = wtx.open_table(SAT_TO_SATPOINT)?;
       let mut outpoint_to_sat_ranges = wtx.open_table(OUTPOINT_TO_SAT_RANGES)?;
@@ -506,6 +560,12 @@ impl Updater {
               .to_vec(),
           };
 
+          inscription_updater.undo_log.record(
+            UndoTable::OutpointToSatRanges,
+            &key,
+            Some(&sat_ranges),
+          );
+
           for chunk in sat_ranges.chunks_exact(11) {
             input_sat_ranges.push_back(SatRange::load(chunk.try_into().unwrap()));
           }
@@ -521,7 +581,7 @@ impl Updater {
           &mut inscription_updater,
           index_inscriptions,
         )?;
-        mysql_data.extend(d);
+        mirror_data.extend(d);
 
         coinbase_inputs.extend(input_sat_ranges);
       }
@@ -537,25 +597,36 @@ impl Updater {
           &mut inscription_updater,
           index_inscriptions,
         )?;
-        mysql_data.extend(d);
+        mirror_data.extend(d);
       }
 
       if !coinbase_inputs.is_empty() {
-        let mut lost_sat_ranges = outpoint_to_sat_ranges
+        let lost_sat_ranges = outpoint_to_sat_ranges
           .remove(&OutPoint::null().store())?
-          .map(|ranges| ranges.value().to_vec())
-          .unwrap_or_default();
+          .map(|ranges| ranges.value().to_vec());
+
+        inscription_updater.undo_log.record(
+          UndoTable::OutpointToSatRanges,
+          &OutPoint::null().store(),
+          lost_sat_ranges.as_deref(),
+        );
+
+        let mut lost_sat_ranges = lost_sat_ranges.unwrap_or_default();
 
         for (start, end) in coinbase_inputs {
           if !Sat(start).is_common() {
-            sat_to_satpoint.insert(
-              &start,
-              &SatPoint {
-                outpoint: OutPoint::null(),
-                offset: lost_sats,
-              }
-              .store(),
-            )?;
+            inscription_updater.undo_log.record_guard(
+              UndoTable::SatToSatpoint,
+              &start.to_le_bytes(),
+              sat_to_satpoint.insert(
+                &start,
+                &SatPoint {
+                  outpoint: OutPoint::null(),
+                  offset: lost_sats,
+                }
+                .store(),
+              )?,
+            );
           }
 
           lost_sat_ranges.extend_from_slice(&(start, end).store());
@@ -568,26 +639,73 @@ impl Updater {
     } else {
       for (tx, txid) in block.txdata.iter().skip(1).chain(block.txdata.first()) {
         let d = inscription_updater.index_transaction_inscriptions(tx, *txid, None)?;
-        mysql_data.extend(d);
+        mirror_data.extend(d);
       }
     }
 
-    if let Some(mysql) = index.mysql_database.clone() {
-      let data_length = mysql_data.len();
-      match mysql.insert_inscriptions(mysql_data) {
-        Ok(_) => log::info!("Insert {data_length} item success"),
-        Err(err) => log::info!("Insert {data_length} item fail:{err}"),
+    if self.index_addresses {
+      let mut outpoint_to_txout = wtx.open_table(OUTPOINT_TO_TXOUT)?;
+      let mut script_pubkey_to_outpoint = wtx.open_multimap_table(SCRIPT_PUBKEY_TO_OUTPOINT)?;
+
+      for (tx, txid) in &block.txdata {
+        Self::index_transaction_addresses(
+          tx,
+          *txid,
+          &mut outpoint_to_txout,
+          &mut script_pubkey_to_outpoint,
+          &mut inscription_updater.undo_log,
+        )?;
       }
     }
 
-    statistic_to_count.insert(&Statistic::LostSats.key(), &inscription_updater.lost_sats)?;
+    let mut undo_log = inscription_updater.undo_log;
 
-    statistic_to_count.insert(
-      &Statistic::UnboundInscriptions.key(),
-      &inscription_updater.unbound_inscriptions,
-    )?;
+    undo_log.record_guard(
+      UndoTable::StatisticToCount,
+      &Statistic::LostSats.key().to_le_bytes(),
+      statistic_to_count.insert(&Statistic::LostSats.key(), &inscription_updater.lost_sats)?,
+    );
 
-    height_to_block_hash.insert(&self.height, &block.header.block_hash().store())?;
+    undo_log.record_guard(
+      UndoTable::StatisticToCount,
+      &Statistic::UnboundInscriptions.key().to_le_bytes(),
+      statistic_to_count.insert(
+        &Statistic::UnboundInscriptions.key(),
+        &inscription_updater.unbound_inscriptions,
+      )?,
+    );
+
+    // Added to the statistics when the block is committed.
+    undo_log.record_increment(Statistic::OutputsTraversed, outputs_in_block);
+    undo_log.record_increment(
+      Statistic::SatRanges,
+      self.sat_ranges_since_flush - sat_ranges_before_block,
+    );
+
+    undo_log.record_guard(
+      UndoTable::HeightToBlockHash,
+      &self.height.to_le_bytes(),
+      height_to_block_hash.insert(&self.height, &block.header.block_hash().store())?,
+    );
+
+    let mut height_to_undo_log = wtx.open_table(HEIGHT_TO_UNDO_LOG)?;
+    height_to_undo_log.insert(&self.height, undo_log.store().as_slice())?;
+    if let Some(height) = self.height.checked_sub(UNDO_LOG_DEPTH) {
+      height_to_undo_log.remove(&height)?;
+    }
+
+    if let Some(mirror) = index.mirror.as_deref() {
+      let data_length = mirror_data.len();
+      write_mirror_block(
+        mirror,
+        &mut self.mirror_synced,
+        self.height,
+        block.header.time,
+        mirror_data,
+      )
+      .with_context(|| format!("failed to write block {} to mirror", self.height))?;
+      log::info!("Insert {data_length} item success");
+    }
 
     self.height += 1;
     self.outputs_traversed += outputs_in_block;
@@ -610,12 +728,12 @@ impl Updater {
     outputs_traversed: &mut u64,
     inscription_updater: &mut InscriptionUpdater,
     index_inscriptions: bool,
-  ) -> Result<Vec<MysqlInscription>> {
-    let mut mysql_data: Vec<MysqlInscription> = vec![];
+  ) -> Result<Vec<MirrorInscription>> {
+    let mut mirror_data: Vec<MirrorInscription> = vec![];
     if index_inscriptions {
       let d =
         inscription_updater.index_transaction_inscriptions(tx, txid, Some(input_sat_ranges))?;
-      mysql_data.extend(d);
+      mirror_data.extend(d);
     }
 
     for (vout, output) in tx.output.iter().enumerate() {
@@ -632,14 +750,18 @@ impl Updater {
           .ok_or_else(|| anyhow!("insufficient inputs for transaction outputs"))?;
 
         if !Sat(range.0).is_common() {
-          sat_to_satpoint.insert(
-            &range.0,
-            &SatPoint {
-              outpoint,
-              offset: output.value - remaining,
-            }
-            .store(),
-          )?;
+          inscription_updater.undo_log.record_guard(
+            UndoTable::SatToSatpoint,
+            &range.0.to_le_bytes(),
+            sat_to_satpoint.insert(
+              &range.0,
+              &SatPoint {
+                outpoint,
+                offset: output.value - remaining,
+              }
+              .store(),
+            )?,
+          );
         }
 
         let count = range.1 - range.0;
@@ -662,14 +784,82 @@ impl Updater {
 
       *outputs_traversed += 1;
 
+      inscription_updater
+        .undo_log
+        .record(UndoTable::OutpointToSatRanges, &outpoint.store(), None);
       self.range_cache.insert(outpoint.store(), sats);
       self.outputs_inserted_since_flush += 1;
     }
 
-    Ok(mysql_data)
+    Ok(mirror_data)
+  }
+
+  fn index_transaction_addresses(
+    tx: &Transaction,
+    txid: Txid,
+    outpoint_to_txout: &mut Table<&OutPointValue, &[u8]>,
+    script_pubkey_to_outpoint: &mut MultimapTable<&[u8], &OutPointValue>,
+    undo_log: &mut UndoLog,
+  ) -> Result {
+    for input in &tx.input {
+      if input.previous_output.is_null() {
+        continue;
+      }
+
+      let key = input.previous_output.store();
+
+      let Some(txout) = outpoint_to_txout
+        .remove(&key)?
+        .map(|txout| txout.value().to_vec())
+      else {
+        continue;
+      };
+
+      undo_log.record(UndoTable::OutpointToTxout, &key, Some(&txout));
+
+      let script_pubkey = consensus::encode::deserialize::<TxOut>(&txout)?.script_pubkey;
+
+      let removed = script_pubkey_to_outpoint.remove(script_pubkey.as_bytes(), &key)?;
+      undo_log.record(
+        UndoTable::ScriptPubkeyToOutpoint,
+        &[key.as_slice(), script_pubkey.as_bytes()].concat(),
+        removed.then_some(&[]),
+      );
+    }
+
+    for (vout, output) in tx.output.iter().enumerate() {
+      if output.script_pubkey.is_provably_unspendable() {
+        continue;
+      }
+
+      let key = OutPoint {
+        vout: vout.try_into().unwrap(),
+        txid,
+      }
+      .store();
+
+      undo_log.record_guard(
+        UndoTable::OutpointToTxout,
+        &key,
+        outpoint_to_txout.insert(&key, consensus::encode::serialize(output).as_slice())?,
+      );
+      let existed = script_pubkey_to_outpoint.insert(output.script_pubkey.as_bytes(), &key)?;
+      undo_log.record(
+        UndoTable::ScriptPubkeyToOutpoint,
+        &[key.as_slice(), output.script_pubkey.as_bytes()].concat(),
+        existed.then_some(&[]),
+      );
+    }
+
+    Ok(())
   }
 
-  fn commit(&mut self, wtx: WriteTransaction, value_cache: HashMap<OutPoint, u64>) -> Result {
+  fn commit(
+    &mut self,
+    index: &Index,
+    wtx: WriteTransaction,
+    value_cache: HashMap<OutPoint, u64>,
+  ) -> Result {
     log::info!(
       "Committing at block height {}, {} outputs traversed, {} in map, {} cached",
       self.height,
@@ -709,7 +899,34 @@ impl Updater {
     self.sat_ranges_since_flush = 0;
     Index::increment_statistic(&wtx, Statistic::Commits, 1)?;
 
+    // The commit is undone with the last block it wrote.
+    if let Some(height) = self.height.checked_sub(1) {
+      let mut height_to_undo_log = wtx.open_table(HEIGHT_TO_UNDO_LOG)?;
+      let undo_log = height_to_undo_log
+        .get(&height)?
+        .map(|undo_log| UndoLog::load(undo_log.value()))
+        .transpose()?;
+      if let Some(mut undo_log) = undo_log {
+        undo_log.record_increment(Statistic::Commits, 1);
+        height_to_undo_log.insert(&height, undo_log.store().as_slice())?;
+      }
+    }
+
+    if let (Some(mirror), Some(height)) = (index.mirror.as_deref(), self.height.checked_sub(1)) {
+      skip_mirror_blocks(mirror, &mut self.mirror_synced, height)
+        .with_context(|| format!("failed to advance mirror to block {height}"))?;
+    }
+
     wtx.commit()?;
+
+    if let Some(mirror) = &index.mirror {
+      if let Some(height) = self.height.checked_sub(UNDO_LOG_DEPTH + 1) {
+        mirror
+          .prune_inscription_log(height)
+          .with_context(|| format!("failed to prune mirror change log below block {height}"))?;
+      }
+    }
+
     Ok(())
   }
 }
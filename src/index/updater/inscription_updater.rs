@@ -8,8 +8,14 @@ pub(super) struct Flotsam {
 }
 
 enum Origin {
-  New { fee: u64 },
-  Old { old_satpoint: SatPoint },
+  New {
+    fee: u64,
+    content_type: Option<String>,
+    content_length: Option<u64>,
+  },
+  Old {
+    old_satpoint: SatPoint,
+  },
 }
 
 pub(super) struct InscriptionUpdater<'a, 'db, 'tx> {
@@ -27,8 +33,9 @@ pub(super) struct InscriptionUpdater<'a, 'db, 'tx> {
   satpoint_to_id: &'a mut Table<'db, 'tx, &'static SatPointValue, &'static InscriptionIdValue>,
   timestamp: u32,
   pub(super) unbound_inscriptions: u64,
+  pub(super) undo_log: UndoLog,
   value_cache: &'a mut HashMap<OutPoint, u64>,
-  mysql_database: Option<Arc<MysqlDatabase>>,
+  mirror: Option<Arc<dyn MirrorStore>>,
 }
 
 impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
@@ -45,7 +52,7 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
     timestamp: u32,
     unbound_inscriptions: u64,
     value_cache: &'a mut HashMap<OutPoint, u64>,
-    mysql_database: Option<Arc<MysqlDatabase>>,
+    mirror: Option<Arc<dyn MirrorStore>>,
   ) -> Result<Self> {
     let next_number = number_to_id
       .iter()?
@@ -69,8 +76,9 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
       satpoint_to_id,
       timestamp,
       unbound_inscriptions,
+      undo_log: UndoLog::default(),
       value_cache,
-      mysql_database,
+      mirror,
     })
   }
 
@@ -79,11 +87,11 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
     tx: &Transaction,
     txid: Txid,
     input_sat_ranges: Option<&VecDeque<(u64, u64)>>,
-  ) -> Result<Vec<MysqlInscription>> {
+  ) -> Result<Vec<MirrorInscription>> {
     let mut inscriptions = Vec::new();
 
     let mut input_value = 0;
-    let mut mysql_data: Vec<MysqlInscription> = vec![];
+    let mut mirror_data: Vec<MirrorInscription> = vec![];
     for tx_in in &tx.input {
       if tx_in.previous_output.is_null() {
         input_value += Height(self.height).subsidy();
@@ -97,13 +105,18 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
             origin: Origin::Old { old_satpoint },
           });
         }
+        let key = tx_in.previous_output.store();
         input_value += if let Some(value) = self.value_cache.remove(&tx_in.previous_output) {
+          self
+            .undo_log
+            .record(UndoTable::OutpointToValue, &key, Some(&value.to_le_bytes()));
+          value
+        } else if let Some(value) = self.outpoint_to_value.remove(&key)? {
+          let value = value.value();
+          self
+            .undo_log
+            .record(UndoTable::OutpointToValue, &key, Some(&value.to_le_bytes()));
           value
-        } else if let Some(value) = self
-          .outpoint_to_value
-          .remove(&tx_in.previous_output.store())?
-        {
-          value.value()
         } else {
           self.value_receiver.blocking_recv().ok_or_else(|| {
             anyhow!(
@@ -115,14 +128,21 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
       }
     }
 
-    if inscriptions.iter().all(|flotsam| flotsam.offset != 0)
-      && Inscription::from_transaction(tx).is_some()
+    if let Some(inscription) = inscriptions
+      .iter()
+      .all(|flotsam| flotsam.offset != 0)
+      .then(|| Inscription::from_transaction(tx))
+      .flatten()
     {
       let flotsam = Flotsam {
         inscription_id: txid.into(),
         offset: 0,
         origin: Origin::New {
           fee: input_value - tx.output.iter().map(|txout| txout.value).sum::<u64>(),
+          content_type: inscription.content_type().map(str::to_owned),
+          content_length: inscription
+            .content_length()
+            .map(|length| length.try_into().unwrap()),
         },
       };
 
@@ -171,8 +191,8 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
           offset: flotsam.offset - output_value,
         };
 
-        let new_address = if let Some(mysql_database) = self.mysql_database.clone() {
-          if let Ok(addr) = Address::from_script(&tx_out.script_pubkey, mysql_database.network) {
+        let new_address = if let Some(mirror) = self.mirror.clone() {
+          if let Ok(addr) = Address::from_script(&tx_out.script_pubkey, mirror.network()) {
             format!("{}", addr)
           } else {
             "".to_owned()
@@ -183,10 +203,32 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
 
         let flotsam = inscriptions.next().unwrap();
 
-        mysql_data.push(MysqlInscription {
+        let (old_satpoint, metadata) = match &flotsam.origin {
+          Origin::New {
+            fee,
+            content_type,
+            content_length,
+          } => (
+            None,
+            Some(InscriptionMetadata {
+              number: self.next_number,
+              genesis_height: self.height,
+              genesis_fee: *fee,
+              timestamp: self.timestamp,
+              content_type: content_type.clone(),
+              content_length: *content_length,
+            }),
+          ),
+          Origin::Old { old_satpoint } => (Some(*old_satpoint), None),
+        };
+
+        mirror_data.push(MirrorInscription {
           inscription_id: flotsam.inscription_id,
+          old_satpoint,
           new_satpoint,
           new_address,
+          output_value: tx_out.value,
+          metadata,
         });
 
         self.update_inscription_location(input_sat_ranges, flotsam, new_satpoint)?;
@@ -194,13 +236,14 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
 
       output_value = end;
 
-      self.value_cache.insert(
-        OutPoint {
-          vout: vout.try_into().unwrap(),
-          txid,
-        },
-        tx_out.value,
-      );
+      let outpoint = OutPoint {
+        vout: vout.try_into().unwrap(),
+        txid,
+      };
+      self
+        .undo_log
+        .record(UndoTable::OutpointToValue, &outpoint.store(), None);
+      self.value_cache.insert(outpoint, tx_out.value);
     }
 
     if is_coinbase {
@@ -212,14 +255,14 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
         self.update_inscription_location(input_sat_ranges, flotsam, new_satpoint)?;
       }
       self.lost_sats += self.reward - output_value;
-      Ok(mysql_data)
+      Ok(mirror_data)
     } else {
       self.flotsam.extend(inscriptions.map(|flotsam| Flotsam {
         offset: self.reward + flotsam.offset - output_value,
         ..flotsam
       }));
       self.reward += input_value - output_value;
-      Ok(mysql_data)
+      Ok(mirror_data)
     }
   }
 
@@ -233,12 +276,21 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
 
     match flotsam.origin {
       Origin::Old { old_satpoint } => {
-        self.satpoint_to_id.remove(&old_satpoint.store())?;
+        let old_satpoint = old_satpoint.store();
+        self.undo_log.record_guard(
+          UndoTable::SatpointToInscriptionId,
+          &old_satpoint,
+          self.satpoint_to_id.remove(&old_satpoint)?,
+        );
       }
-      Origin::New { fee } => {
-        self
-          .number_to_id
-          .insert(&self.next_number, &inscription_id)?;
+      Origin::New { fee, .. } => {
+        self.undo_log.record_guard(
+          UndoTable::InscriptionNumberToInscriptionId,
+          &self.next_number.to_le_bytes(),
+          self
+            .number_to_id
+            .insert(&self.next_number, &inscription_id)?,
+        );
 
         let mut sat = None;
         if let Some(input_sat_ranges) = input_sat_ranges {
@@ -247,7 +299,11 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
             let size = end - start;
             if offset + size > flotsam.offset {
               let n = start + flotsam.offset - offset;
-              self.sat_to_inscription_id.insert(&n, &inscription_id)?;
+              self.undo_log.record_guard(
+                UndoTable::SatToInscriptionId,
+                &n.to_le_bytes(),
+                self.sat_to_inscription_id.insert(&n, &inscription_id)?,
+              );
               sat = Some(Sat(n));
               break;
             }
@@ -255,17 +311,21 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
           }
         }
 
-        self.id_to_entry.insert(
+        self.undo_log.record_guard(
+          UndoTable::InscriptionIdToInscriptionEntry,
           &inscription_id,
-          &InscriptionEntry {
-            fee,
-            height: self.height,
-            number: self.next_number,
-            sat,
-            timestamp: self.timestamp,
-          }
-          .store(),
-        )?;
+          self.id_to_entry.insert(
+            &inscription_id,
+            &InscriptionEntry {
+              fee,
+              height: self.height,
+              number: self.next_number,
+              sat,
+              timestamp: self.timestamp,
+            }
+            .store(),
+          )?,
+        );
 
         self.next_number += 1;
       }
@@ -273,8 +333,16 @@ impl<'a, 'db, 'tx> InscriptionUpdater<'a, 'db, 'tx> {
 
     let new_satpoint = new_satpoint.store();
 
-    self.satpoint_to_id.insert(&new_satpoint, &inscription_id)?;
-    self.id_to_satpoint.insert(&inscription_id, &new_satpoint)?;
+    self.undo_log.record_guard(
+      UndoTable::SatpointToInscriptionId,
+      &new_satpoint,
+      self.satpoint_to_id.insert(&new_satpoint, &inscription_id)?,
+    );
+    self.undo_log.record_guard(
+      UndoTable::InscriptionIdToSatpoint,
+      &inscription_id,
+      self.id_to_satpoint.insert(&inscription_id, &new_satpoint)?,
+    );
 
     Ok(())
   }
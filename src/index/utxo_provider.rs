@@ -0,0 +1,233 @@
+use {super::*, bitcoincore_rpc::json::ScanTxOutRequest, clap::ValueEnum};
+
+#[derive(Default, ValueEnum, Copy, Clone, Debug, PartialEq)]
+pub enum UtxoProviderKind {
+  #[default]
+  Esplora,
+  BitcoinCore,
+  Index,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utxo {
+  pub outpoint: OutPoint,
+  pub value: Amount,
+  pub confirmed: bool,
+}
+
+/// Source of address UTXOs and transactions for the wallet builders.
+pub trait UtxoProvider {
+  fn get_address_utxos(&self, address: &str) -> Result<Vec<Utxo>>;
+
+  fn get_transaction(&self, txid: Txid) -> Result<Transaction>;
+
+  fn is_spent(&self, outpoint: OutPoint) -> Result<bool>;
+
+  fn get_output_value(&self, outpoint: OutPoint) -> Result<Amount> {
+    let tx = self.get_transaction(outpoint.txid)?;
+    let output = usize::try_from(outpoint.vout)
+      .ok()
+      .and_then(|vout| tx.output.get(vout))
+      .ok_or_else(|| BuildError::UtxoProvider(format!("output {outpoint} not found")))?;
+    Ok(Amount::from_sat(output.value))
+  }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ListUnspentStatusEntry {
+  pub confirmed: bool,
+  pub block_height: Option<usize>,
+  pub block_hash: Option<bitcoin::BlockHash>,
+  pub block_time: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize, Serialize)]
+pub struct ListUnspentResultEntry {
+  pub txid: bitcoin::Txid,
+  pub vout: u32,
+  pub status: ListUnspentStatusEntry,
+  #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+  pub value: Amount,
+}
+
+/// Esplora-compatible REST API, such as electrs or mempool.space.
+pub struct Esplora {
+  url: String,
+  timeout: Duration,
+}
+
+impl Esplora {
+  pub fn new(url: &str, timeout: Duration) -> Self {
+    Self {
+      url: url.trim_end_matches('/').into(),
+      timeout,
+    }
+  }
+
+  fn get(&self, path: &str) -> Result<String> {
+    reqwest::blocking::Client::builder()
+      .timeout(self.timeout)
+      .build()
+      .and_then(|client| client.get(format!("{}/{path}", self.url)).send())
+      .and_then(|response| response.error_for_status())
+      .and_then(|response| response.text())
+      .map_err(|err| BuildError::UtxoProvider(err.to_string()).into())
+  }
+}
+
+impl UtxoProvider for Esplora {
+  fn get_address_utxos(&self, address: &str) -> Result<Vec<Utxo>> {
+    let rep = self.get(&format!("address/{address}/utxo"))?;
+    Ok(
+      serde_json::from_str::<Vec<ListUnspentResultEntry>>(&rep)
+        .map_err(|_| BuildError::UtxoProvider(format!("Req utxo error:{}", rep)))?
+        .into_iter()
+        .map(|utxo| Utxo {
+          outpoint: OutPoint::new(utxo.txid, utxo.vout),
+          value: utxo.value,
+          confirmed: utxo.status.confirmed,
+        })
+        .collect(),
+    )
+  }
+
+  fn get_transaction(&self, txid: Txid) -> Result<Transaction> {
+    let rep = self.get(&format!("tx/{txid}/hex"))?;
+    let hex = Vec::from_hex(&rep)
+      .map_err(|_| BuildError::UtxoProvider(format!("Req transaction error:{}", rep)))?;
+    Ok(Decodable::consensus_decode(&mut hex.as_slice())?)
+  }
+
+  fn is_spent(&self, outpoint: OutPoint) -> Result<bool> {
+    #[derive(Deserialize)]
+    struct OutSpend {
+      spent: bool,
+    }
+
+    let rep = self.get(&format!("tx/{}/outspend/{}", outpoint.txid, outpoint.vout))?;
+    Ok(
+      serde_json::from_str::<OutSpend>(&rep)
+        .map_err(|_| BuildError::UtxoProvider(format!("Req outspend error:{}", rep)))?
+        .spent,
+    )
+  }
+}
+
+/// Bitcoin Core RPC. Address UTXOs come from `scantxoutset`, which only sees
+/// confirmed outputs, and transactions from `getrawtransaction`, which needs
+/// `-txindex` for transactions that are not in the mempool.
+pub struct BitcoinCore<'a> {
+  client: &'a Client,
+}
+
+impl<'a> BitcoinCore<'a> {
+  pub fn new(client: &'a Client) -> Self {
+    Self { client }
+  }
+}
+
+impl<'a> UtxoProvider for BitcoinCore<'a> {
+  fn get_address_utxos(&self, address: &str) -> Result<Vec<Utxo>> {
+    Ok(
+      self
+        .client
+        .scan_tx_out_set_blocking(&[ScanTxOutRequest::Single(format!("addr({address})"))])
+        .map_err(|err| BuildError::UtxoProvider(err.to_string()))?
+        .unspents
+        .into_iter()
+        .map(|utxo| Utxo {
+          outpoint: OutPoint::new(utxo.txid, utxo.vout),
+          value: utxo.amount,
+          confirmed: true,
+        })
+        .collect(),
+    )
+  }
+
+  fn get_transaction(&self, txid: Txid) -> Result<Transaction> {
+    Ok(
+      self
+        .client
+        .get_raw_transaction(&txid, None)
+        .map_err(|err| BuildError::UtxoProvider(err.to_string()))?,
+    )
+  }
+
+  fn is_spent(&self, outpoint: OutPoint) -> Result<bool> {
+    Ok(
+      self
+        .client
+        .get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+        .map_err(|err| BuildError::UtxoProvider(err.to_string()))?
+        .is_none(),
+    )
+  }
+}
+
+/// The local index, which needs `--index-addresses` to list address UTXOs,
+/// falling back to its Bitcoin Core node for transactions.
+pub struct LocalIndex<'a> {
+  index: &'a Index,
+}
+
+impl<'a> LocalIndex<'a> {
+  pub fn new(index: &'a Index) -> Self {
+    Self { index }
+  }
+}
+
+impl<'a> UtxoProvider for LocalIndex<'a> {
+  fn get_address_utxos(&self, address: &str) -> Result<Vec<Utxo>> {
+    let address = Address::from_str(address)
+      .map_err(|err| BuildError::UtxoProvider(format!("invalid address {address}: {err}")))?;
+    Ok(
+      self
+        .index
+        .get_address_unspent_outputs(&address)
+        .map_err(|err| BuildError::UtxoProvider(err.to_string()))?
+        .into_iter()
+        .map(|(outpoint, value)| Utxo {
+          outpoint,
+          value,
+          confirmed: true,
+        })
+        .collect(),
+    )
+  }
+
+  fn get_transaction(&self, txid: Txid) -> Result<Transaction> {
+    self
+      .index
+      .get_transaction(txid)?
+      .ok_or_else(|| BuildError::UtxoProvider(format!("transaction {txid} not found")).into())
+  }
+
+  fn is_spent(&self, outpoint: OutPoint) -> Result<bool> {
+    BitcoinCore::new(&self.index.client).is_spent(outpoint)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, std::net::TcpListener};
+
+  #[test]
+  fn esplora_requests_time_out() {
+    // Accepts connections but never answers, like a stalled upstream.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let start = Instant::now();
+    assert_matches!(
+      Esplora::new(&url, Duration::from_millis(200))
+        .get_transaction(txid(1))
+        .unwrap_err()
+        .downcast::<BuildError>()
+        .unwrap(),
+      BuildError::UtxoProvider(_)
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    drop(listener);
+  }
+}
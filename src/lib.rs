@@ -22,7 +22,6 @@ use {
     height::Height,
     index::{Index, List},
     inscription::Inscription,
-    inscription_id::InscriptionId,
     media::Media,
     options::Options,
     outgoing::Outgoing,
@@ -73,7 +72,14 @@ use {
 };
 
 pub use crate::{
-  fee_rate::FeeRate, object::Object, rarity::Rarity, sat::Sat, sat_point::SatPoint,
+  fee_policy::{FeeMethod, FeePolicy},
+  fee_rate::{FeeMode, FeeRate, RevealFeePolicy},
+  inscription_id::InscriptionId,
+  object::Object,
+  rarity::Rarity,
+  sat::Sat,
+  sat_point::SatPoint,
+  subcommand::wallet::build_error::BuildError,
   subcommand::wallet::transaction_builder::TransactionBuilder,
 };
 
@@ -102,6 +108,7 @@ mod decimal;
 mod degree;
 mod deserialize_from_str;
 mod epoch;
+mod fee_policy;
 mod fee_rate;
 mod height;
 pub mod index;
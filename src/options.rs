@@ -1,4 +1,9 @@
-use {super::*, bitcoincore_rpc::Auth};
+use {
+  super::*,
+  crate::index::utxo_provider::UtxoProviderKind,
+  bitcoin::util::bip32::ExtendedPrivKey,
+  bitcoincore_rpc::{jsonrpc, Auth},
+};
 
 #[derive(Clone, Default, Debug, Parser)]
 #[clap(group(
@@ -28,6 +33,20 @@ pub struct Options {
   pub cookie_file: Option<PathBuf>,
   #[clap(long, help = "Store index in <DATA_DIR>.")]
   pub data_dir: Option<PathBuf>,
+  #[clap(long, help = "Use Esplora API at <ESPLORA_URL>.")]
+  pub esplora_url: Option<String>,
+  #[clap(
+    long,
+    arg_enum,
+    default_value = "conservative",
+    help = "Estimate fee rates that aren't given in <FEE_MODE> mode."
+  )]
+  pub fee_mode: FeeMode,
+  #[clap(
+    long,
+    help = "Estimate fee rates that aren't given for confirmation within <FEE_TARGET> blocks. [default: 6]"
+  )]
+  pub fee_target: Option<u16>,
   #[clap(
     long,
     help = "Don't look for inscriptions below <FIRST_INSCRIPTION_HEIGHT>."
@@ -37,16 +56,38 @@ pub struct Options {
   pub height_limit: Option<u64>,
   #[clap(long, help = "Use index at <INDEX>.")]
   pub index: Option<PathBuf>,
+  #[clap(long, help = "Track unspent outputs of all addresses.")]
+  pub index_addresses: bool,
   #[clap(long, help = "Track location of all satoshis.")]
   pub index_sats: bool,
   #[clap(long, short, help = "Use regtest. Equivalent to `--chain regtest`.")]
   pub regtest: bool,
+  #[clap(
+    long,
+    default_value = "+0.02",
+    help = "Price reveal transactions from the commit fee rate with <REVEAL_FEE_POLICY>: `same`, `+<SATS>` sats/vB more, or `x<FACTOR>` times as much."
+  )]
+  pub reveal_fee_policy: RevealFeePolicy,
+  #[clap(long, help = "Derive reveal keys from <REVEAL_MASTER_KEY>.")]
+  pub reveal_master_key: Option<ExtendedPrivKey>,
+  #[clap(
+    long,
+    help = "Give up on Bitcoin Core RPC and Esplora requests after <RPC_TIMEOUT> seconds. [default: 15]"
+  )]
+  pub rpc_timeout: Option<u64>,
   #[clap(long, help = "Connect to Bitcoin Core RPC at <RPC_URL>.")]
   pub rpc_url: Option<String>,
   #[clap(long, short, help = "Use signet. Equivalent to `--chain signet`.")]
   pub signet: bool,
   #[clap(long, short, help = "Use testnet. Equivalent to `--chain testnet`.")]
   pub testnet: bool,
+  #[clap(
+    long,
+    arg_enum,
+    default_value = "esplora",
+    help = "Fetch address UTXOs and transactions from <UTXO_PROVIDER>."
+  )]
+  pub utxo_provider: UtxoProviderKind,
   #[clap(long, default_value = "ord", help = "Use wallet named <WALLET>.")]
   pub wallet: String,
 }
@@ -76,6 +117,21 @@ impl Options {
     }
   }
 
+  pub fn fee_target(&self) -> u16 {
+    self.fee_target.unwrap_or(6)
+  }
+
+  pub fn rpc_timeout(&self) -> Duration {
+    Duration::from_secs(self.rpc_timeout.unwrap_or(15))
+  }
+
+  pub fn esplora_url(&self) -> String {
+    self
+      .esplora_url
+      .clone()
+      .unwrap_or_else(|| self.chain().default_mempool_url().into())
+  }
+
   pub fn rpc_url(&self) -> String {
     self.rpc_url.clone().unwrap_or_else(|| {
       format!(
@@ -131,6 +187,10 @@ impl Options {
     }
   }
 
+  pub fn fee_policy(&self) -> Result<FeePolicy> {
+    Ok(self.load_config()?.fee_policy)
+  }
+
   fn format_bitcoin_core_version(version: usize) -> String {
     format!(
       "{}.{}.{}",
@@ -203,8 +263,16 @@ impl Options {
       );
     }
 
-    let client = Client::new(&rpc_url, auth)
+    let (user, pass) = auth.get_user_pass()?;
+    let mut transport = jsonrpc::simple_http::Builder::new()
+      .timeout(self.rpc_timeout())
+      .url(&rpc_url)
       .with_context(|| format!("failed to connect to Bitcoin Core RPC at {rpc_url}"))?;
+    if let Some(user) = user {
+      transport = transport.auth(user, pass);
+    }
+
+    let client = Client::from_jsonrpc(jsonrpc::Client::with_transport(transport.build()));
 
     let rpc_chain = match client.get_blockchain_info()?.chain.as_str() {
       "main" => Chain::Mainnet,
@@ -278,6 +346,70 @@ mod tests {
     );
   }
 
+  #[test]
+  fn rpc_timeout_defaults_to_fifteen_seconds() {
+    assert_eq!(
+      Arguments::try_parse_from(["ord", "index"])
+        .unwrap()
+        .options
+        .rpc_timeout(),
+      Duration::from_secs(15)
+    );
+    assert_eq!(
+      Arguments::try_parse_from(["ord", "--rpc-timeout=2", "index"])
+        .unwrap()
+        .options
+        .rpc_timeout(),
+      Duration::from_secs(2)
+    );
+  }
+
+  #[test]
+  fn esplora_url_defaults_to_chain_mempool_url() {
+    assert_eq!(
+      Arguments::try_parse_from(["ord", "--chain=signet", "index"])
+        .unwrap()
+        .options
+        .esplora_url(),
+      Chain::Signet.default_mempool_url()
+    );
+  }
+
+  #[test]
+  fn esplora_url_overrides_network() {
+    assert_eq!(
+      Arguments::try_parse_from([
+        "ord",
+        "--esplora-url=http://127.0.0.1:3000",
+        "--chain=signet",
+        "index"
+      ])
+      .unwrap()
+      .options
+      .esplora_url(),
+      "http://127.0.0.1:3000"
+    );
+  }
+
+  #[test]
+  fn utxo_provider() {
+    assert_eq!(
+      Arguments::try_parse_from(["ord", "index"])
+        .unwrap()
+        .options
+        .utxo_provider,
+      UtxoProviderKind::Esplora
+    );
+    assert_eq!(
+      Arguments::try_parse_from(["ord", "--utxo-provider=bitcoin-core", "index"])
+        .unwrap()
+        .options
+        .utxo_provider,
+      UtxoProviderKind::BitcoinCore
+    );
+    Arguments::try_parse_from(["ord", "--utxo-provider=foo", "index"]).unwrap_err();
+  }
+
   #[test]
   fn cookie_file_overrides_network() {
     assert_eq!(
@@ -1,8 +1,7 @@
-use bitcoin::Network;
-use clap::{Arg, Command};
+use clap::{value_parser, Arg, Command};
 use log::{error, info};
 use ord::chain::Chain;
-use ord::index::{Index, MysqlDatabase};
+use ord::index::{Index, MirrorStore, MysqlDatabase, SqliteDatabase};
 use ord::options::Options;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -15,6 +14,7 @@ fn main() {
       Arg::new("chain")
         .long("chain")
         .takes_value(true)
+        .value_parser(value_parser!(Chain))
         .default_value("test")
         .help("Sets the chain"),
     )
@@ -66,6 +66,19 @@ fn main() {
         .takes_value(true)
         .help("Mysql password."),
     )
+    .arg(
+      Arg::new("mysql-database")
+        .long("mysql-database")
+        .takes_value(true)
+        .help("Mirror inscriptions to mysql database <MYSQL_DATABASE>. Defaults to ord_<CHAIN>."),
+    )
+    .arg(
+      Arg::new("sqlite")
+        .long("sqlite")
+        .takes_value(true)
+        .conflicts_with("mysql-host")
+        .help("Mirror inscriptions to SQLite database at <SQLITE>."),
+    )
     .arg(
       Arg::new("target-height")
         .long("target-height")
@@ -74,24 +87,8 @@ fn main() {
     );
 
   let matches = args.get_matches();
-  let chain = matches
-    .get_one::<String>("chain")
-    .map(|s| s.as_str())
-    .unwrap();
-
-  let chain_argument = match chain {
-    "main" => Chain::Mainnet,
-    "regtest" => Chain::Regtest,
-    "signet" => Chain::Signet,
-    _ => Chain::Testnet,
-  };
-
-  let network = match chain {
-    "main" => Network::Bitcoin,
-    "regtest" => Network::Regtest,
-    "signet" => Network::Signet,
-    _ => Network::Testnet,
-  };
+  let chain_argument = *matches.get_one::<Chain>("chain").unwrap();
+  let network = chain_argument.network();
 
   let bitcoin_data_dir: Option<PathBuf> = matches
     .get_one::<String>("bitcoin-data-dir")
@@ -106,6 +103,8 @@ fn main() {
   let mysql_host = matches.get_one::<String>("mysql-host").cloned();
   let mysql_username = matches.get_one::<String>("mysql-username").cloned();
   let mysql_password = matches.get_one::<String>("mysql-password").cloned();
+  let mysql_database = matches.get_one::<String>("mysql-database").cloned();
+  let sqlite: Option<PathBuf> = matches.get_one::<String>("sqlite").map(|s| s.into());
 
   let rpc_url = matches.get_one::<String>("rpc-url").cloned();
 
@@ -123,29 +122,56 @@ fn main() {
     config_dir: None,
     cookie_file: None,
     data_dir,
+    esplora_url: None,
+    fee_mode: Default::default(),
+    fee_target: None,
     first_inscription_height: None,
     height_limit: None,
     index: None,
+    index_addresses: false,
     index_sats: false,
     regtest: false,
+    reveal_fee_policy: Default::default(),
+    reveal_master_key: None,
+    rpc_timeout: None,
     rpc_url,
     signet: false,
     testnet: false,
+    utxo_provider: Default::default(),
     wallet: "ord".to_string(),
   };
 
-  let database = if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
+  let database: Option<Arc<dyn MirrorStore>> = if let Some(sqlite) = sqlite {
+    info!("Use sqlite...");
+    match SqliteDatabase::open(&sqlite, network) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Sqlite open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
+  } else if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
     info!("Use redb...");
     None
   } else {
     info!("Use mysql...");
-    Some(Arc::new(
-      MysqlDatabase::new(mysql_host, mysql_username, mysql_password, network).unwrap(),
-    ))
+    match MysqlDatabase::new(
+      mysql_host,
+      mysql_username,
+      mysql_password,
+      mysql_database,
+      network,
+    ) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Mysql open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
   };
 
   let open_result = if let Some(db) = database {
-    Index::open_with_mysql(&options, db)
+    Index::open_with_mirror(&options, db)
   } else {
     Index::open(&options)
   };
@@ -1,30 +1,43 @@
+#![allow(clippy::result_large_err)]
+
 use anyhow::{anyhow, Error};
-use bitcoin::{Address, Amount, Network, OutPoint, Txid};
-use clap::{Arg, Command};
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin::util::key::PrivateKey;
+use bitcoin::{Address, AddressType, Amount, Network, OutPoint, Txid};
+use clap::{value_parser, Arg, Command, ValueEnum};
 use hyper::server::Server;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Method, Request, Response, StatusCode};
-use log::{error, info};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use log::{error, info, warn};
 use ord::chain::Chain;
-use ord::index::MysqlDatabase;
+use ord::index::utxo_provider::UtxoProviderKind;
+use ord::index::{Index, MirrorStore, MysqlDatabase, OrderStatus, SqliteDatabase};
 use ord::options::Options;
 use ord::outgoing::Outgoing;
+use ord::subcommand::wallet::broadcast::Broadcast;
+use ord::subcommand::wallet::bump::Bump;
 use ord::subcommand::wallet::cancel::Cancel;
 use ord::subcommand::wallet::mint::Mint;
 use ord::subcommand::wallet::mints;
+use ord::subcommand::wallet::recover::Recover;
 use ord::subcommand::wallet::transfer::Transfer;
-use ord::{FeeRate, TransactionBuilder};
+use ord::InscriptionId;
+use ord::{FeeMode, FeePolicy, FeeRate, RevealFeePolicy, TransactionBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use serde_json::Value;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 use tokio::task;
 
+mod rpc;
+mod snapshot;
+
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct MintParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: String,
   destination: Option<Address>,
@@ -32,88 +45,48 @@ struct MintParam {
   repeat: Option<u64>,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct MintData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: MintParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct TransferParam {
   source: Address,
   destination: Address,
   outgoing: String,
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   op_return: String,
   brc20_transfer: bool,
   addition_outgoing: Vec<String>,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct TransferData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: TransferParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct TransferWithFeeParam {
   source: Address,
   destination: Address,
   outgoing: String,
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   op_return: String,
   brc20_transfer: bool,
   addition_outgoing: Vec<String>,
   addition_fee: u64,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct TransferWithFeeData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: TransferWithFeeParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct MintsParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: Vec<String>,
   destination: Option<Address>,
   extension: Option<String>,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct MintsData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: MintsParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct CancelParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   inputs: Vec<String>,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct CancelData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: CancelParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct MintWithPostageParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: String,
   destination: Option<Address>,
@@ -122,17 +95,9 @@ struct MintWithPostageParam {
   target_postage: u64,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct MintWithPostageData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: MintWithPostageParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct MintsWithPostageParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: Vec<String>,
   destination: Option<Address>,
@@ -140,17 +105,9 @@ struct MintsWithPostageParam {
   target_postage: u64,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct MintsWithPostageData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: MintsWithPostageParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct ReMintParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: String,
   destination: Option<Address>,
@@ -160,17 +117,9 @@ struct ReMintParam {
   remint: String,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct ReMintData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: ReMintParam,
-}
-
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
 struct ReMintsParam {
-  fee_rate: f64,
+  fee_rate: Option<f64>,
   source: Address,
   content: Vec<String>,
   destination: Option<Address>,
@@ -180,11 +129,46 @@ struct ReMintsParam {
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct ReMintsData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: ReMintsParam,
+struct RecoverParam {
+  fee_rate: Option<f64>,
+  commit: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+struct BumpParam {
+  fee_rate: Option<f64>,
+  commit: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+struct EstimateMintParam {
+  fee_rate: Option<f64>,
+  address_type: String,
+  content_type: String,
+  content_size: usize,
+  destination: Option<Address>,
+  repeat: Option<u64>,
+  target_postage: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+struct EstimateTransferParam {
+  fee_rate: Option<f64>,
+  address_type: String,
+  destination: Option<Address>,
+  count: Option<usize>,
+  op_return: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+struct BroadcastParam {
+  commit_txid: String,
+  commit: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
+struct GetOrderParam {
+  commit: String,
 }
 
 #[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
@@ -192,536 +176,574 @@ struct IsWhitelistParam {
   source: String,
 }
 
-#[derive(Clone, PartialEq, Debug, Deserialize, Serialize)]
-struct IsWhitelistData {
-  jsonrpc: Option<String>,
-  id: Option<u32>,
-  method: String,
-  params: IsWhitelistParam,
+#[derive(Clone)]
+struct State {
+  snapshot: Arc<snapshot::Snapshot>,
+  service_address: Address,
+  fee_policy: Arc<FeePolicy>,
+  mirror: Option<Arc<dyn MirrorStore>>,
+  /// Reveal keys come from `--reveal-master-key`, so recovery keys are
+  /// derived again instead of stored.
+  derives_reveal_keys: bool,
+  blocking_permits: Arc<Semaphore>,
+}
+
+/// The fee rate a request asked for, if any. Without one the index estimates
+/// it with `estimatesmartfee`.
+fn fee_rate(fee_rate: Option<f64>) -> Result<Option<FeeRate>, Error> {
+  fee_rate.map(FeeRate::try_from).transpose()
+}
+
+fn is_whitelist(state: State, params: IsWhitelistParam) -> Result<Value, Error> {
+  info!("isWhitelist from {}", params.source);
+
+  let level = state
+    .mirror
+    .ok_or(anyhow!("not database"))?
+    .whitelist_level(&params.source);
+
+  Ok(serde_json::json!({
+    "is_whitelist": level.is_some(),
+    "discount": state.fee_policy.discount(level.as_deref()),
+    "level": level,
+  }))
+}
+
+/// Mint outputs never carry the recovery key, so a mint needs either a mirror
+/// to store it in or a master key to derive it again.
+fn keeps_recovery_keys(state: &State) -> Result<(), Error> {
+  if state.mirror.is_none() && !state.derives_reveal_keys {
+    return Err(anyhow!(
+      "Mint refused: recovery keys need a database or --reveal-master-key"
+    ));
+  }
+  Ok(())
+}
+
+fn mint(state: State, mint: Mint) -> Result<Value, Error> {
+  keeps_recovery_keys(&state)?;
+  let source = mint.source.clone();
+  let destination = mint.destination.clone().unwrap_or(source.clone());
+  info!("Mint from {source} to {destination}");
+
+  let output = mint.build(
+    &state.snapshot.index(),
+    Some(state.service_address),
+    &state.fee_policy,
+    state.mirror.clone(),
+  )?;
+  if let Some(mirror) = state.mirror {
+    if !state.derives_reveal_keys {
+      mirror.insert_recovery_keys(output.commit_txid, &source, &output.recovery_key)?;
+    }
+    mirror.insert_order(&output.order(source, destination))?;
+  }
+  Ok(serde_json::to_value(output)?)
+}
+
+fn mints(state: State, mint: mints::Mint) -> Result<Value, Error> {
+  keeps_recovery_keys(&state)?;
+  let source = mint.source.clone();
+  let destination = mint.destination.clone().unwrap_or(source.clone());
+  info!("Mints from {source} to {destination}");
+
+  let output = mint.build(
+    &state.snapshot.index(),
+    Some(state.service_address),
+    &state.fee_policy,
+    state.mirror.clone(),
+  )?;
+  if let Some(mirror) = state.mirror {
+    if !state.derives_reveal_keys {
+      mirror.insert_recovery_keys(output.commit_txid, &source, &output.recovery_key)?;
+    }
+    mirror.insert_order(&output.order(source, destination))?;
+  }
+  Ok(serde_json::to_value(output)?)
 }
 
-fn add_fee(service_fee: Option<Amount>, add: u64) -> Option<Amount> {
-  if let Some(fee) = service_fee {
-    Some(fee + Amount::from_sat(add))
+fn transfer(state: State, params: TransferWithFeeParam) -> Result<Value, Error> {
+  info!("Transfer from {} to {}", params.source, params.destination);
+
+  let op_return = if params.op_return.is_empty() {
+    None
   } else {
-    Some(Amount::from_sat(add))
+    Some(params.op_return)
+  };
+
+  let mut addition_outgoing = vec![];
+  for item in params.addition_outgoing.iter() {
+    addition_outgoing.push(Outgoing::from_str(item)?)
   }
+  let transfer = Transfer {
+    fee_rate: fee_rate(params.fee_rate)?,
+    destination: params.destination,
+    source: params.source,
+    outgoing: Outgoing::from_str(&params.outgoing)?,
+    op_return,
+    brc20_transfer: Some(params.brc20_transfer),
+    addition_outgoing,
+    addition_fee: Amount::from_sat(params.addition_fee),
+  };
+  let output = transfer.build(&state.snapshot.index(), state.mirror)?;
+  Ok(serde_json::to_value(output)?)
 }
 
-async fn _handle_request(
-  options: Options,
-  service_address: Address,
-  service_fee: u64,
-  mysql: Option<Arc<MysqlDatabase>>,
-  req: Request<Body>,
-) -> Result<Response<Body>, Error> {
-  let path: Vec<&str> = req.uri().path().split('/').skip(1).collect();
+fn cancel(state: State, params: CancelParam) -> Result<Value, Error> {
+  info!("Cancel from {}", params.source);
 
-  let service_fee = Some(Amount::from_sat(service_fee));
-  match (req.method(), path.first()) {
-    (&Method::GET, Some(&"query")) => match path.get(1) {
-      Some(&"inscription") => {
-        let addr = path.get(2).ok_or(anyhow!("not found address"))?;
-        let data = mysql
-          .ok_or(anyhow!("not database"))?
-          .get_inscription_by_address(&(*addr).to_owned())?;
-        let json_str = serde_json::to_string(&data).map_err(|_| anyhow!("serde fail"))?;
-        Ok(Response::new(Body::from(json_str)))
-      }
-      _ => Ok(Response::new(Body::from("get not recognize"))),
-    },
-    (&Method::POST, Some(&"isWhitelist")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: IsWhitelistData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source.clone();
-      info!("isWhitelist from {source}");
-
-      match form_data.method.as_str() {
-        "isWhitelist" => {
-          let data = mysql
-            .ok_or(anyhow!("not database"))?
-            .is_whitelist(&form_data.params.source);
-
-          let mut output = BTreeMap::new();
-          output.insert("is_whitelist", data);
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
+  let mut inputs: Vec<OutPoint> = vec![];
+  for item in &params.inputs {
+    inputs.push(OutPoint::from_str(item)?);
+  }
+
+  let cancel = Cancel {
+    fee_rate: fee_rate(params.fee_rate)?,
+    source: params.source,
+    inputs,
+  };
+  let output = cancel.build(
+    &state.snapshot.index(),
+    Some(state.service_address),
+    &state.fee_policy,
+    state.mirror,
+  )?;
+  Ok(serde_json::to_value(output)?)
+}
+
+fn recover(state: State, params: RecoverParam) -> Result<Value, Error> {
+  let commit = Txid::from_str(&params.commit)?;
+  info!("Recover commit {commit}");
+
+  let mirror = state.mirror.ok_or(anyhow!("not database"))?;
+  let index = state.snapshot.index();
+  let (source, recovery_key) =
+    recovery_keys(&index, mirror.as_ref(), commit, state.derives_reveal_keys)?;
+
+  let recover = Recover {
+    fee_rate: fee_rate(params.fee_rate)?,
+    commit,
+    destination: source,
+    recovery_key,
+  };
+  let output = recover.build(&index)?;
+  Ok(serde_json::to_value(output)?)
+}
+
+/// The stored recovery keys of `commit`. Orders built with a master key have
+/// none, so theirs are derived again from the stored reveals.
+fn recovery_keys(
+  index: &Index,
+  mirror: &dyn MirrorStore,
+  commit: Txid,
+  derives_reveal_keys: bool,
+) -> Result<(Address, Vec<PrivateKey>), Error> {
+  let err = match mirror.get_recovery_keys(commit) {
+    Ok((source, recovery_keys)) => {
+      let mut recovery_key = vec![];
+      for item in &recovery_keys {
+        recovery_key.push(PrivateKey::from_str(item)?);
       }
+      return Ok((source, recovery_key));
     }
-    (&Method::POST, Some(&"mint")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: MintData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("Mint from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "mint" => {
-          let mint = Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            repeat: form_data.params.repeat,
-            target_postage: TransactionBuilder::TARGET_POSTAGE,
-            remint: None,
-          };
-
-          let output = mint.build(options, Some(service_address), service_fee, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+    Err(err) => err,
+  };
+
+  match mirror.get_order(commit)? {
+    Some(order) if derives_reveal_keys && !order.reveals.is_empty() => {
+      let recovery_key = Bump::recovery_keys(index, commit, &order.reveals)?;
+      Ok((order.source, recovery_key))
     }
-    (&Method::POST, Some(&"mints")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: MintsData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("Mints from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "mints" => {
-          let mint = mints::Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            target_postage: TransactionBuilder::TARGET_POSTAGE,
-            remint: None,
-          };
-
-          let output = mint.build(options, Some(service_address), service_fee, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+    _ => Err(err),
+  }
+}
+
+fn bump(state: State, params: BumpParam) -> Result<Value, Error> {
+  let commit = Txid::from_str(&params.commit)?;
+  info!("Bump reveals of commit {commit}");
+
+  let mirror = state.mirror.ok_or(anyhow!("not database"))?;
+  let order = mirror
+    .get_order(commit)?
+    .ok_or_else(|| anyhow!("Order for commit {commit} not found"))?;
+
+  let bump = Bump {
+    commit,
+    fee_rate: fee_rate(params.fee_rate)?,
+    reveal: order.reveals.clone(),
+  };
+  let output = bump.build(&state.snapshot.index())?;
+  mirror.insert_order(&output.order(order))?;
+
+  Ok(serde_json::to_value(output)?)
+}
+
+fn estimate_mint(state: State, params: EstimateMintParam) -> Result<Value, Error> {
+  let index = state.snapshot.index();
+  let estimate = Mint::estimate(
+    index.options(),
+    AddressType::from_str(&params.address_type)?,
+    &params.content_type,
+    params.content_size,
+    params.repeat.unwrap_or(1),
+    params.destination,
+    params
+      .target_postage
+      .map_or(TransactionBuilder::TARGET_POSTAGE, Amount::from_sat),
+    index.fee_rate(fee_rate(params.fee_rate)?)?,
+    Some(state.service_address),
+    &state.fee_policy,
+  )?;
+  Ok(serde_json::to_value(estimate)?)
+}
+
+fn estimate_transfer(state: State, params: EstimateTransferParam) -> Result<Value, Error> {
+  let index = state.snapshot.index();
+  let estimate = Transfer::estimate(
+    index.options().chain().network(),
+    AddressType::from_str(&params.address_type)?,
+    params.destination,
+    params.count.unwrap_or(1),
+    params.op_return.filter(|op_return| !op_return.is_empty()),
+    index.fee_rate(fee_rate(params.fee_rate)?)?,
+  )?;
+  Ok(serde_json::to_value(estimate)?)
+}
+
+fn broadcast(state: State, params: BroadcastParam) -> Result<Value, Error> {
+  let commit_txid = Txid::from_str(&params.commit_txid)?;
+  info!("Broadcast commit {commit_txid}");
+
+  let order = state
+    .mirror
+    .ok_or(anyhow!("not database"))?
+    .get_order(commit_txid)?
+    .ok_or_else(|| anyhow!("Order for commit {commit_txid} not found"))?;
+
+  if order.reveals.is_empty() {
+    return Err(anyhow!(
+      "Order for commit {commit_txid} has no reveal transactions to broadcast"
+    ));
+  }
+  if order.reveals.len() != order.reveal_txids.len() {
+    return Err(anyhow!(
+      "Order for commit {commit_txid} stored reveals do not match reveal_txids"
+    ));
+  }
+
+  let broadcast = Broadcast {
+    commit: params.commit,
+    commit_txid: Some(order.commit_txid),
+    reveal: order.reveals,
+  };
+  let output = broadcast.build(&state.snapshot.index())?;
+  Ok(serde_json::to_value(output)?)
+}
+
+fn get_order(state: State, params: GetOrderParam) -> Result<Value, Error> {
+  let commit = Txid::from_str(&params.commit)?;
+  info!("Get order of commit {commit}");
+
+  let mirror = state.mirror.ok_or(anyhow!("not database"))?;
+  let status = order_status(&state.snapshot.index(), mirror.as_ref(), commit)?;
+  Ok(serde_json::to_value(status)?)
+}
+
+fn order_status(
+  index: &Index,
+  mirror: &dyn MirrorStore,
+  commit: Txid,
+) -> Result<OrderStatus, Error> {
+  let order = mirror
+    .get_order(commit)?
+    .ok_or_else(|| anyhow!("Order for commit {commit} not found"))?;
+  let state = index.get_order_state(&order)?;
+  Ok(OrderStatus { order, state })
+}
+
+fn dispatch(state: State, method: &str, params: Value) -> Result<Value, rpc::Error> {
+  let result = match method {
+    "isWhitelist" => is_whitelist(state, rpc::params(params)?),
+    "mint" => {
+      let params: MintParam = rpc::params(params)?;
+      mint(
+        state,
+        Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          repeat: params.repeat,
+          target_postage: TransactionBuilder::TARGET_POSTAGE,
+          remint: None,
+        },
+      )
     }
-    (&Method::POST, Some(&"transfer")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: TransferData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data.params.destination;
-      info!("Transfer from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "transfer" => {
-          let op_return = if form_data.params.op_return.is_empty() {
-            None
-          } else {
-            Some(form_data.params.op_return)
-          };
-
-          let mut addition_outgoing = vec![];
-          for item in form_data.params.addition_outgoing.iter() {
-            addition_outgoing.push(Outgoing::from_str(item)?)
-          }
-          let addition_fee = Amount::from_sat(0);
-          let transfer = Transfer {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination,
-            source,
-            outgoing: Outgoing::from_str(&form_data.params.outgoing)?,
-            op_return,
-            brc20_transfer: Some(form_data.params.brc20_transfer),
-            addition_outgoing,
-            addition_fee,
-          };
-          let output = transfer.build(options, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+    "mints" => {
+      let params: MintsParam = rpc::params(params)?;
+      mints(
+        state,
+        mints::Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          target_postage: TransactionBuilder::TARGET_POSTAGE,
+          remint: None,
+        },
+      )
     }
-    (&Method::POST, Some(&"transferWithFee")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: TransferWithFeeData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data.params.destination;
-      info!("TransferWithFee from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "transferWithFee" => {
-          let op_return = if form_data.params.op_return.is_empty() {
-            None
-          } else {
-            Some(form_data.params.op_return)
-          };
-
-          let mut addition_outgoing = vec![];
-          for item in form_data.params.addition_outgoing.iter() {
-            addition_outgoing.push(Outgoing::from_str(item)?)
-          }
-          let addition_fee = Amount::from_sat(form_data.params.addition_fee);
-          let transfer = Transfer {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination,
-            source,
-            outgoing: Outgoing::from_str(&form_data.params.outgoing)?,
-            op_return,
-            brc20_transfer: Some(form_data.params.brc20_transfer),
-            addition_outgoing,
-            addition_fee,
-          };
-          let output = transfer.build(options, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+    "mintWithPostage" => {
+      let params: MintWithPostageParam = rpc::params(params)?;
+      mint(
+        state,
+        Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          repeat: params.repeat,
+          target_postage: Amount::from_sat(params.target_postage),
+          remint: None,
+        },
+      )
     }
-    (&Method::POST, Some(&"cancel")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: CancelData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      info!("Cancel from {source}");
+    "mintsWithPostage" => {
+      let params: MintsWithPostageParam = rpc::params(params)?;
+      mints(
+        state,
+        mints::Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          target_postage: Amount::from_sat(params.target_postage),
+          remint: None,
+        },
+      )
+    }
+    "reMint" => {
+      let params: ReMintParam = rpc::params(params)?;
+      mint(
+        state,
+        Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          repeat: params.repeat,
+          target_postage: Amount::from_sat(params.target_postage),
+          remint: Some(Txid::from_str(&params.remint).map_err(rpc::Error::invalid_params)?),
+        },
+      )
+    }
+    "reMints" => {
+      let params: ReMintsParam = rpc::params(params)?;
+      mints(
+        state,
+        mints::Mint {
+          fee_rate: fee_rate(params.fee_rate).map_err(rpc::Error::invalid_params)?,
+          destination: params.destination,
+          source: params.source,
+          extension: params.extension,
+          content: params.content,
+          target_postage: Amount::from_sat(params.target_postage),
+          remint: Some(Txid::from_str(&params.remint).map_err(rpc::Error::invalid_params)?),
+        },
+      )
+    }
+    "transfer" => {
+      let params: TransferParam = rpc::params(params)?;
+      transfer(
+        state,
+        TransferWithFeeParam {
+          source: params.source,
+          destination: params.destination,
+          outgoing: params.outgoing,
+          fee_rate: params.fee_rate,
+          op_return: params.op_return,
+          brc20_transfer: params.brc20_transfer,
+          addition_outgoing: params.addition_outgoing,
+          addition_fee: 0,
+        },
+      )
+    }
+    "transferWithFee" => transfer(state, rpc::params(params)?),
+    "cancel" => cancel(state, rpc::params(params)?),
+    "recover" => recover(state, rpc::params(params)?),
+    "bump" => bump(state, rpc::params(params)?),
+    "getOrder" => get_order(state, rpc::params(params)?),
+    "broadcast" => broadcast(state, rpc::params(params)?),
+    "estimateMint" => estimate_mint(state, rpc::params(params)?),
+    "estimateTransfer" => estimate_transfer(state, rpc::params(params)?),
+    _ => return Err(rpc::Error::method_not_found(method)),
+  };
 
-      let mut inputs: Vec<OutPoint> = vec![];
-      for item in &form_data.params.inputs {
-        inputs.push(OutPoint::from_str(item)?);
-      }
+  result.map_err(|err| {
+    error!("{method} fail:{err:#}");
+    rpc::Error::server_error(err)
+  })
+}
 
-      match form_data.method.as_str() {
-        "cancel" => {
-          let cancel = Cancel {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            source,
-            inputs,
-          };
-          let output = cancel.build(
-            options,
-            Some(service_address),
-            Some(Amount::from_sat(1000)),
-            mysql,
-          )?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
-    }
-    (&Method::POST, Some(&"mintWithPostage")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: MintWithPostageData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("MintWithPostage from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "mintWithPostage" => {
-          let mint = Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            repeat: form_data.params.repeat,
-            target_postage: Amount::from_sat(form_data.params.target_postage),
-            remint: None,
-          };
-
-          let output = mint.build(options, Some(service_address), service_fee, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+#[derive(Debug)]
+enum BlockingError {
+  Overloaded,
+  Panic(task::JoinError),
+}
+
+/// Runs index, Bitcoin Core, UTXO provider and MySQL work on the blocking pool
+/// so it never stalls the async workers. At most `--max-blocking-requests`
+/// run at once. Later requests are turned away instead of queueing behind a
+/// slow upstream.
+async fn blocking<T: Send + 'static>(
+  permits: Arc<Semaphore>,
+  f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, BlockingError> {
+  let permit = permits
+    .try_acquire_owned()
+    .map_err(|_| BlockingError::Overloaded)?;
+
+  task::spawn_blocking(move || {
+    let _permit = permit;
+    f()
+  })
+  .await
+  .map_err(BlockingError::Panic)
+}
+
+async fn call(state: State, value: Value) -> Option<rpc::Response> {
+  let call = match rpc::Call::from_value(value) {
+    Ok(call) => call,
+    Err(response) => return Some(response),
+  };
+
+  let permits = state.blocking_permits.clone();
+  let method = call.method.clone();
+  let result = match blocking(permits, move || dispatch(state, &method, call.params)).await {
+    Ok(result) => result,
+    Err(BlockingError::Overloaded) => {
+      error!("{} rejected, server overloaded", call.method);
+      Err(rpc::Error::overloaded())
     }
-    (&Method::POST, Some(&"mintsWithPostage")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: MintsWithPostageData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("MintsWithPostage from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "mintsWithPostage" => {
-          let mint = mints::Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            target_postage: Amount::from_sat(form_data.params.target_postage),
-            remint: None,
-          };
-
-          let output = mint.build(options, Some(service_address), service_fee, mysql)?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
-      }
+    Err(BlockingError::Panic(panic)) => {
+      error!("{} panic:{panic}", call.method);
+      Err(rpc::Error::internal_error("Internal error"))
     }
-    (&Method::POST, Some(&"reMint")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: ReMintData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("reMint from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "reMint" => {
-          let mint = Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            repeat: form_data.params.repeat,
-            target_postage: Amount::from_sat(form_data.params.target_postage),
-            remint: Some(Txid::from_str(&form_data.params.remint)?),
-          };
-
-          let output = mint.build(
-            options,
-            Some(service_address),
-            service_fee,
-            mysql,
-          )?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
+  };
+
+  call.id.map(|id| rpc::Response::new(id, result))
+}
+
+fn json_response<T: Serialize>(value: &T) -> Result<Response<Body>, Error> {
+  Ok(
+    Response::builder()
+      .header(header::CONTENT_TYPE, "application/json")
+      .body(Body::from(serde_json::to_string(value)?))?,
+  )
+}
+
+async fn handle_rpc(state: State, req: Request<Body>) -> Result<Response<Body>, Error> {
+  let body = hyper::body::to_bytes(req.into_body()).await?;
+
+  match rpc::parse(&body) {
+    Err(response) => json_response(&response),
+    Ok(rpc::Payload::Single(value)) => match call(state, value).await {
+      Some(response) => json_response(&response),
+      None => Ok(Response::new(Body::empty())),
+    },
+    Ok(rpc::Payload::Batch(values)) => {
+      let mut responses = vec![];
+      for value in values {
+        if let Some(response) = call(state.clone(), value).await {
+          responses.push(response);
         }
       }
-    }
-    (&Method::POST, Some(&"reMints")) => {
-      let full_body = hyper::body::to_bytes(req.into_body()).await?;
-      let decoded_body = String::from_utf8_lossy(&full_body).to_string();
-
-      let form_data: ReMintsData = match serde_json::from_str(&decoded_body) {
-        Ok(data) => data,
-        Err(_) => {
-          return Ok(Response::new(Body::from("Invalid form data")));
-        }
-      };
-      let source = form_data.params.source;
-      let destination = form_data
-        .params
-        .destination
-        .clone()
-        .unwrap_or(source.clone());
-      info!("reMints from {source} to {destination}");
-
-      match form_data.method.as_str() {
-        "reMints" => {
-          let mint = mints::Mint {
-            fee_rate: FeeRate::try_from(form_data.params.fee_rate)?,
-            destination: form_data.params.destination,
-            source,
-            extension: form_data.params.extension,
-            content: form_data.params.content,
-            target_postage: Amount::from_sat(form_data.params.target_postage),
-            remint: Some(Txid::from_str(&form_data.params.remint)?),
-          };
-
-          let output = mint.build(
-            options,
-            Some(service_address),
-            service_fee,
-            mysql,
-          )?;
-          Ok(Response::new(Body::from(serde_json::to_string(&output)?)))
-        }
-        _ => {
-          let response = Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Body::from("Method not found"))
-            .unwrap();
-          Ok(response)
-        }
+      if responses.is_empty() {
+        Ok(Response::new(Body::empty()))
+      } else {
+        json_response(&responses)
       }
     }
-    _ => {
-      // 处理其他请求
-      let response = Response::builder()
-        .status(StatusCode::NOT_FOUND)
-        .body(Body::empty())
-        .unwrap();
-      Ok(response)
-    }
   }
 }
 
-async fn handle_request(
-  options: Options,
-  service_address: Address,
-  service_fee: u64,
-  mysql: Option<Arc<MysqlDatabase>>,
-  req: Request<Body>,
+/// Answers a `GET /query/...` request from the mirror.
+async fn query<T: Serialize + Send + 'static>(
+  state: State,
+  f: impl FnOnce(&dyn MirrorStore) -> Result<T, Error> + Send + 'static,
 ) -> Result<Response<Body>, Error> {
-  let result = task::spawn(async move {
-    match _handle_request(options, service_address, service_fee, mysql, req).await {
-      Ok(v) => Ok(v),
-      Err(e) => {
-        error!("Req fail:{e}");
-        let format_error = format!("{}", e).to_lowercase();
-        let final_error = if format_error.contains("database") {
-          String::from("API requests are too frequent, please try again later")
-        } else {
-          format!("{}", e)
-        };
-        Ok(
+  let data = match state.mirror.clone() {
+    Some(mirror) => match blocking(state.blocking_permits, move || f(mirror.as_ref())).await {
+      Ok(data) => data,
+      Err(BlockingError::Overloaded) => {
+        error!("Query rejected, server overloaded");
+        return Ok(
           Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body(Body::from(final_error))
-            .unwrap(),
-        )
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::from("Server overloaded, retry later"))?,
+        );
       }
-    }
-  })
-    .await;
-  match result {
-    Ok(response) => response,
-    Err(panic) => {
-      error!("Req panic:{panic}");
+      Err(BlockingError::Panic(panic)) => Err(anyhow!("Query panic:{panic}")),
+    },
+    None => Err(anyhow!("not database")),
+  };
+  match data {
+    Ok(data) => json_response(&data),
+    Err(err) => {
+      error!("Query fail:{err}");
       Ok(
         Response::builder()
           .status(StatusCode::BAD_REQUEST)
-          .body(Body::from(
-            "API requests are too frequent, please try again later",
-          ))
-          .unwrap(),
+          .body(Body::from(err.to_string()))?,
       )
     }
   }
 }
 
+async fn handle_request(state: State, req: Request<Body>) -> Result<Response<Body>, Error> {
+  let path: Vec<&str> = req.uri().path().split('/').skip(1).collect();
+
+  match (req.method(), path.as_slice()) {
+    (&Method::GET, ["query", "inscription", addr]) => {
+      let addr = (*addr).to_owned();
+      query(state, move |mirror| {
+        mirror.get_inscription_by_address(&addr)
+      })
+      .await
+    }
+    (&Method::GET, ["query", "provenance", inscription_id]) => {
+      let inscription_id = (*inscription_id).to_owned();
+      query(state, move |mirror| {
+        mirror.get_inscription_transfers(InscriptionId::from_str(&inscription_id)?)
+      })
+      .await
+    }
+    (&Method::GET, ["query", "activity", addr]) => {
+      let addr = (*addr).to_owned();
+      query(state, move |mirror| mirror.get_address_transfers(&addr)).await
+    }
+    (&Method::GET, ["query", "order", commit]) => {
+      let commit = (*commit).to_owned();
+      let snapshot = state.snapshot.clone();
+      query(state, move |mirror| {
+        order_status(&snapshot.index(), mirror, Txid::from_str(&commit)?)
+      })
+      .await
+    }
+    (&Method::POST, [""]) => handle_rpc(state, req).await,
+    _ => Ok(
+      Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Body::empty())?,
+    ),
+  }
+}
+
 #[tokio::main]
 async fn main() {
   std::env::set_var("RUST_LOG", "info");
@@ -731,6 +753,7 @@ async fn main() {
       Arg::new("chain")
         .long("chain")
         .takes_value(true)
+        .value_parser(value_parser!(Chain))
         .default_value("test")
         .help("Sets the chain"),
     )
@@ -740,12 +763,17 @@ async fn main() {
         .takes_value(true)
         .help("Sets the service address"),
     )
+    .arg(
+      Arg::new("config")
+        .long("config")
+        .takes_value(true)
+        .help("Load configuration, including the service fee policy, from <CONFIG>."),
+    )
     .arg(
       Arg::new("service-fee")
         .long("service-fee")
         .takes_value(true)
-        .default_value("3000")
-        .help("Sets the service fee"),
+        .help("Deprecated, set `per_item` of `mint` and `mints` in the `fee_policy` config instead. Overrides it with <SERVICE_FEE>."),
     )
     .arg(
       Arg::new("bitcoin-data-dir")
@@ -777,6 +805,13 @@ async fn main() {
         .takes_value(true)
         .help("Connect to Bitcoin Core RPC at <RPC_URL>."),
     )
+    .arg(
+      Arg::new("rpc-timeout")
+        .long("rpc-timeout")
+        .takes_value(true)
+        .default_value("15")
+        .help("Give up on Bitcoin Core RPC and Esplora requests after <RPC_TIMEOUT> seconds."),
+    )
     .arg(
       Arg::new("ip")
         .long("ip")
@@ -801,13 +836,71 @@ async fn main() {
         .long("mysql-password")
         .takes_value(true)
         .help("Mysql password."),
+    )
+    .arg(
+      Arg::new("mysql-database")
+        .long("mysql-database")
+        .takes_value(true)
+        .help("Mirror inscriptions to mysql database <MYSQL_DATABASE>. Defaults to ord_<CHAIN>."),
+    )
+    .arg(
+      Arg::new("sqlite")
+        .long("sqlite")
+        .takes_value(true)
+        .conflicts_with("mysql-host")
+        .help("Mirror inscriptions to SQLite database at <SQLITE>."),
+    )
+    .arg(
+      Arg::new("utxo-provider")
+        .long("utxo-provider")
+        .takes_value(true)
+        .default_value("esplora")
+        .help("Fetch UTXOs from <UTXO_PROVIDER>: esplora, bitcoin-core or index."),
+    )
+    .arg(
+      Arg::new("esplora-url")
+        .long("esplora-url")
+        .takes_value(true)
+        .help("Use Esplora API at <ESPLORA_URL>."),
+    )
+    .arg(
+      Arg::new("reveal-master-key")
+        .long("reveal-master-key")
+        .takes_value(true)
+        .help("Derive reveal keys from <REVEAL_MASTER_KEY>."),
+    )
+    .arg(
+      Arg::new("fee-target")
+        .long("fee-target")
+        .takes_value(true)
+        .default_value("6")
+        .help("Estimate fee rates that aren't given for confirmation within <FEE_TARGET> blocks."),
+    )
+    .arg(
+      Arg::new("fee-mode")
+        .long("fee-mode")
+        .takes_value(true)
+        .default_value("conservative")
+        .help("Estimate fee rates that aren't given in <FEE_MODE> mode: economical or conservative."),
+    )
+    .arg(
+      Arg::new("reveal-fee-policy")
+        .long("reveal-fee-policy")
+        .takes_value(true)
+        .default_value("+0.02")
+        .help("Price reveal transactions from the commit fee rate with <REVEAL_FEE_POLICY>: `same`, `+<SATS>` sats/vB more, or `x<FACTOR>` times as much."),
+    )
+    .arg(
+      Arg::new("max-blocking-requests")
+        .long("max-blocking-requests")
+        .takes_value(true)
+        .default_value("16")
+        .help("Run at most <MAX_BLOCKING_REQUESTS> requests against the index, Bitcoin Core, UTXO provider and MySQL at once, and turn away the rest."),
     );
 
   let matches = args.get_matches();
-  let chain = matches
-    .get_one::<String>("chain")
-    .map(|s| s.as_str())
-    .unwrap();
+  let chain_argument = *matches.get_one::<Chain>("chain").unwrap();
+  let network = chain_argument.network();
   let service_address: Address = Address::from_str(
     matches
       .get_one::<String>("service-address")
@@ -816,20 +909,6 @@ async fn main() {
   )
     .unwrap();
 
-  let chain_argument = match chain {
-    "main" => Chain::Mainnet,
-    "regtest" => Chain::Regtest,
-    "signet" => Chain::Signet,
-    _ => Chain::Testnet,
-  };
-
-  let network = match chain {
-    "main" => Network::Bitcoin,
-    "regtest" => Network::Regtest,
-    "signet" => Network::Signet,
-    _ => Network::Testnet,
-  };
-
   let bitcoin_data_dir: Option<PathBuf> = matches
     .get_one::<String>("bitcoin-data-dir")
     .map(|s| s.into());
@@ -842,24 +921,99 @@ async fn main() {
 
   let rpc_url = matches.get_one::<String>("rpc-url").cloned();
 
+  let rpc_timeout: u64 = matches
+    .get_one::<String>("rpc-timeout")
+    .map(|s| s.parse().unwrap())
+    .unwrap();
+
   let ip = matches.get_one::<String>("ip").cloned().unwrap();
 
-  let service_fee: u64 = matches
+  let config: Option<PathBuf> = matches.get_one::<String>("config").map(|s| s.into());
+
+  let service_fee: Option<u64> = matches
     .get_one::<String>("service-fee")
-    .map(|s| s.parse().unwrap_or(3000))
+    .map(|s| s.parse().unwrap());
+
+  let utxo_provider = matches
+    .get_one::<String>("utxo-provider")
+    .map(|s| UtxoProviderKind::from_str(s, true).unwrap())
+    .unwrap();
+
+  let esplora_url = matches.get_one::<String>("esplora-url").cloned();
+
+  let max_blocking_requests: usize = matches
+    .get_one::<String>("max-blocking-requests")
+    .map(|s| s.parse().unwrap())
+    .unwrap();
+
+  let fee_target: u16 = matches
+    .get_one::<String>("fee-target")
+    .map(|s| s.parse().unwrap())
+    .unwrap();
+
+  let fee_mode = matches
+    .get_one::<String>("fee-mode")
+    .map(|s| FeeMode::from_str(s, true).unwrap())
     .unwrap();
 
+  let reveal_fee_policy = matches
+    .get_one::<String>("reveal-fee-policy")
+    .map(|s| RevealFeePolicy::from_str(s).unwrap())
+    .unwrap();
+
+  let reveal_master_key = match matches
+    .get_one::<String>("reveal-master-key")
+    .map(|s| ExtendedPrivKey::from_str(s))
+    .transpose()
+  {
+    Ok(reveal_master_key) => reveal_master_key,
+    Err(e) => {
+      error!("Reveal master key parse error:{e}");
+      std::process::exit(1);
+    }
+  };
+
+  if let Some(reveal_master_key) = reveal_master_key {
+    if (reveal_master_key.network == Network::Bitcoin) != (network == Network::Bitcoin) {
+      error!("Reveal master key is not valid for {network}");
+      std::process::exit(1);
+    }
+  } else {
+    info!("No reveal master key, reveal keys are random...");
+  }
+
   let mysql_host = matches.get_one::<String>("mysql-host").cloned();
   let mysql_username = matches.get_one::<String>("mysql-username").cloned();
   let mysql_password = matches.get_one::<String>("mysql-password").cloned();
-  let database = if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
+  let mysql_database = matches.get_one::<String>("mysql-database").cloned();
+  let sqlite: Option<PathBuf> = matches.get_one::<String>("sqlite").map(|s| s.into());
+  let database: Option<Arc<dyn MirrorStore>> = if let Some(sqlite) = sqlite {
+    info!("Use sqlite...");
+    match SqliteDatabase::open(&sqlite, network) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Sqlite open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
+  } else if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
     info!("Use redb...");
     None
   } else {
     info!("Use mysql...");
-    Some(Arc::new(
-      MysqlDatabase::new(mysql_host, mysql_username, mysql_password, network).unwrap(),
-    ))
+    match MysqlDatabase::new(
+      mysql_host,
+      mysql_username,
+      mysql_password,
+      mysql_database,
+      network,
+    ) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Mysql open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
   };
 
   let options = Options {
@@ -867,43 +1021,59 @@ async fn main() {
     bitcoin_rpc_pass,
     bitcoin_rpc_user,
     chain_argument,
-    config: None,
+    config,
     config_dir: None,
     cookie_file: None,
     data_dir,
+    esplora_url,
+    fee_mode,
+    fee_target: Some(fee_target),
     first_inscription_height: None,
     height_limit: None,
     index: None,
+    index_addresses: utxo_provider == UtxoProviderKind::Index,
     index_sats: false,
     regtest: false,
+    reveal_fee_policy,
+    reveal_master_key,
+    rpc_timeout: Some(rpc_timeout),
     rpc_url,
     signet: false,
     testnet: false,
+    utxo_provider,
     wallet: "ord".to_string(),
   };
 
+  let mut fee_policy = match options.fee_policy() {
+    Ok(fee_policy) => fee_policy,
+    Err(e) => {
+      error!("Fee policy load error:{e:#}");
+      std::process::exit(1);
+    }
+  };
+
+  if let Some(service_fee) = service_fee {
+    warn!("--service-fee is deprecated, set `per_item` of `mint` and `mints` in the `fee_policy` config instead");
+    fee_policy.mint.per_item = service_fee;
+    fee_policy.mints.per_item = service_fee;
+  }
+
   let addr = SocketAddr::new(ip.as_str().parse().unwrap(), 3080);
   info!(
     "Server running at http://{}, network:{:?}, service:{:?}",
-    addr,
-    chain_argument,
-    service_address.clone()
+    addr, chain_argument, service_address
   );
+  let state = State {
+    snapshot: Arc::new(snapshot::Snapshot::open(options).unwrap()),
+    service_address,
+    fee_policy: Arc::new(fee_policy),
+    mirror: database,
+    derives_reveal_keys: reveal_master_key.is_some(),
+    blocking_permits: Arc::new(Semaphore::new(max_blocking_requests)),
+  };
   let make_svc = make_service_fn(move |_conn| {
-    let options = options.clone();
-    let service_address = service_address.clone();
-    let database = database.clone();
-    async move {
-      Ok::<_, Error>(service_fn(move |req| {
-        handle_request(
-          options.clone(),
-          service_address.clone(),
-          service_fee,
-          database.clone(),
-          req,
-        )
-      }))
-    }
+    let state = state.clone();
+    async move { Ok::<_, Error>(service_fn(move |req| handle_request(state.clone(), req))) }
   });
 
   let server = Server::bind(&addr).serve(make_svc);
@@ -912,3 +1082,200 @@ async fn main() {
     error!("Server error: {}", e);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use clap::Parser;
+  use serde_json::json;
+  use tempfile::TempDir;
+  use tokio::runtime::Runtime;
+
+  struct Context {
+    rpc_server: test_bitcoincore_rpc::Handle,
+    tempdir: TempDir,
+  }
+
+  impl Context {
+    fn new() -> Self {
+      let rpc_server = test_bitcoincore_rpc::builder()
+        .network(Network::Regtest)
+        .build();
+      let tempdir = TempDir::new().unwrap();
+      std::fs::write(tempdir.path().join("cookie"), "username:password").unwrap();
+      Self {
+        rpc_server,
+        tempdir,
+      }
+    }
+
+    fn state(&self, mirror: Option<Arc<dyn MirrorStore>>, derives_reveal_keys: bool) -> State {
+      let options = Options::try_parse_from([
+        "ord".as_ref(),
+        "--rpc-url".as_ref(),
+        self.rpc_server.url().as_ref(),
+        "--data-dir".as_ref(),
+        self.tempdir.path().as_os_str(),
+        "--cookie-file".as_ref(),
+        self.tempdir.path().join("cookie").as_os_str(),
+        "--regtest".as_ref(),
+      ])
+      .unwrap();
+      Index::open(&options).unwrap().update().unwrap();
+
+      State {
+        snapshot: Arc::new(snapshot::Snapshot::open(options).unwrap()),
+        service_address: Address::from_str("bcrt1qs758ursh4q9z627kt3pp5yysm78ddny6txaqgw").unwrap(),
+        fee_policy: Arc::new(FeePolicy::default()),
+        mirror,
+        derives_reveal_keys,
+        blocking_permits: Arc::new(Semaphore::new(1)),
+      }
+    }
+  }
+
+  fn order(commit: Txid, source: &Address, reveals: Vec<String>) -> ord::index::Order {
+    ord::index::Order {
+      commit_txid: commit,
+      reveal_txids: vec![commit],
+      reveals,
+      inputs: Vec::new(),
+      source: source.clone(),
+      destination: source.clone(),
+      service_fee: 0,
+      satpoint_fee: 0,
+      network_fee: 0,
+      commit_fee: 0,
+      created_at: 0,
+    }
+  }
+
+  #[test]
+  fn broadcast_tells_legacy_orders_from_mismatched_reveals() {
+    let context = Context::new();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&context.tempdir.path().join("mirror.db"), Network::Regtest).unwrap(),
+    );
+    let state = context.state(Some(mirror.clone()), false);
+    let source = state.service_address.clone();
+
+    let legacy =
+      Txid::from_str("1111111111111111111111111111111111111111111111111111111111111111").unwrap();
+    mirror
+      .insert_order(&order(legacy, &source, Vec::new()))
+      .unwrap();
+
+    let mismatched =
+      Txid::from_str("2222222222222222222222222222222222222222222222222222222222222222").unwrap();
+    mirror
+      .insert_order(&order(mismatched, &source, vec!["00".into(), "00".into()]))
+      .unwrap();
+
+    let error = |commit: Txid| {
+      dispatch(
+        state.clone(),
+        "broadcast",
+        json!({"commit_txid": commit.to_string(), "commit": ""}),
+      )
+      .unwrap_err()
+      .message
+    };
+
+    assert_eq!(
+      error(legacy),
+      format!("Order for commit {legacy} has no reveal transactions to broadcast")
+    );
+    assert_eq!(
+      error(mismatched),
+      format!("Order for commit {mismatched} stored reveals do not match reveal_txids")
+    );
+  }
+
+  #[test]
+  fn recover_prefers_stored_keys_with_master_key() {
+    let context = Context::new();
+    let mirror = Arc::new(
+      SqliteDatabase::open(&context.tempdir.path().join("mirror.db"), Network::Regtest).unwrap(),
+    );
+    let state = context.state(Some(mirror.clone()), true);
+
+    let commit =
+      Txid::from_str("1111111111111111111111111111111111111111111111111111111111111111").unwrap();
+    let source = state.service_address.clone();
+    let recovery_key = PrivateKey::new(
+      bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+      Network::Regtest,
+    );
+
+    // Built before the master key was set: random keys, stored with the order.
+    mirror
+      .insert_recovery_keys(commit, &source, &[recovery_key.to_wif()])
+      .unwrap();
+    mirror
+      .insert_order(&order(commit, &source, vec!["00".into()]))
+      .unwrap();
+
+    let (stored_source, recovery_keys) =
+      recovery_keys(&state.snapshot.index(), mirror.as_ref(), commit, true).unwrap();
+    assert_eq!(stored_source, source);
+    assert_eq!(
+      recovery_keys
+        .into_iter()
+        .map(PrivateKey::to_wif)
+        .collect::<Vec<_>>(),
+      [recovery_key.to_wif()],
+    );
+  }
+
+  #[test]
+  fn mint_without_database_or_master_key_is_refused() {
+    let context = Context::new();
+    let state = context.state(None, false);
+    let source = "bcrt1qs758ursh4q9z627kt3pp5yysm78ddny6txaqgw";
+
+    for (method, content) in [("mint", json!("foo")), ("mints", json!(["foo"]))] {
+      let error = dispatch(
+        state.clone(),
+        method,
+        json!({"source": source, "content": content}),
+      )
+      .unwrap_err();
+      assert_eq!(error.code, rpc::SERVER_ERROR);
+      assert!(
+        error.message.contains("recovery keys need a database"),
+        "{}",
+        error.message
+      );
+    }
+  }
+
+  #[test]
+  fn blocking_rejects_when_permits_are_taken() {
+    Runtime::new().unwrap().block_on(async {
+      let permits = Arc::new(Semaphore::new(1));
+
+      let held = permits.clone().try_acquire_owned().unwrap();
+      assert!(matches!(
+        blocking(permits.clone(), || 1).await,
+        Err(BlockingError::Overloaded)
+      ));
+
+      drop(held);
+      assert_eq!(blocking(permits.clone(), || 1).await.unwrap(), 1);
+      assert_eq!(permits.available_permits(), 1);
+    });
+  }
+
+  #[test]
+  fn blocking_reports_panics() {
+    Runtime::new().unwrap().block_on(async {
+      let permits = Arc::new(Semaphore::new(1));
+
+      assert!(matches!(
+        blocking(permits.clone(), || panic!("boom")).await,
+        Err(BlockingError::Panic(_))
+      ));
+      assert_eq!(permits.available_permits(), 1);
+    });
+  }
+}
@@ -0,0 +1,368 @@
+use ord::BuildError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const SERVER_ERROR: i64 = -32000;
+pub const INSUFFICIENT_FUNDS: i64 = -32001;
+pub const DUST: i64 = -32002;
+pub const UNSUPPORTED_ADDRESS_TYPE: i64 = -32003;
+pub const INSCRIPTION_NOT_FOUND: i64 = -32004;
+pub const ALREADY_INSCRIBED: i64 = -32005;
+pub const UTXO_PROVIDER: i64 = -32006;
+pub const REJECTED: i64 = -32007;
+pub const OVERLOADED: i64 = -32008;
+pub const PARTIALLY_BROADCAST: i64 = -32009;
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Error {
+  pub code: i64,
+  pub message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub data: Option<Value>,
+}
+
+impl Error {
+  pub fn new(code: i64, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+      data: None,
+    }
+  }
+
+  pub fn parse_error(err: serde_json::Error) -> Self {
+    Self::new(PARSE_ERROR, format!("Parse error: {err}"))
+  }
+
+  pub fn invalid_request(message: &str) -> Self {
+    Self::new(INVALID_REQUEST, format!("Invalid request: {message}"))
+  }
+
+  pub fn method_not_found(method: &str) -> Self {
+    Self::new(METHOD_NOT_FOUND, format!("Method not found: {method}"))
+  }
+
+  pub fn invalid_params(err: impl std::fmt::Display) -> Self {
+    Self::new(INVALID_PARAMS, format!("Invalid params: {err}"))
+  }
+
+  pub fn internal_error(message: impl Into<String>) -> Self {
+    Self::new(INTERNAL_ERROR, message)
+  }
+
+  pub fn overloaded() -> Self {
+    Self::new(OVERLOADED, "Server overloaded, retry later")
+  }
+
+  /// Only the outermost message of `err` reaches the client, the context
+  /// beneath it may name queries, URLs or paths. Callers log the full chain.
+  pub fn server_error(err: anyhow::Error) -> Self {
+    let Some(build_error) = err.downcast_ref::<BuildError>() else {
+      return Self::new(SERVER_ERROR, err.to_string());
+    };
+
+    let (code, data) = match build_error {
+      BuildError::AlreadyInscribed {
+        satpoint,
+        inscription_id,
+      } => (
+        ALREADY_INSCRIBED,
+        json!({
+          "satpoint": satpoint.to_string(),
+          "inscription_id": inscription_id.to_string(),
+        }),
+      ),
+      BuildError::Dust {
+        output_value,
+        dust_value,
+      } => (
+        DUST,
+        json!({
+          "output_value": output_value.to_sat(),
+          "dust_value": dust_value.to_sat(),
+        }),
+      ),
+      BuildError::InscriptionNotFound(inscription_id) => (
+        INSCRIPTION_NOT_FOUND,
+        json!({ "inscription_id": inscription_id.to_string() }),
+      ),
+      BuildError::InsufficientFunds {
+        required,
+        available,
+      } => (
+        INSUFFICIENT_FUNDS,
+        json!({
+          "required": required.to_sat(),
+          "available": available.to_sat(),
+        }),
+      ),
+      BuildError::PartiallyBroadcast {
+        commit,
+        sent,
+        failed,
+        reason,
+      } => (
+        PARTIALLY_BROADCAST,
+        json!({
+          "commit": commit.to_string(),
+          "reveal": sent.iter().map(|txid| txid.to_string()).collect::<Vec<String>>(),
+          "failed": failed.to_string(),
+          "reason": reason,
+        }),
+      ),
+      BuildError::Rejected { txid, reason } => (
+        REJECTED,
+        json!({
+          "txid": txid.to_string(),
+          "reason": reason,
+        }),
+      ),
+      BuildError::UnsupportedAddressType(address) => (
+        UNSUPPORTED_ADDRESS_TYPE,
+        json!({ "address": address.to_string() }),
+      ),
+      BuildError::UtxoProvider(err) => (UTXO_PROVIDER, json!({ "reason": err })),
+    };
+
+    Self {
+      code,
+      message: build_error.to_string(),
+      data: Some(data),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Response {
+  pub jsonrpc: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<Error>,
+  pub id: Value,
+}
+
+impl Response {
+  pub fn new(id: Value, result: Result<Value, Error>) -> Self {
+    match result {
+      Ok(result) => Self {
+        jsonrpc: "2.0",
+        result: Some(result),
+        error: None,
+        id,
+      },
+      Err(error) => Self::error(id, error),
+    }
+  }
+
+  pub fn error(id: Value, error: Error) -> Self {
+    Self {
+      jsonrpc: "2.0",
+      result: None,
+      error: Some(error),
+      id,
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Call {
+  /// `None` for notifications, which get no response.
+  pub id: Option<Value>,
+  pub method: String,
+  pub params: Value,
+}
+
+impl Call {
+  pub fn from_value(value: Value) -> Result<Self, Response> {
+    let Value::Object(mut object) = value else {
+      return Err(Response::error(
+        Value::Null,
+        Error::invalid_request("expected object"),
+      ));
+    };
+
+    let id = match object.remove("id") {
+      Some(id @ (Value::Null | Value::Number(_) | Value::String(_))) => Some(id),
+      Some(_) => {
+        return Err(Response::error(
+          Value::Null,
+          Error::invalid_request("id must be a string, number or null"),
+        ))
+      }
+      None => None,
+    };
+
+    let error_id = id.clone().unwrap_or(Value::Null);
+
+    if object.get("jsonrpc") != Some(&Value::String("2.0".into())) {
+      return Err(Response::error(
+        error_id,
+        Error::invalid_request("jsonrpc must be \"2.0\""),
+      ));
+    }
+
+    let Some(Value::String(method)) = object.remove("method") else {
+      return Err(Response::error(
+        error_id,
+        Error::invalid_request("method must be a string"),
+      ));
+    };
+
+    let params = match object.remove("params") {
+      Some(params @ (Value::Object(_) | Value::Array(_))) => params,
+      Some(_) => {
+        return Err(Response::error(
+          error_id,
+          Error::invalid_request("params must be an object or array"),
+        ))
+      }
+      None => Value::Null,
+    };
+
+    Ok(Self { id, method, params })
+  }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Payload {
+  Single(Value),
+  Batch(Vec<Value>),
+}
+
+pub fn parse(body: &[u8]) -> Result<Payload, Response> {
+  match serde_json::from_slice(body) {
+    Ok(Value::Array(values)) if values.is_empty() => Err(Response::error(
+      Value::Null,
+      Error::invalid_request("empty batch"),
+    )),
+    Ok(Value::Array(values)) => Ok(Payload::Batch(values)),
+    Ok(value) => Ok(Payload::Single(value)),
+    Err(err) => Err(Response::error(Value::Null, Error::parse_error(err))),
+  }
+}
+
+pub fn params<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+  serde_json::from_value(params).map_err(Error::invalid_params)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_error() {
+    assert_eq!(parse(b"{").unwrap_err().error.unwrap().code, PARSE_ERROR);
+  }
+
+  #[test]
+  fn empty_batch_is_invalid() {
+    assert_eq!(
+      parse(b"[]").unwrap_err().error.unwrap().code,
+      INVALID_REQUEST
+    );
+  }
+
+  #[test]
+  fn batch() {
+    assert_eq!(
+      parse(br#"[{"jsonrpc":"2.0","method":"a","id":1},1]"#).unwrap(),
+      Payload::Batch(vec![json!({"jsonrpc":"2.0","method":"a","id":1}), json!(1)])
+    );
+  }
+
+  #[test]
+  fn call() {
+    assert_eq!(
+      Call::from_value(json!({"jsonrpc":"2.0","method":"mint","params":{"a":1},"id":"x"})).unwrap(),
+      Call {
+        id: Some(json!("x")),
+        method: "mint".into(),
+        params: json!({"a":1}),
+      }
+    );
+  }
+
+  #[test]
+  fn notification_has_no_id() {
+    assert_eq!(
+      Call::from_value(json!({"jsonrpc":"2.0","method":"mint"}))
+        .unwrap()
+        .id,
+      None
+    );
+  }
+
+  #[test]
+  fn invalid_call_echoes_id() {
+    let response = Call::from_value(json!({"jsonrpc":"1.0","method":"mint","id":7})).unwrap_err();
+    assert_eq!(response.id, json!(7));
+    assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+  }
+
+  #[test]
+  fn invalid_params() {
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+      _fee_rate: f64,
+    }
+
+    assert_eq!(
+      params::<Params>(json!({})).unwrap_err().code,
+      INVALID_PARAMS
+    );
+  }
+
+  #[test]
+  fn build_errors_have_codes() {
+    let error = Error::server_error(
+      BuildError::InsufficientFunds {
+        required: bitcoin::Amount::from_sat(2),
+        available: bitcoin::Amount::from_sat(1),
+      }
+      .into(),
+    );
+    assert_eq!(error.code, INSUFFICIENT_FUNDS);
+    assert_eq!(error.data, Some(json!({"required": 2, "available": 1})));
+  }
+
+  #[test]
+  fn other_errors_are_server_errors() {
+    assert_eq!(
+      Error::server_error(anyhow::anyhow!("foo")),
+      Error::new(SERVER_ERROR, "foo")
+    );
+  }
+
+  #[test]
+  fn server_errors_hide_context() {
+    assert_eq!(
+      Error::server_error(
+        anyhow::anyhow!("no such table: INSCRIPTION_ORDER").context("failed to query order")
+      ),
+      Error::new(SERVER_ERROR, "failed to query order")
+    );
+  }
+
+  #[test]
+  fn response_serialization() {
+    assert_eq!(
+      serde_json::to_value(Response::new(json!(1), Ok(json!("ok")))).unwrap(),
+      json!({"jsonrpc":"2.0","result":"ok","id":1})
+    );
+    assert_eq!(
+      serde_json::to_value(Response::new(
+        Value::Null,
+        Err(Error::method_not_found("foo"))
+      ))
+      .unwrap(),
+      json!({"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found: foo"},"id":null})
+    );
+  }
+}
@@ -0,0 +1,137 @@
+use anyhow::Result;
+use log::{info, warn};
+use ord::index::Index;
+use ord::options::Options;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+type Stamp = Option<(SystemTime, u64)>;
+
+/// One read-only `Index` shared by every request. `ord_index` commits to the
+/// redb file from another process, which this handle only sees after
+/// reopening, so it reopens whenever the file's mtime or length changes.
+pub struct Snapshot {
+  options: Options,
+  current: Mutex<(Stamp, Arc<Index>)>,
+  /// Held while reopening, so only one request reopens at a time. Requests
+  /// that find it taken keep using the current index.
+  reopening: Mutex<()>,
+}
+
+impl Snapshot {
+  pub fn open(options: Options) -> Result<Self> {
+    let index = Index::read_open(&options)?;
+    let stamp = Self::stamp(&index);
+    Ok(Self {
+      options,
+      current: Mutex::new((stamp, Arc::new(index))),
+      reopening: Mutex::new(()),
+    })
+  }
+
+  pub fn index(&self) -> Arc<Index> {
+    let (stamp, index) = self.current();
+
+    let latest = Self::stamp(&index);
+    if latest == stamp {
+      return index;
+    }
+
+    let Ok(_reopening) = self.reopening.try_lock() else {
+      return index;
+    };
+
+    // Another request may have reopened it while this one checked the stamp.
+    let (stamp, index) = self.current();
+    if latest == stamp {
+      return index;
+    }
+
+    match Index::read_open(&self.options) {
+      Ok(reopened) => {
+        info!("Index changed, reopened");
+        let reopened = Arc::new(reopened);
+        *self.current.lock().unwrap() = (latest, reopened.clone());
+        reopened
+      }
+      Err(err) => {
+        warn!("Reopen index fail, keep previous snapshot:{err}");
+        index
+      }
+    }
+  }
+
+  fn current(&self) -> (Stamp, Arc<Index>) {
+    let current = self.current.lock().unwrap();
+    (current.0, current.1.clone())
+  }
+
+  fn stamp(index: &Index) -> Stamp {
+    let metadata = fs::metadata(index.path()).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bitcoin::Network;
+  use clap::Parser;
+  use std::ffi::OsStr;
+  use tempfile::TempDir;
+
+  #[test]
+  fn reopens_when_index_file_changes() {
+    let rpc_server = test_bitcoincore_rpc::builder()
+      .network(Network::Regtest)
+      .build();
+
+    let tempdir = TempDir::new().unwrap();
+    let cookie_file = tempdir.path().join("cookie");
+    fs::write(&cookie_file, "username:password").unwrap();
+
+    let options = |args: &[&OsStr]| {
+      Options::try_parse_from(
+        [
+          "ord".as_ref(),
+          "--rpc-url".as_ref(),
+          rpc_server.url().as_ref(),
+          "--data-dir".as_ref(),
+          tempdir.path().as_os_str(),
+          "--cookie-file".as_ref(),
+          cookie_file.as_os_str(),
+          "--regtest".as_ref(),
+        ]
+        .iter()
+        .chain(args),
+      )
+      .unwrap()
+    };
+
+    // redb locks an open file, so the index is built at the default path
+    // and replaced at the path the snapshot reads, like a new commit would.
+    let writer = options(&[]);
+    let path = tempdir.path().join("snapshot.redb");
+    let reader = options(&["--index".as_ref(), path.as_os_str()]);
+    let publish = || {
+      Index::open(&writer).unwrap().update().unwrap();
+      let staged = tempdir.path().join("staged.redb");
+      fs::copy(tempdir.path().join("regtest").join("index.redb"), &staged).unwrap();
+      fs::rename(staged, &path).unwrap();
+    };
+
+    publish();
+
+    let snapshot = Snapshot::open(reader).unwrap();
+    let first = snapshot.index();
+    assert!(Arc::ptr_eq(&first, &snapshot.index()));
+
+    rpc_server.mine_blocks(1);
+    publish();
+
+    let second = snapshot.index();
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert!(Arc::ptr_eq(&second, &snapshot.index()));
+  }
+}
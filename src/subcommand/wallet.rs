@@ -5,16 +5,21 @@ use {
     All, Secp256k1,
   },
   bitcoin::{
+    util::address::{Payload, WitnessVersion},
     util::bip32::{ChildNumber, DerivationPath, ExtendedPrivKey, Fingerprint},
-    Network,
+    AddressType, Network,
   },
   bitcoincore_rpc::bitcoincore_rpc_json::{ImportDescriptors, Timestamp},
+  build_error::BuildError,
   fee_rate::FeeRate,
   miniscript::descriptor::{Descriptor, DescriptorSecretKey, DescriptorXKey, Wildcard},
   transaction_builder::TransactionBuilder,
 };
 
 pub mod balance;
+pub mod broadcast;
+pub mod build_error;
+pub mod bump;
 pub mod cancel;
 pub mod cardinals;
 pub mod create;
@@ -24,6 +29,7 @@ pub mod mint;
 pub mod mints;
 pub mod outputs;
 pub mod receive;
+pub mod recover;
 mod restore;
 pub mod sats;
 pub mod send;
@@ -47,6 +53,12 @@ pub(crate) enum Wallet {
   Mints(mints::Mint),
   #[clap(about = "Cancel transaction")]
   Cancel(cancel::Cancel),
+  #[clap(about = "Recover stuck commit transaction outputs")]
+  Recover(recover::Recover),
+  #[clap(about = "Broadcast signed commit and reveal transactions")]
+  Broadcast(broadcast::Broadcast),
+  #[clap(about = "Rebuild reveal transactions at a new fee rate")]
+  Bump(bump::Bump),
   #[clap(about = "List wallet inscriptions")]
   Inscriptions,
   #[clap(about = "Generate receive address")]
@@ -75,6 +87,9 @@ impl Wallet {
       Self::Mint(mint) => mint.run(options),
       Self::Mints(mints) => mints.run(options),
       Self::Cancel(cancel) => cancel.run(options),
+      Self::Recover(recover) => recover.run(options),
+      Self::Broadcast(broadcast) => broadcast.run(options),
+      Self::Bump(bump) => bump.run(options),
       Self::Inscriptions => inscriptions::run(options),
       Self::Receive => receive::run(options),
       Self::Restore(restore) => restore.run(options),
@@ -87,6 +102,24 @@ impl Wallet {
   }
 }
 
+/// An address of `address_type` for sizing transactions whose addresses
+/// aren't known yet. Only its script length is meaningful.
+pub(crate) fn placeholder_address(address_type: AddressType, network: Network) -> Result<Address> {
+  let (version, program_len) = match address_type {
+    AddressType::P2tr => (WitnessVersion::V1, 32),
+    AddressType::P2wpkh => (WitnessVersion::V0, 20),
+    _ => bail!("address type {address_type} is not supported, only p2tr and p2wpkh are"),
+  };
+
+  Ok(Address {
+    payload: Payload::WitnessProgram {
+      version,
+      program: vec![0; program_len],
+    },
+    network,
+  })
+}
+
 fn get_change_address(client: &Client) -> Result<Address> {
   client
     .call("getrawchangeaddress", &["bech32m".into()])
@@ -0,0 +1,314 @@
+use super::*;
+use base64::Engine;
+use bitcoin::blockdata::witness::Witness;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::psbt::Psbt;
+
+/// Most transactions Bitcoin Core's `testmempoolaccept` takes in one package.
+const MAX_PACKAGE_COUNT: usize = 25;
+
+#[derive(Debug, Parser)]
+pub struct Broadcast {
+  #[clap(
+    long,
+    help = "Broadcast signed commit PSBT <COMMIT>, hex or base64 encoded."
+  )]
+  pub commit: String,
+  #[clap(
+    long,
+    help = "Require the commit transaction to have txid <COMMIT_TXID>."
+  )]
+  pub commit_txid: Option<Txid>,
+  #[clap(
+    long,
+    help = "Broadcast signed reveal transaction <REVEAL> after the commit."
+  )]
+  pub reveal: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+  pub commit: Txid,
+  pub reveal: Vec<Txid>,
+}
+
+impl Broadcast {
+  /// Finalizes the commit PSBT, checks the commit and reveals against the
+  /// mempool, then sends them in order. Nothing is sent unless every
+  /// transaction would be accepted. If a reveal then fails to send, the
+  /// error is a `BuildError::PartiallyBroadcast` with what was sent.
+  pub fn build(self, index: &Index) -> Result<Output> {
+    let commit_tx = Self::finalize(Self::decode_psbt(&self.commit)?)?;
+    let commit = commit_tx.txid();
+
+    if let Some(commit_txid) = self.commit_txid {
+      if commit != commit_txid {
+        bail!(
+          "commit transaction {commit} does not match order commit {commit_txid}, \
+           the reveal transactions would not be valid"
+        );
+      }
+    }
+
+    let reveal_txs = self
+      .reveal
+      .iter()
+      .map(|reveal| deserialize(&hex::decode(reveal)?).context("invalid reveal transaction"))
+      .collect::<Result<Vec<Transaction>>>()?;
+
+    for reveal_tx in &reveal_txs {
+      if !reveal_tx
+        .input
+        .iter()
+        .any(|input| input.previous_output.txid == commit)
+      {
+        bail!(
+          "reveal transaction {} does not spend commit transaction {commit}",
+          reveal_tx.txid()
+        );
+      }
+    }
+
+    let client = index.options().bitcoin_rpc_client()?;
+
+    log::info!("Test mempool accept...");
+    for package in Self::packages(&commit_tx, &reveal_txs) {
+      for result in client.test_mempool_accept(&package)? {
+        if !result.allowed {
+          bail!(BuildError::Rejected {
+            txid: result.txid,
+            reason: result.reject_reason.unwrap_or_default(),
+          });
+        }
+      }
+    }
+
+    let output = Self::send(&commit_tx, &reveal_txs, |tx| {
+      Ok(client.send_raw_transaction(tx)?)
+    })?;
+
+    log::info!("Broadcast commit {commit} success");
+
+    Ok(output)
+  }
+
+  /// Splits the reveals into packages small enough for `testmempoolaccept`,
+  /// each led by the commit they spend.
+  fn packages<'a>(
+    commit_tx: &'a Transaction,
+    reveal_txs: &'a [Transaction],
+  ) -> Vec<Vec<&'a Transaction>> {
+    if reveal_txs.is_empty() {
+      return vec![vec![commit_tx]];
+    }
+
+    reveal_txs
+      .chunks(MAX_PACKAGE_COUNT - 1)
+      .map(|chunk| std::iter::once(commit_tx).chain(chunk).collect())
+      .collect()
+  }
+
+  fn send(
+    commit_tx: &Transaction,
+    reveal_txs: &[Transaction],
+    mut send: impl FnMut(&Transaction) -> Result<Txid>,
+  ) -> Result<Output> {
+    let commit = commit_tx.txid();
+
+    send(commit_tx).with_context(|| format!("failed to send commit transaction {commit}"))?;
+
+    let mut reveal = vec![];
+    for reveal_tx in reveal_txs {
+      match send(reveal_tx) {
+        Ok(txid) => reveal.push(txid),
+        Err(err) => bail!(BuildError::PartiallyBroadcast {
+          commit,
+          sent: reveal,
+          failed: reveal_tx.txid(),
+          reason: format!("{err:#}"),
+        }),
+      }
+    }
+
+    Ok(Output { commit, reveal })
+  }
+
+  pub fn run(self, options: Options) -> Result {
+    print_json(self.build(&Index::read_open(&options)?)?)?;
+    Ok(())
+  }
+
+  fn decode_psbt(psbt: &str) -> Result<Psbt> {
+    let bytes = match hex::decode(psbt) {
+      Ok(bytes) => bytes,
+      Err(_) => base64::engine::general_purpose::STANDARD
+        .decode(psbt)
+        .context("commit PSBT is neither hex nor base64")?,
+    };
+
+    deserialize(&bytes).context("invalid commit PSBT")
+  }
+
+  /// Moves each input's signature into its final witness and extracts the
+  /// signed transaction. Only key path p2tr and p2wpkh inputs, the address
+  /// types mints are built for, can be finalized.
+  fn finalize(mut psbt: Psbt) -> Result<Transaction> {
+    for (i, input) in psbt.inputs.iter_mut().enumerate() {
+      if input.final_script_witness.is_some() {
+        continue;
+      }
+
+      let witness = if let Some(signature) = input.tap_key_sig {
+        vec![signature.to_vec()]
+      } else if let [(public_key, signature)] =
+        input.partial_sigs.iter().collect::<Vec<_>>().as_slice()
+      {
+        vec![signature.to_vec(), public_key.to_bytes()]
+      } else {
+        bail!("commit PSBT input {i} is not signed");
+      };
+
+      input.final_script_witness = Some(Witness::from_vec(witness));
+      input.partial_sigs.clear();
+      input.tap_key_sig = None;
+    }
+
+    Ok(psbt.extract_tx())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    bitcoin::{consensus::encode::serialize, SchnorrSig, SchnorrSighashType},
+  };
+
+  fn psbt() -> Psbt {
+    Psbt::from_unsigned_tx(Transaction {
+      version: 1,
+      lock_time: bitcoin::PackedLockTime::ZERO,
+      input: vec![tx_in(outpoint(1))],
+      output: vec![tx_out(10_000, recipient())],
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn finalize_key_path_signature() {
+    let signature = SchnorrSig {
+      sig: bitcoin::secp256k1::schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+      hash_ty: SchnorrSighashType::Default,
+    };
+
+    let mut psbt = psbt();
+    psbt.inputs[0].tap_key_sig = Some(signature);
+    let txid = psbt.unsigned_tx.txid();
+
+    let tx = Broadcast::finalize(psbt).unwrap();
+
+    assert_eq!(tx.txid(), txid);
+    assert_eq!(tx.input[0].witness.to_vec(), vec![signature.to_vec()]);
+  }
+
+  #[test]
+  fn finalize_rejects_unsigned_input() {
+    assert_eq!(
+      Broadcast::finalize(psbt()).unwrap_err().to_string(),
+      "commit PSBT input 0 is not signed"
+    );
+  }
+
+  #[test]
+  fn decode_hex_and_base64_psbt() {
+    let bytes = serialize(&psbt());
+
+    assert_eq!(
+      Broadcast::decode_psbt(&hex::encode(&bytes)).unwrap(),
+      psbt()
+    );
+    assert_eq!(
+      Broadcast::decode_psbt(&base64::engine::general_purpose::STANDARD.encode(&bytes)).unwrap(),
+      psbt()
+    );
+  }
+  fn reveals(n: u32) -> Vec<Transaction> {
+    (0..n)
+      .map(|vout| Transaction {
+        version: 1,
+        lock_time: bitcoin::PackedLockTime::ZERO,
+        input: vec![tx_in(OutPoint {
+          txid: psbt().unsigned_tx.txid(),
+          vout,
+        })],
+        output: vec![tx_out(546, recipient())],
+      })
+      .collect()
+  }
+
+  #[test]
+  fn packages_fit_testmempoolaccept() {
+    let commit_tx = psbt().unsigned_tx;
+
+    assert_eq!(Broadcast::packages(&commit_tx, &[]), vec![vec![&commit_tx]]);
+
+    let reveal_txs = reveals(50);
+    let packages = Broadcast::packages(&commit_tx, &reveal_txs);
+    assert_eq!(
+      packages.iter().map(Vec::len).collect::<Vec<usize>>(),
+      [25, 25, 3]
+    );
+    for package in &packages {
+      assert_eq!(package[0], &commit_tx);
+    }
+    assert_eq!(
+      packages
+        .iter()
+        .flat_map(|package| package[1..].iter().copied())
+        .collect::<Vec<&Transaction>>(),
+      reveal_txs.iter().collect::<Vec<&Transaction>>(),
+    );
+  }
+
+  #[test]
+  fn send_reports_partial_broadcast() {
+    let commit_tx = psbt().unsigned_tx;
+    let reveal_txs = reveals(3);
+
+    let err = Broadcast::send(&commit_tx, &reveal_txs, |tx| {
+      if tx.txid() == reveal_txs[1].txid() {
+        bail!("too-long-mempool-chain");
+      }
+      Ok(tx.txid())
+    })
+    .unwrap_err();
+
+    assert_matches!(
+      err.downcast_ref::<BuildError>(),
+      Some(BuildError::PartiallyBroadcast { commit, sent, failed, reason })
+        if *commit == commit_tx.txid()
+          && *sent == [reveal_txs[0].txid()]
+          && *failed == reveal_txs[1].txid()
+          && reason == "too-long-mempool-chain"
+    );
+  }
+
+  #[test]
+  fn send_fails_cleanly_without_commit() {
+    let commit_tx = psbt().unsigned_tx;
+    let mut sent = 0;
+
+    let err = Broadcast::send(&commit_tx, &reveals(2), |_| {
+      sent += 1;
+      bail!("insufficient fee");
+    })
+    .unwrap_err();
+
+    assert_eq!(sent, 1);
+    assert!(err.downcast_ref::<BuildError>().is_none());
+    assert_eq!(
+      err.to_string(),
+      format!("failed to send commit transaction {}", commit_tx.txid())
+    );
+  }
+}
@@ -0,0 +1,203 @@
+use {super::*, bitcoin::AddressType, std::collections::BTreeSet};
+
+/// Failures of the mint, transfer and cancel builders that callers may want
+/// to handle programmatically. Everything else is reported as a plain
+/// `anyhow` error.
+#[derive(Debug, PartialEq)]
+pub enum BuildError {
+  AlreadyInscribed {
+    satpoint: SatPoint,
+    inscription_id: InscriptionId,
+  },
+  Dust {
+    output_value: Amount,
+    dust_value: Amount,
+  },
+  InscriptionNotFound(InscriptionId),
+  /// `required` is a lower bound, it does not include network fees unless
+  /// they are all that is missing.
+  InsufficientFunds {
+    required: Amount,
+    available: Amount,
+  },
+  /// Sending reveal transaction `failed` failed after the commit and the
+  /// reveals in `sent` were already broadcast.
+  PartiallyBroadcast {
+    commit: Txid,
+    sent: Vec<Txid>,
+    failed: Txid,
+    reason: String,
+  },
+  /// Bitcoin Core's `testmempoolaccept` turned down a transaction.
+  Rejected {
+    txid: Txid,
+    reason: String,
+  },
+  UnsupportedAddressType(Address),
+  UtxoProvider(String),
+}
+
+impl Display for BuildError {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Self::AlreadyInscribed {
+        satpoint,
+        inscription_id,
+      } => write!(f, "sat at {satpoint} already inscribed with inscription {inscription_id}"),
+      Self::Dust {
+        output_value,
+        dust_value,
+      } => write!(f, "output value is below dust value: {output_value} < {dust_value}"),
+      Self::InscriptionNotFound(inscription_id) => {
+        write!(f, "inscription {inscription_id} not found")
+      }
+      Self::InsufficientFunds {
+        required,
+        available,
+      } => write!(
+        f,
+        "Wallet balance is insufficient: at least {required} required, {available} available. Please reduce the number of minting times or deposit more funds."
+      ),
+      Self::PartiallyBroadcast {
+        commit,
+        sent,
+        failed,
+        reason,
+      } => write!(
+        f,
+        "commit transaction {commit} and {} reveal transactions were broadcast, but reveal transaction {failed} failed: {reason}",
+        sent.len()
+      ),
+      Self::Rejected { txid, reason } => {
+        write!(f, "transaction {txid} rejected by mempool: {reason}")
+      }
+      Self::UnsupportedAddressType(address) => write!(
+        f,
+        "address `{address}` is not supported, only p2tr and p2wpkh addresses are"
+      ),
+      Self::UtxoProvider(err) => write!(f, "utxo provider failed: {err}"),
+    }
+  }
+}
+
+impl std::error::Error for BuildError {}
+
+impl BuildError {
+  pub(crate) fn check_address_type(address: &Address) -> Result<AddressType, Self> {
+    match address.address_type() {
+      Some(address_type @ (AddressType::P2tr | AddressType::P2wpkh)) => Ok(address_type),
+      _ => Err(Self::UnsupportedAddressType(address.clone())),
+    }
+  }
+
+  pub(crate) fn cardinal_value(
+    utxos: &BTreeMap<OutPoint, Amount>,
+    inscriptions: &BTreeMap<SatPoint, InscriptionId>,
+  ) -> Amount {
+    let inscribed_utxos = inscriptions
+      .keys()
+      .map(|satpoint| satpoint.outpoint)
+      .collect::<BTreeSet<OutPoint>>();
+
+    utxos
+      .iter()
+      .filter(|(outpoint, _)| !inscribed_utxos.contains(outpoint))
+      .map(|(_, amount)| *amount)
+      .sum()
+  }
+
+  pub(crate) fn from_transaction_builder(
+    err: transaction_builder::Error,
+    required: Amount,
+    available: Amount,
+  ) -> Error {
+    match err {
+      transaction_builder::Error::Dust {
+        output_value,
+        dust_value,
+      } => Self::Dust {
+        output_value,
+        dust_value,
+      }
+      .into(),
+      transaction_builder::Error::NotEnoughCardinalUtxos => Self::InsufficientFunds {
+        required,
+        available,
+      }
+      .into(),
+      transaction_builder::Error::UtxoContainsAdditionalInscription {
+        inscribed_satpoint,
+        inscription_id,
+        ..
+      } => Self::AlreadyInscribed {
+        satpoint: inscribed_satpoint,
+        inscription_id,
+      }
+      .into(),
+      err => err.into(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cardinal_value_skips_inscribed_outputs() {
+    assert_eq!(
+      BuildError::cardinal_value(
+        &[
+          (outpoint(1), Amount::from_sat(1_000)),
+          (outpoint(2), Amount::from_sat(2_000)),
+        ]
+        .into_iter()
+        .collect(),
+        &[(satpoint(1, 0), inscription_id(1))].into_iter().collect(),
+      ),
+      Amount::from_sat(2_000)
+    );
+  }
+
+  #[test]
+  fn not_enough_cardinal_utxos_is_insufficient_funds() {
+    assert_eq!(
+      BuildError::from_transaction_builder(
+        transaction_builder::Error::NotEnoughCardinalUtxos,
+        Amount::from_sat(10_000),
+        Amount::from_sat(5_000),
+      )
+      .downcast::<BuildError>()
+      .unwrap(),
+      BuildError::InsufficientFunds {
+        required: Amount::from_sat(10_000),
+        available: Amount::from_sat(5_000),
+      }
+    );
+  }
+
+  #[test]
+  fn other_transaction_builder_errors_pass_through() {
+    assert_eq!(
+      BuildError::from_transaction_builder(
+        transaction_builder::Error::ValueOverflow,
+        Amount::ZERO,
+        Amount::ZERO,
+      )
+      .downcast::<transaction_builder::Error>()
+      .unwrap(),
+      transaction_builder::Error::ValueOverflow
+    );
+  }
+
+  #[test]
+  fn unsupported_address_type() {
+    let address = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"
+      .parse::<Address>()
+      .unwrap();
+    assert_eq!(
+      BuildError::check_address_type(&address),
+      Err(BuildError::UnsupportedAddressType(address))
+    );
+  }
+}
@@ -0,0 +1,433 @@
+use super::*;
+use crate::index::Order;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::schnorr::{TapTweak, UntweakedKeyPair};
+use bitcoin::secp256k1::{self, XOnlyPublicKey};
+use bitcoin::util::bip32::ExtendedPrivKey;
+use bitcoin::util::key::PrivateKey;
+use bitcoin::util::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use bitcoincore_rpc::RawTx;
+use mint::Mint;
+
+#[derive(Debug, Parser)]
+pub struct Bump {
+  #[clap(
+    long,
+    help = "Rebuild reveal transactions of commit transaction <COMMIT>."
+  )]
+  pub commit: Txid,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB for the reveals. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
+  #[clap(long, help = "Rebuild signed reveal transaction <REVEAL>.")]
+  pub reveal: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+  pub commit_txid: Txid,
+  pub inscription: Vec<InscriptionId>,
+  pub reveal: Vec<String>,
+  pub network_fee: u64,
+  pub fee_rate: f64,
+}
+
+impl Output {
+  /// `order` with its reveal transactions replaced by the rebuilt ones.
+  pub fn order(&self, order: Order) -> Order {
+    Order {
+      reveal_txids: self
+        .inscription
+        .iter()
+        .map(|inscription_id| inscription_id.txid)
+        .collect(),
+      reveals: self.reveal.clone(),
+      network_fee: order.commit_fee + self.network_fee,
+      ..order
+    }
+  }
+}
+
+/// A reveal transaction together with what spending its commit output takes.
+struct RevealSpend {
+  reveal_tx: Transaction,
+  prevout: TxOut,
+  reveal_script: Script,
+  spend_info: TaprootSpendInfo,
+}
+
+impl Bump {
+  /// Rebuilds the reveal transactions at a new fee rate and signs them with
+  /// the reveal key derived again from `--reveal-master-key` and the commit
+  /// transaction. The fee comes out of the postage output, so the commit
+  /// transaction is left as it is.
+  pub fn build(self, index: &Index) -> Result<Output> {
+    if self.reveal.is_empty() {
+      bail!(
+        "no reveal transactions of commit {} to rebuild",
+        self.commit
+      );
+    }
+
+    log::info!("Get commit transaction...");
+    let commit_tx = index.get_transaction_by_mempool(self.commit)?;
+    let fee_rate = index.fee_rate(self.fee_rate)?;
+
+    let (reveal_txs, network_fee) =
+      Self::rebuild(Self::master_key(index)?, &commit_tx, &self.reveal, fee_rate)?;
+
+    log::info!("Build bump success");
+
+    Ok(Output {
+      commit_txid: self.commit,
+      inscription: reveal_txs.iter().map(|tx| tx.txid().into()).collect(),
+      reveal: reveal_txs.iter().map(|tx| tx.raw_hex()).collect(),
+      network_fee: network_fee.to_sat(),
+      fee_rate: fee_rate.rate(),
+    })
+  }
+
+  /// The keys that sweep the commit outputs spent by `reveals`, derived
+  /// again from `--reveal-master-key` instead of being stored.
+  pub fn recovery_keys(index: &Index, commit: Txid, reveals: &[String]) -> Result<Vec<PrivateKey>> {
+    Self::derive_recovery_keys(
+      Self::master_key(index)?,
+      index.options().chain().network(),
+      &index.get_transaction_by_mempool(commit)?,
+      reveals,
+    )
+  }
+
+  pub fn run(self, options: Options) -> Result {
+    print_json(self.build(&Index::read_open(&options)?)?)?;
+    Ok(())
+  }
+
+  fn master_key(index: &Index) -> Result<ExtendedPrivKey> {
+    index
+      .options()
+      .reveal_master_key
+      .ok_or_else(|| anyhow!("reveal keys can only be derived again with a reveal master key"))
+  }
+
+  fn rebuild(
+    master_key: ExtendedPrivKey,
+    commit_tx: &Transaction,
+    reveals: &[String],
+    fee_rate: FeeRate,
+  ) -> Result<(Vec<Transaction>, Amount)> {
+    let secp256k1 = Secp256k1::new();
+    let key_pair = Self::key_pair(&secp256k1, master_key, commit_tx)?;
+
+    let mut reveal_txs = vec![];
+    let mut network_fee = Amount::ZERO;
+    for reveal in reveals {
+      let spend = Self::reveal_spend(&secp256k1, &key_pair, commit_tx, reveal)?;
+      let control_block = spend
+        .spend_info
+        .control_block(&(spend.reveal_script.clone(), LeafVersion::TapScript))
+        .expect("should compute control block");
+
+      let (mut reveal_tx, fee) = Mint::build_reveal_transaction(
+        &control_block,
+        fee_rate,
+        spend.reveal_tx.input[0].previous_output,
+        spend.reveal_tx.output,
+        &spend.reveal_script,
+      );
+
+      let other_outputs = reveal_tx.output[1..]
+        .iter()
+        .map(|output| output.value)
+        .sum::<u64>();
+      let postage = spend
+        .prevout
+        .value
+        .checked_sub(other_outputs + fee.to_sat())
+        .ok_or(BuildError::InsufficientFunds {
+          required: Amount::from_sat(other_outputs) + fee,
+          available: Amount::from_sat(spend.prevout.value),
+        })?;
+
+      let dust_value = reveal_tx.output[0].script_pubkey.dust_value();
+      if postage < dust_value.to_sat() {
+        bail!(BuildError::Dust {
+          output_value: Amount::from_sat(postage),
+          dust_value,
+        });
+      }
+      reveal_tx.output[0].value = postage;
+
+      Mint::sign_reveal_transaction(
+        &secp256k1,
+        &key_pair,
+        &mut reveal_tx,
+        spend.prevout,
+        &spend.reveal_script,
+        &control_block,
+      );
+
+      network_fee += fee;
+      reveal_txs.push(reveal_tx);
+    }
+
+    Ok((reveal_txs, network_fee))
+  }
+
+  fn derive_recovery_keys(
+    master_key: ExtendedPrivKey,
+    network: Network,
+    commit_tx: &Transaction,
+    reveals: &[String],
+  ) -> Result<Vec<PrivateKey>> {
+    let secp256k1 = Secp256k1::new();
+    let key_pair = Self::key_pair(&secp256k1, master_key, commit_tx)?;
+
+    let mut recovery_keys = vec![];
+    for reveal in reveals {
+      let spend = Self::reveal_spend(&secp256k1, &key_pair, commit_tx, reveal)?;
+      let recovery_key = PrivateKey::new(
+        key_pair
+          .tap_tweak(&secp256k1, spend.spend_info.merkle_root())
+          .to_inner()
+          .secret_key(),
+        network,
+      );
+      if !recovery_keys.contains(&recovery_key) {
+        recovery_keys.push(recovery_key);
+      }
+    }
+
+    Ok(recovery_keys)
+  }
+
+  /// The reveal key of `commit_tx`, derived from the first outpoint it spends
+  /// like `Mint::create_inscription_transactions` does.
+  fn key_pair(
+    secp256k1: &Secp256k1<secp256k1::All>,
+    master_key: ExtendedPrivKey,
+    commit_tx: &Transaction,
+  ) -> Result<UntweakedKeyPair> {
+    Mint::reveal_key_pair(
+      secp256k1,
+      Some(master_key),
+      commit_tx.input[0].previous_output,
+    )
+  }
+
+  /// Checks that `reveal` spends an output of `commit_tx` locked to `key_pair`
+  /// and its reveal script.
+  fn reveal_spend(
+    secp256k1: &Secp256k1<secp256k1::All>,
+    key_pair: &UntweakedKeyPair,
+    commit_tx: &Transaction,
+    reveal: &str,
+  ) -> Result<RevealSpend> {
+    let reveal_tx: Transaction =
+      deserialize(&hex::decode(reveal)?).context("invalid reveal transaction")?;
+    let txid = reveal_tx.txid();
+
+    let outpoint = match reveal_tx.input.as_slice() {
+      [input] if input.previous_output.txid == commit_tx.txid() => input.previous_output,
+      _ => bail!(
+        "reveal transaction {txid} does not spend commit transaction {}",
+        commit_tx.txid()
+      ),
+    };
+
+    let prevout = usize::try_from(outpoint.vout)
+      .ok()
+      .and_then(|vout| commit_tx.output.get(vout))
+      .cloned()
+      .ok_or_else(|| anyhow!("commit transaction has no output {outpoint}"))?;
+
+    let witness = reveal_tx.input[0].witness.to_vec();
+    if witness.len() != 3 {
+      bail!("reveal transaction {txid} is not a signed script path spend");
+    }
+    let reveal_script = Script::from(witness[1].clone());
+
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(key_pair);
+    let spend_info = TaprootBuilder::new()
+      .add_leaf(0, reveal_script.clone())
+      .expect("adding leaf should work")
+      .finalize(secp256k1, public_key)
+      .expect("finalizing taproot builder should work");
+
+    if Script::new_v1_p2tr_tweaked(spend_info.output_key()) != prevout.script_pubkey {
+      bail!("commit output {outpoint} is not locked to a reveal key derived from the master key");
+    }
+
+    Ok(RevealSpend {
+      reveal_tx,
+      prevout,
+      reveal_script,
+      spend_info,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    bitcoin::{
+      consensus::encode::serialize_hex,
+      schnorr::TweakedPublicKey,
+      util::sighash::{Prevouts, SighashCache},
+      util::taproot::TapLeafHash,
+      SchnorrSighashType,
+    },
+  };
+
+  fn master_key(seed: u8) -> ExtendedPrivKey {
+    ExtendedPrivKey::new_master(Network::Regtest, &[seed; 32]).unwrap()
+  }
+
+  fn mint(master_key: ExtendedPrivKey) -> (Transaction, Vec<Transaction>, PrivateKey) {
+    let source = recipient();
+    let (commit_tx, reveal_txs, recovery_key_pair, ..) = Mint::create_inscription_transactions(
+      AddressType::P2wpkh,
+      vec![],
+      inscription("text/plain;charset=utf-8", "bump"),
+      BTreeMap::new(),
+      Network::Testnet,
+      [(outpoint(1), Amount::from_sat(100_000))].into(),
+      [change(0), change(1)],
+      source,
+      FeeRate::try_from(1.0).unwrap(),
+      FeeRate::try_from(1.0).unwrap(),
+      false,
+      change(2),
+      2,
+      Amount::from_sat(3000),
+      Amount::from_sat(10_000),
+      Some(master_key),
+    )
+    .unwrap();
+
+    (
+      commit_tx,
+      reveal_txs,
+      PrivateKey::new(recovery_key_pair.to_inner().secret_key(), Network::Testnet),
+    )
+  }
+
+  #[test]
+  fn rebuild_re_signs_reveals_at_new_fee_rate() {
+    let (commit_tx, reveal_txs, _) = mint(master_key(1));
+    let reveals = reveal_txs
+      .iter()
+      .map(serialize_hex)
+      .collect::<Vec<String>>();
+
+    let (bumped, network_fee) = Bump::rebuild(
+      master_key(1),
+      &commit_tx,
+      &reveals,
+      FeeRate::try_from(3.0).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(bumped.len(), 2);
+
+    let secp256k1 = Secp256k1::new();
+    let mut fees = Amount::ZERO;
+    for (old, new) in reveal_txs.iter().zip(&bumped) {
+      assert_ne!(old.txid(), new.txid());
+      assert_eq!(old.input[0].previous_output, new.input[0].previous_output);
+      assert_eq!(old.output.len(), new.output.len());
+      assert_eq!(old.output[1..], new.output[1..]);
+      assert!(new.output[0].value < old.output[0].value);
+
+      let prevout = commit_tx.output[new.input[0].previous_output.vout as usize].clone();
+      fees +=
+        Amount::from_sat(prevout.value - new.output.iter().map(|output| output.value).sum::<u64>());
+
+      let witness = new.input[0].witness.to_vec();
+      let reveal_script = Script::from(witness[1].clone());
+      let signature_hash = SighashCache::new(new)
+        .taproot_script_spend_signature_hash(
+          0,
+          &Prevouts::All(&[prevout]),
+          TapLeafHash::from_script(&reveal_script, LeafVersion::TapScript),
+          SchnorrSighashType::Default,
+        )
+        .unwrap();
+      let public_key = XOnlyPublicKey::from_slice(&reveal_script.as_bytes()[1..33]).unwrap();
+
+      secp256k1
+        .verify_schnorr(
+          &secp256k1::schnorr::Signature::from_slice(&witness[0]).unwrap(),
+          &secp256k1::Message::from_slice(signature_hash.as_inner()).unwrap(),
+          &public_key,
+        )
+        .unwrap();
+    }
+    assert_eq!(fees, network_fee);
+  }
+
+  #[test]
+  fn recovery_keys_are_derived_again_from_commit() {
+    let (commit_tx, reveal_txs, recovery_key) = mint(master_key(1));
+    let reveals = reveal_txs
+      .iter()
+      .map(serialize_hex)
+      .collect::<Vec<String>>();
+
+    let recovery_keys =
+      Bump::derive_recovery_keys(master_key(1), Network::Testnet, &commit_tx, &reveals).unwrap();
+
+    assert_eq!(recovery_keys, [recovery_key]);
+
+    let (x_only_pub_key, _parity) =
+      XOnlyPublicKey::from_keypair(&recovery_key.inner.keypair(&Secp256k1::new()));
+    assert_eq!(
+      Script::new_v1_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(x_only_pub_key)),
+      commit_tx.output[0].script_pubkey
+    );
+  }
+
+  #[test]
+  fn rebuild_requires_the_master_key_used_to_mint() {
+    let (commit_tx, reveal_txs, _) = mint(master_key(1));
+    let reveals = reveal_txs
+      .iter()
+      .map(serialize_hex)
+      .collect::<Vec<String>>();
+
+    assert!(Bump::rebuild(
+      master_key(2),
+      &commit_tx,
+      &reveals,
+      FeeRate::try_from(3.0).unwrap(),
+    )
+    .unwrap_err()
+    .to_string()
+    .contains("is not locked to a reveal key derived from the master key"));
+  }
+
+  #[test]
+  fn rebuild_keeps_postage_above_dust() {
+    let (commit_tx, reveal_txs, _) = mint(master_key(1));
+    let reveals = reveal_txs
+      .iter()
+      .map(serialize_hex)
+      .collect::<Vec<String>>();
+
+    assert_matches!(
+      Bump::rebuild(
+        master_key(1),
+        &commit_tx,
+        &reveals,
+        FeeRate::try_from(100.0).unwrap(),
+      )
+      .unwrap_err()
+      .downcast::<BuildError>()
+      .unwrap(),
+      BuildError::Dust { .. } | BuildError::InsufficientFunds { .. }
+    );
+  }
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::index::{ConstructTransaction, MysqlDatabase, TransactionOutputArray};
+use crate::index::{ConstructTransaction, MirrorStore, TransactionOutputArray};
 use bitcoin::blockdata::{script, witness::Witness};
 use bitcoin::consensus::encode::serialize_hex;
 use bitcoin::psbt::Psbt;
@@ -11,8 +11,11 @@ pub struct Cancel {
   pub source: Address,
   #[clap(long, help = "The inputs that needs to be canceled.")]
   pub inputs: Vec<OutPoint>,
-  #[clap(long, help = "Use fee rate of <FEE_RATE> sats/vB")]
-  pub fee_rate: FeeRate,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,16 +26,19 @@ pub struct Output {
   pub service_fee: u64,
   pub commit_vsize: u64,
   pub commit_fee: u64,
+  pub fee_rate: f64,
 }
 
 impl Cancel {
   pub fn build(
     self,
-    options: Options,
+    index: &Index,
     service_address: Option<Address>,
-    service_fee: Option<Amount>,
-    _mysql: Option<Arc<MysqlDatabase>>,
+    fee_policy: &FeePolicy,
+    mirror: Option<Arc<dyn MirrorStore>>,
   ) -> Result<Output> {
+    let options = index.options();
+
     if !self.source.is_valid_for_network(options.chain().network()) {
       bail!(
         "Address `{}` is not valid for {}",
@@ -42,31 +48,22 @@ impl Cancel {
     }
 
     // check address types, only support p2tr and p2wpkh
-    let address_type = if let Some(address_type) = self.source.address_type() {
-      if (address_type == AddressType::P2tr) || (address_type == AddressType::P2wpkh) {
-        address_type
-      } else {
-        bail!(
-          "Address type `{}` is not valid, only support p2tr and p2wpkh",
-          address_type
-        );
-      }
-    } else {
-      bail!(
-        "Address `{}` is not valid for {}",
-        self.source,
-        options.chain()
-      );
-    };
-
-    log::info!("Open index...");
-    let index = Index::read_open(&options)?;
-    // index.update()?;
+    let address_type = BuildError::check_address_type(&self.source)?;
 
     log::info!("Get utxo...");
     let unspent_outputs = index.get_unspent_outputs_by_outpoints(&self.inputs)?;
 
-    let mut service_fee = service_fee.unwrap_or(Amount::ZERO).to_sat();
+    let whitelist_level =
+      mirror.and_then(|mirror| mirror.whitelist_level(&self.source.to_string()));
+
+    let mut service_fee = fee_policy
+      .service_fee(
+        FeeMethod::Cancel,
+        u64::try_from(self.inputs.len())?,
+        Amount::ZERO,
+        whitelist_level.as_deref(),
+      )
+      .to_sat();
     if service_address.is_none() {
       service_fee = 0;
     }
@@ -88,13 +85,17 @@ impl Cancel {
         },
       ]
     };
+    let fee_rate = index.fee_rate(self.fee_rate)?;
     let (mut cancel_tx, network_fee) =
-      Self::build_cancel_transaction(self.fee_rate, self.inputs, output, address_type);
+      Self::build_cancel_transaction(fee_rate, self.inputs, output, address_type);
     let commit_vsize = cancel_tx.vsize() as u64;
 
     let input_amount = Self::get_amount(&cancel_tx, &unspent_outputs)?;
     if input_amount <= network_fee {
-      bail!("Input amount less than network fee");
+      bail!(BuildError::InsufficientFunds {
+        required: Amount::from_sat(network_fee),
+        available: Amount::from_sat(input_amount),
+      });
     }
     if input_amount <= network_fee + service_fee {
       service_fee = input_amount - network_fee;
@@ -117,11 +118,17 @@ impl Cancel {
       service_fee,
       commit_vsize,
       commit_fee: network_fee,
+      fee_rate: fee_rate.rate(),
     })
   }
 
   pub fn run(self, options: Options) -> Result {
-    print_json(self.build(options, None, None, None)?)?;
+    print_json(self.build(
+      &Index::read_open(&options)?,
+      None,
+      &options.fee_policy()?,
+      None,
+    )?)?;
     Ok(())
   }
 
@@ -1,4 +1,4 @@
-use crate::index::{ConstructTransaction, MysqlDatabase, TransactionOutputArray};
+use crate::index::{ConstructTransaction, MirrorStore, Order, TransactionOutputArray};
 use bitcoin::psbt::Psbt;
 use bitcoin::{consensus::encode::serialize_hex, AddressType};
 use bitcoincore_rpc::RawTx;
@@ -6,22 +6,30 @@ use {
   super::*,
   bitcoin::{
     blockdata::{opcodes, script},
+    consensus::encode::serialize,
+    hashes::sha256,
     policy::MAX_STANDARD_TX_WEIGHT,
     schnorr::{TapTweak, TweakedKeyPair, TweakedPublicKey, UntweakedKeyPair},
     secp256k1::{
       self, constants::SCHNORR_SIGNATURE_SIZE, rand, schnorr::Signature, Secp256k1, XOnlyPublicKey,
     },
+    util::bip32::{ChildNumber, ExtendedPrivKey},
+    util::key::PrivateKey,
     util::sighash::{Prevouts, SighashCache},
-    util::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder},
+    util::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo},
     PackedLockTime, SchnorrSighashType, Witness,
   },
-  std::collections::BTreeSet,
+  std::{collections::BTreeSet, iter},
 };
 
 #[derive(Debug, Serialize)]
 pub struct Output {
   pub inscription: Vec<InscriptionId>,
   pub commit: String,
+  pub commit_txid: Txid,
+  /// Outputs spent by the commit transaction.
+  #[serde(skip)]
+  pub commit_inputs: Vec<OutPoint>,
   pub commit_custom: Vec<String>,
   pub reveal: Vec<String>,
   pub service_fee: u64,
@@ -29,12 +37,61 @@ pub struct Output {
   pub network_fee: u64,
   pub commit_vsize: u64,
   pub commit_fee: u64,
+  pub fee_rate: f64,
+  pub reveal_fee_rate: f64,
+  /// WIF key that can sweep the commit outputs, which all share it. Never
+  /// serialized: the server keeps it with `insert_recovery_keys`.
+  #[serde(skip)]
+  pub recovery_key: Vec<String>,
+}
+
+/// The fees of a mint as `Output` would show them, quoted by
+/// `Mint::estimate`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+  pub service_fee: u64,
+  pub satpoint_fee: u64,
+  pub network_fee: u64,
+  pub commit_vsize: u64,
+  pub commit_fee: u64,
+  pub fee_rate: f64,
+  pub reveal_fee_rate: f64,
+}
+
+impl Output {
+  /// The order to record for this output, built now for `source` to
+  /// inscribe to `destination`.
+  pub fn order(&self, source: Address, destination: Address) -> Order {
+    Order {
+      commit_txid: self.commit_txid,
+      reveal_txids: self
+        .inscription
+        .iter()
+        .map(|inscription_id| inscription_id.txid)
+        .collect(),
+      reveals: self.reveal.clone(),
+      inputs: self.commit_inputs.clone(),
+      source,
+      destination,
+      service_fee: self.service_fee,
+      satpoint_fee: self.satpoint_fee,
+      network_fee: self.network_fee,
+      commit_fee: self.commit_fee,
+      created_at: SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0),
+    }
+  }
 }
 
 #[derive(Debug, Parser)]
 pub struct Mint {
-  #[clap(long, help = "Use fee rate of <FEE_RATE> sats/vB")]
-  pub fee_rate: FeeRate,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
   #[clap(long, help = "Send inscription to <DESTINATION>.")]
   pub destination: Option<Address>,
   #[clap(long, help = "Send inscription from <SOURCE>.")]
@@ -52,24 +109,20 @@ pub struct Mint {
 }
 
 impl Mint {
-  pub const SERVICE_FEE: Amount = Amount::from_sat(3000);
-
   pub fn build(
     self,
-    options: Options,
+    index: &Index,
     service_address: Option<Address>,
-    service_fee: Option<Amount>,
-    mysql: Option<Arc<MysqlDatabase>>,
+    fee_policy: &FeePolicy,
+    mirror: Option<Arc<dyn MirrorStore>>,
   ) -> Result<Output> {
+    let options = index.options();
+
     let repeat: u64 = self.repeat.unwrap_or(1);
     let extension = "data.".to_owned() + &self.extension.unwrap_or(".txt".to_owned());
 
     let inscription = Inscription::from_content(options.chain(), &extension, self.content)?;
 
-    log::info!("Open index...");
-    let index = Index::read_open(&options)?;
-    // index.update()?;
-
     let source = self.source;
     let reveal_tx_destination = self.destination.unwrap_or_else(|| source.clone());
 
@@ -85,26 +138,13 @@ impl Mint {
     }
 
     // check address types, only support p2tr and p2wpkh
-    let address_type = if let Some(address_type) = source.address_type() {
-      if (address_type == AddressType::P2tr) || (address_type == AddressType::P2wpkh) {
-        address_type
-      } else {
-        bail!(
-          "Address type `{}` is not valid, only support p2tr and p2wpkh",
-          address_type
-        );
-      }
-    } else {
-      bail!("Address `{}` is not valid for {}", source, options.chain());
-    };
+    let address_type = BuildError::check_address_type(&source)?;
 
     let service_address = service_address.unwrap_or(source.clone());
 
     log::info!("Get utxo...");
     let query_address = &format!("{}", source);
-    let mut additional_service_fee = Amount::ZERO;
     let (utxos, satpoints) = if let Some(commit_id) = self.remint {
-      additional_service_fee = Amount::from_sat(3000);
       let (utxos, recommit_tx) =
         index.get_unspent_outputs_by_commit_id(query_address, BTreeMap::new(), commit_id)?;
       (
@@ -125,11 +165,11 @@ impl Mint {
       )
     };
 
-    let mut is_whitelist = false;
-    let inscriptions = if let Some(mysql) = mysql {
-      log::info!("Get inscriptions by mysql...");
-      is_whitelist = mysql.is_whitelist(query_address);
-      mysql.get_inscription_by_address(query_address)?
+    let mut whitelist_level = None;
+    let inscriptions = if let Some(mirror) = mirror {
+      log::info!("Get inscriptions by mirror...");
+      whitelist_level = mirror.whitelist_level(query_address);
+      mirror.get_inscription_by_address(query_address)?
     } else {
       log::info!("Get inscriptions by redb...");
       index.get_inscriptions(None)?
@@ -137,38 +177,38 @@ impl Mint {
 
     let commit_tx_change = [source.clone(), source.clone()];
 
-    let service_fee = if is_whitelist {
-      Amount::ZERO
-    } else {
-      service_fee.unwrap_or(Self::SERVICE_FEE)
-    };
-
-    let reveal_fee_rate = FeeRate::try_from(self.fee_rate.0 + 0.02)?;
-    let (
-      unsigned_commit_tx,
-      reveal_txs,
-      _recovery_key_pair,
-      service_fee,
-      satpoint_fee,
-      network_fee,
-    ) = Mint::create_inscription_transactions(
-      address_type,
-      satpoints,
-      inscription,
-      inscriptions,
-      options.chain().network(),
-      utxos.clone(),
-      commit_tx_change,
-      reveal_tx_destination,
-      self.fee_rate,
-      reveal_fee_rate,
-      false,
-      service_address,
-      usize::try_from(repeat)?,
-      service_fee,
+    let service_fee = fee_policy.service_fee(
+      if self.remint.is_some() {
+        FeeMethod::Remint
+      } else {
+        FeeMethod::Mint
+      },
+      repeat,
       self.target_postage,
-      additional_service_fee,
-    )?;
+      whitelist_level.as_deref(),
+    );
+
+    let fee_rate = index.fee_rate(self.fee_rate)?;
+    let reveal_fee_rate = options.reveal_fee_policy.apply(fee_rate)?;
+    let (unsigned_commit_tx, reveal_txs, recovery_key_pair, service_fee, satpoint_fee, network_fee) =
+      Mint::create_inscription_transactions(
+        address_type,
+        satpoints,
+        inscription,
+        inscriptions,
+        options.chain().network(),
+        utxos.clone(),
+        commit_tx_change,
+        reveal_tx_destination,
+        fee_rate,
+        reveal_fee_rate,
+        false,
+        service_address,
+        usize::try_from(repeat)?,
+        service_fee,
+        self.target_postage,
+        options.reveal_master_key,
+      )?;
 
     let commit_vsize = Self::estimate_vsize(&unsigned_commit_tx, address_type) as u64;
     let commit_fee = Self::calculate_fee(&unsigned_commit_tx, &utxos);
@@ -178,8 +218,20 @@ impl Mint {
     let unsigned_commit_psbt = Self::get_psbt(&unsigned_commit_tx, &utxos, &source)?;
     let unsigned_commit_custom = Self::get_custom(&unsigned_commit_psbt);
 
+    let recovery_key = PrivateKey::new(
+      recovery_key_pair.to_inner().secret_key(),
+      options.chain().network(),
+    )
+    .to_wif();
+
     let output = Output {
       commit: serialize_hex(&unsigned_commit_psbt),
+      commit_txid: unsigned_commit_tx.txid(),
+      commit_inputs: unsigned_commit_tx
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .collect(),
       commit_custom: unsigned_commit_custom,
       reveal: reveal_txs
         .clone()
@@ -192,13 +244,25 @@ impl Mint {
       network_fee,
       commit_vsize,
       commit_fee,
+      fee_rate: fee_rate.rate(),
+      reveal_fee_rate: reveal_fee_rate.rate(),
+      recovery_key: vec![recovery_key],
     };
     log::info!("Build mint success");
     Ok(output)
   }
 
   pub fn run(self, options: Options) -> Result {
-    print_json(self.build(options, None, Some(Self::SERVICE_FEE), None)?)?;
+    let output = self.build(
+      &Index::read_open(&options)?,
+      None,
+      &options.fee_policy()?,
+      None,
+    )?;
+    let recovery_key = output.recovery_key.clone();
+    let mut output = serde_json::to_value(output)?;
+    output["recovery_key"] = recovery_key.into();
+    print_json(output)?;
     Ok(())
   }
 
@@ -250,7 +314,53 @@ impl Mint {
       .unwrap()
   }
 
-  fn create_inscription_transactions(
+  /// Reveal keys are derived from the operator master key at a hardened path
+  /// taken from the first outpoint spent by the commit transaction, so they
+  /// can be derived again from the commit transaction alone. Without a master
+  /// key a random key is used.
+  pub(crate) fn reveal_key_pair(
+    secp256k1: &Secp256k1<secp256k1::All>,
+    master_key: Option<ExtendedPrivKey>,
+    outpoint: OutPoint,
+  ) -> Result<UntweakedKeyPair> {
+    let Some(master_key) = master_key else {
+      return Ok(UntweakedKeyPair::new(secp256k1, &mut rand::thread_rng()));
+    };
+
+    let hash = sha256::Hash::hash(&serialize(&outpoint));
+
+    let path = hash[..8]
+      .chunks(4)
+      .map(|chunk| {
+        ChildNumber::from_hardened_idx(u32::from_be_bytes(chunk.try_into().unwrap()) & 0x7fff_ffff)
+      })
+      .collect::<Result<Vec<ChildNumber>, _>>()?;
+
+    Ok(
+      master_key
+        .derive_priv(secp256k1, &path)?
+        .to_keypair(secp256k1),
+    )
+  }
+
+  /// A reveal key derived from the master key can only be derived again if
+  /// the commit transaction spends the outpoint it was derived from first.
+  pub(crate) fn check_key_outpoint(
+    commit_tx: &Transaction,
+    master_key: Option<ExtendedPrivKey>,
+    key_outpoint: OutPoint,
+  ) -> Result {
+    if master_key.is_some() && commit_tx.input[0].previous_output != key_outpoint {
+      bail!(
+        "commit transaction spends {} first instead of reveal key outpoint {key_outpoint}",
+        commit_tx.input[0].previous_output
+      );
+    }
+
+    Ok(())
+  }
+
+  pub(crate) fn create_inscription_transactions(
     input_type: AddressType,
     satpoints: Vec<SatPoint>,
     inscription: Inscription,
@@ -266,7 +376,7 @@ impl Mint {
     repeat: usize,
     service_fee: Amount,
     target_postage: Amount,
-    additional_service_fee: Amount,
+    reveal_master_key: Option<ExtendedPrivKey>,
   ) -> Result<(Transaction, Vec<Transaction>, TweakedKeyPair, u64, u64, u64)> {
     let satpoints = if !satpoints.is_empty() {
       satpoints
@@ -283,75 +393,40 @@ impl Mint {
           outpoint: *outpoint,
           offset: 0,
         })
-        .ok_or_else(|| anyhow!("wallet contains no cardinal utxos"))?]
+        .ok_or(BuildError::InsufficientFunds {
+          required: target_postage * (repeat as u64) + service_fee,
+          available: Amount::ZERO,
+        })?]
     };
 
     for (inscribed_satpoint, inscription_id) in &inscriptions {
       for satpoint in &satpoints {
-        if inscribed_satpoint == satpoint {
-          return Err(anyhow!("sat at {} already inscribed", satpoint));
-        }
-
         if inscribed_satpoint.outpoint == satpoint.outpoint {
-          return Err(anyhow!(
-          "utxo {} already inscribed with inscription {inscription_id} on sat {inscribed_satpoint}",
-          satpoint.outpoint,
-        ));
+          bail!(BuildError::AlreadyInscribed {
+            satpoint: *inscribed_satpoint,
+            inscription_id: *inscription_id,
+          });
         }
       }
     }
 
     let secp256k1 = Secp256k1::new();
-    let key_pair = UntweakedKeyPair::new(&secp256k1, &mut rand::thread_rng());
-    let (public_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
-
-    let reveal_script = inscription.append_reveal_script(
-      script::Builder::new()
-        .push_slice(&public_key.serialize())
-        .push_opcode(opcodes::all::OP_CHECKSIG),
-    );
-
-    let taproot_spend_info = TaprootBuilder::new()
-      .add_leaf(0, reveal_script.clone())
-      .expect("adding leaf should work")
-      .finalize(&secp256k1, public_key)
-      .expect("finalizing taproot builder should work");
-
-    let control_block = taproot_spend_info
-      .control_block(&(reveal_script.clone(), LeafVersion::TapScript))
-      .expect("should compute control block");
+    let key_outpoint = satpoints[0].outpoint;
+    let key_pair = Self::reveal_key_pair(&secp256k1, reveal_master_key, key_outpoint)?;
+    let (reveal_script, taproot_spend_info, control_block) =
+      Self::reveal_spend_info(&secp256k1, &key_pair, &inscription);
 
     let commit_tx_address = Address::p2tr_tweaked(taproot_spend_info.output_key(), network);
 
     let mut reveal_fees: Vec<Amount> = vec![];
 
-    let service_fee = service_fee * (repeat as u64)  + additional_service_fee;
-
     let mut outputs = vec![];
     for i in 0..repeat {
-      let reveal_output = if i == 0 {
-        let mut tx_out = vec![TxOut {
-          script_pubkey: destination.script_pubkey(),
-          value: 0,
-        }];
-        if service_fee.to_sat() > 0 {
-          tx_out.push(TxOut {
-            script_pubkey: service_address.script_pubkey(),
-            value: 0,
-          });
-        }
-        tx_out
-      } else {
-        vec![TxOut {
-          script_pubkey: destination.script_pubkey(),
-          value: 0,
-        }]
-      };
       let (_, reveal_fee) = Self::build_reveal_transaction(
         &control_block,
         reveal_fee_rate,
         OutPoint::null(),
-        reveal_output,
+        Self::reveal_outputs(i, &destination, &service_address, service_fee, Amount::ZERO),
         &reveal_script,
       );
       reveal_fees.push(reveal_fee);
@@ -365,6 +440,9 @@ impl Mint {
       }
     }
 
+    let required = outputs.iter().map(|(_, amount)| *amount).sum::<Amount>();
+    let available = BuildError::cardinal_value(&utxos, &inscriptions);
+
     let unsigned_commit_tx = TransactionBuilder::build_transaction_with_value_v1(
       input_type,
       satpoints,
@@ -373,73 +451,48 @@ impl Mint {
       outputs,
       change,
       commit_fee_rate,
-    )?;
+    )
+    .map_err(|err| BuildError::from_transaction_builder(err, required, available))?;
+
+    Self::check_key_outpoint(&unsigned_commit_tx, reveal_master_key, key_outpoint)?;
 
     let mut reveal_txs: Vec<Transaction> = vec![];
 
     let satpoint_fee = (target_postage * (repeat as u64)).to_sat();
     let network_fee = reveal_fees.clone().into_iter().sum::<Amount>().to_sat();
-    let service_fee = service_fee.to_sat();
     for i in 0..repeat {
-      let reveal_output = if i == 0 {
-        let mut tx_out = vec![TxOut {
-          script_pubkey: destination.script_pubkey(),
-          value: target_postage.to_sat(),
-        }];
-        if service_fee > 0 {
-          tx_out.push(TxOut {
-            script_pubkey: service_address.script_pubkey(),
-            value: service_fee,
-          })
-        }
-        tx_out
-      } else {
-        vec![TxOut {
-          script_pubkey: destination.script_pubkey(),
-          value: target_postage.to_sat(),
-        }]
-      };
-
       let (txid, vout) = (unsigned_commit_tx.txid(), u32::try_from(i).unwrap());
 
       let (mut reveal_tx, _fee) = Self::build_reveal_transaction(
         &control_block,
         reveal_fee_rate,
         OutPoint { txid, vout },
-        reveal_output,
+        Self::reveal_outputs(
+          i,
+          &destination,
+          &service_address,
+          service_fee,
+          target_postage,
+        ),
         &reveal_script,
       );
 
       if reveal_tx.output[0].value < reveal_tx.output[0].script_pubkey.dust_value().to_sat() {
-        bail!("commit transaction output would be dust");
+        bail!(BuildError::Dust {
+          output_value: Amount::from_sat(reveal_tx.output[0].value),
+          dust_value: reveal_tx.output[0].script_pubkey.dust_value(),
+        });
       }
 
-      let mut sighash_cache = SighashCache::new(&mut reveal_tx);
-
-      let prevout = unsigned_commit_tx.output[i].clone();
-
-      let signature_hash = sighash_cache
-        .taproot_script_spend_signature_hash(
-          0,
-          &Prevouts::All(&[prevout]),
-          TapLeafHash::from_script(&reveal_script, LeafVersion::TapScript),
-          SchnorrSighashType::Default,
-        )
-        .expect("signature hash should compute");
-
-      let signature = secp256k1.sign_schnorr(
-        &secp256k1::Message::from_slice(signature_hash.as_inner())
-          .expect("should be cryptographically secure hash"),
+      Self::sign_reveal_transaction(
+        &secp256k1,
         &key_pair,
+        &mut reveal_tx,
+        unsigned_commit_tx.output[i].clone(),
+        &reveal_script,
+        &control_block,
       );
 
-      let witness = sighash_cache
-        .witness_mut(0)
-        .expect("getting mutable witness reference should work");
-      witness.push(signature.as_ref());
-      witness.push(reveal_script.clone());
-      witness.push(&control_block.serialize());
-
       let reveal_weight = reveal_tx.weight();
 
       if !no_limit && reveal_weight > MAX_STANDARD_TX_WEIGHT.try_into().unwrap() {
@@ -466,12 +519,148 @@ impl Mint {
       unsigned_commit_tx,
       reveal_txs,
       recovery_key_pair,
-      service_fee,
+      service_fee.to_sat(),
       satpoint_fee,
       network_fee,
     ))
   }
 
+  /// The fees `build` would charge for `repeat` inscriptions of
+  /// `content_size` bytes, without selecting coins. The commit transaction is
+  /// assumed to spend one coin of `address_type` and return change to the
+  /// same address, as `build` does whenever the first coin covers the mint.
+  /// `destination` and `service_address` default to an address of
+  /// `address_type`, like the source address they default to in `build`.
+  /// The service fee is the `fee_policy` mint fee without any whitelist
+  /// discount.
+  pub fn estimate(
+    options: &Options,
+    address_type: AddressType,
+    content_type: &str,
+    content_size: usize,
+    repeat: u64,
+    destination: Option<Address>,
+    target_postage: Amount,
+    fee_rate: FeeRate,
+    service_address: Option<Address>,
+    fee_policy: &FeePolicy,
+  ) -> Result<Estimate> {
+    let chain = options.chain();
+
+    if let Some(limit) = chain.inscription_content_size_limit() {
+      if content_size > limit {
+        bail!(
+          "content size of {content_size} bytes exceeds {limit} byte limit for {chain} inscriptions"
+        );
+      }
+    }
+
+    let source = placeholder_address(address_type, chain.network())?;
+    let destination = destination.unwrap_or_else(|| source.clone());
+    let service_address = service_address.unwrap_or_else(|| source.clone());
+
+    let dust_value = destination.script_pubkey().dust_value();
+    if target_postage < dust_value {
+      bail!(BuildError::Dust {
+        output_value: target_postage,
+        dust_value,
+      });
+    }
+
+    let inscription = Inscription::new(Some(content_type.into()), Some(vec![0; content_size]));
+
+    let secp256k1 = Secp256k1::new();
+    let key_pair = UntweakedKeyPair::new(&secp256k1, &mut rand::thread_rng());
+    let (reveal_script, taproot_spend_info, control_block) =
+      Self::reveal_spend_info(&secp256k1, &key_pair, &inscription);
+    let commit_tx_address = Address::p2tr_tweaked(taproot_spend_info.output_key(), chain.network());
+
+    let service_fee = fee_policy.service_fee(FeeMethod::Mint, repeat, target_postage, None);
+    let reveal_fee_rate = options.reveal_fee_policy.apply(fee_rate)?;
+    let reveal_fee = (0..usize::try_from(repeat)?)
+      .map(|i| {
+        Self::build_reveal_transaction(
+          &control_block,
+          reveal_fee_rate,
+          OutPoint::null(),
+          Self::reveal_outputs(i, &destination, &service_address, service_fee, Amount::ZERO),
+          &reveal_script,
+        )
+        .1
+      })
+      .sum::<Amount>();
+
+    let commit_vsize = TransactionBuilder::estimate_vbytes_with(
+      1,
+      address_type,
+      iter::repeat(commit_tx_address)
+        .take(usize::try_from(repeat)?)
+        .chain(iter::once(source))
+        .collect(),
+    );
+    let commit_fee = fee_rate.fee(commit_vsize);
+
+    Ok(Estimate {
+      service_fee: service_fee.to_sat(),
+      satpoint_fee: (target_postage * repeat).to_sat(),
+      network_fee: (commit_fee + reveal_fee).to_sat(),
+      commit_vsize: commit_vsize as u64,
+      commit_fee: commit_fee.to_sat(),
+      fee_rate: fee_rate.rate(),
+      reveal_fee_rate: reveal_fee_rate.rate(),
+    })
+  }
+
+  /// The script the reveal transactions spend the commit outputs with, the
+  /// commit outputs' taproot tree, and the control block for the script.
+  fn reveal_spend_info(
+    secp256k1: &Secp256k1<secp256k1::All>,
+    key_pair: &UntweakedKeyPair,
+    inscription: &Inscription,
+  ) -> (Script, TaprootSpendInfo, ControlBlock) {
+    let (public_key, _parity) = XOnlyPublicKey::from_keypair(key_pair);
+
+    let reveal_script = inscription.append_reveal_script(
+      script::Builder::new()
+        .push_slice(&public_key.serialize())
+        .push_opcode(opcodes::all::OP_CHECKSIG),
+    );
+
+    let taproot_spend_info = TaprootBuilder::new()
+      .add_leaf(0, reveal_script.clone())
+      .expect("adding leaf should work")
+      .finalize(secp256k1, public_key)
+      .expect("finalizing taproot builder should work");
+
+    let control_block = taproot_spend_info
+      .control_block(&(reveal_script.clone(), LeafVersion::TapScript))
+      .expect("should compute control block");
+
+    (reveal_script, taproot_spend_info, control_block)
+  }
+
+  /// Outputs of the `i`th reveal transaction: `postage` to `destination`,
+  /// and on the first one the whole service fee to `service_address`.
+  fn reveal_outputs(
+    i: usize,
+    destination: &Address,
+    service_address: &Address,
+    service_fee: Amount,
+    postage: Amount,
+  ) -> Vec<TxOut> {
+    let mut outputs = vec![TxOut {
+      script_pubkey: destination.script_pubkey(),
+      value: postage.to_sat(),
+    }];
+    if i == 0 && service_fee > Amount::ZERO {
+      outputs.push(TxOut {
+        script_pubkey: service_address.script_pubkey(),
+        value: service_fee.to_sat(),
+      });
+    }
+    outputs
+  }
+
   fn estimate_vsize(transaction: &Transaction, input_type: AddressType) -> usize {
     let mut modified_tx = transaction.clone();
     let witness_size = if input_type == AddressType::P2tr {
@@ -485,7 +674,43 @@ impl Mint {
     modified_tx.vsize()
   }
 
-  fn build_reveal_transaction(
+  /// Signs the script path spend of the commit output `prevout` by the only
+  /// input of `reveal_tx` and fills in its witness.
+  pub(crate) fn sign_reveal_transaction(
+    secp256k1: &Secp256k1<secp256k1::All>,
+    key_pair: &UntweakedKeyPair,
+    reveal_tx: &mut Transaction,
+    prevout: TxOut,
+    reveal_script: &Script,
+    control_block: &ControlBlock,
+  ) {
+    let mut sighash_cache = SighashCache::new(reveal_tx);
+
+    let signature_hash = sighash_cache
+      .taproot_script_spend_signature_hash(
+        0,
+        &Prevouts::All(&[prevout]),
+        TapLeafHash::from_script(reveal_script, LeafVersion::TapScript),
+        SchnorrSighashType::Default,
+      )
+      .expect("signature hash should compute");
+
+    let signature = secp256k1.sign_schnorr(
+      &secp256k1::Message::from_slice(signature_hash.as_inner())
+        .expect("should be cryptographically secure hash"),
+      key_pair,
+    );
+
+    let witness = sighash_cache
+      .witness_mut(0)
+      .expect("getting mutable witness reference should work");
+    *witness = Witness::new();
+    witness.push(signature.as_ref());
+    witness.push(reveal_script.clone());
+    witness.push(&control_block.serialize());
+  }
+
+  pub(crate) fn build_reveal_transaction(
     control_block: &ControlBlock,
     fee_rate: FeeRate,
     input: OutPoint,
@@ -521,3 +746,92 @@ impl Mint {
     (reveal_tx, fee)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn estimate_matches_build() {
+    for address_type in [AddressType::P2tr, AddressType::P2wpkh] {
+      let source = placeholder_address(address_type, Network::Bitcoin).unwrap();
+      let fee_rate = FeeRate::try_from(5.0).unwrap();
+      let utxos = [(outpoint(1), Amount::from_sat(100_000_000))]
+        .into_iter()
+        .collect::<BTreeMap<OutPoint, Amount>>();
+
+      let (commit_tx, _, _, service_fee, satpoint_fee, reveal_fee) =
+        Mint::create_inscription_transactions(
+          address_type,
+          vec![],
+          inscription("text/plain;charset=utf-8", [0; 100]),
+          BTreeMap::new(),
+          Network::Bitcoin,
+          utxos.clone(),
+          [source.clone(), source.clone()],
+          source.clone(),
+          fee_rate,
+          FeeRate::try_from(5.02).unwrap(),
+          false,
+          change(0),
+          2,
+          Amount::from_sat(6_000),
+          TransactionBuilder::TARGET_POSTAGE,
+          None,
+        )
+        .unwrap();
+
+      let commit_fee = Mint::calculate_fee(&commit_tx, &utxos);
+
+      assert_eq!(
+        Mint::estimate(
+          &Options::default(),
+          address_type,
+          "text/plain;charset=utf-8",
+          100,
+          2,
+          None,
+          TransactionBuilder::TARGET_POSTAGE,
+          fee_rate,
+          Some(change(0)),
+          &FeePolicy::default(),
+        )
+        .unwrap(),
+        Estimate {
+          service_fee,
+          satpoint_fee,
+          network_fee: commit_fee + reveal_fee,
+          commit_vsize: Mint::estimate_vsize(&commit_tx, address_type) as u64,
+          commit_fee,
+          fee_rate: 5.0,
+          reveal_fee_rate: 5.02,
+        }
+      );
+    }
+  }
+
+  #[test]
+  fn estimate_rejects_dust_postage() {
+    assert_eq!(
+      Mint::estimate(
+        &Options::default(),
+        AddressType::P2tr,
+        "text/plain;charset=utf-8",
+        100,
+        1,
+        None,
+        Amount::from_sat(100),
+        FeeRate::try_from(1.0).unwrap(),
+        None,
+        &FeePolicy::default(),
+      )
+      .unwrap_err()
+      .downcast::<BuildError>()
+      .unwrap(),
+      BuildError::Dust {
+        output_value: Amount::from_sat(100),
+        dust_value: Amount::from_sat(330),
+      }
+    );
+  }
+}
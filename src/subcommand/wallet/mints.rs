@@ -1,4 +1,4 @@
-use crate::index::{ConstructTransaction, MysqlDatabase, TransactionOutputArray};
+use crate::index::{ConstructTransaction, MirrorStore, Order, TransactionOutputArray};
 use bitcoin::psbt::Psbt;
 use bitcoin::{consensus::encode::serialize_hex, AddressType};
 use bitcoincore_rpc::RawTx;
@@ -7,13 +7,12 @@ use {
   bitcoin::{
     blockdata::{opcodes, script},
     policy::MAX_STANDARD_TX_WEIGHT,
-    schnorr::{TapTweak, TweakedKeyPair, TweakedPublicKey, UntweakedKeyPair},
-    secp256k1::{
-      self, constants::SCHNORR_SIGNATURE_SIZE, rand, schnorr::Signature, Secp256k1, XOnlyPublicKey,
-    },
-    util::sighash::{Prevouts, SighashCache},
-    util::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder},
-    PackedLockTime, SchnorrSighashType, Witness,
+    schnorr::{TapTweak, TweakedKeyPair, TweakedPublicKey},
+    secp256k1::{constants::SCHNORR_SIGNATURE_SIZE, schnorr::Signature, Secp256k1, XOnlyPublicKey},
+    util::bip32::ExtendedPrivKey,
+    util::key::PrivateKey,
+    util::taproot::{ControlBlock, LeafVersion, TaprootBuilder},
+    PackedLockTime, Witness,
   },
   std::collections::BTreeSet,
 };
@@ -22,6 +21,10 @@ use {
 pub struct Output {
   pub inscription: Vec<InscriptionId>,
   pub commit: String,
+  pub commit_txid: Txid,
+  /// Outputs spent by the commit transaction.
+  #[serde(skip)]
+  pub commit_inputs: Vec<OutPoint>,
   pub commit_custom: Vec<String>,
   pub reveal: Vec<String>,
   pub service_fee: u64,
@@ -29,12 +32,47 @@ pub struct Output {
   pub network_fee: u64,
   pub commit_vsize: u64,
   pub commit_fee: u64,
+  pub fee_rate: f64,
+  pub reveal_fee_rate: f64,
+  /// One recovery key per inscription, left out of responses.
+  #[serde(skip)]
+  pub recovery_key: Vec<String>,
+}
+
+impl Output {
+  /// The order to record for this output, built now for `source` to
+  /// inscribe to `destination`.
+  pub fn order(&self, source: Address, destination: Address) -> Order {
+    Order {
+      commit_txid: self.commit_txid,
+      reveal_txids: self
+        .inscription
+        .iter()
+        .map(|inscription_id| inscription_id.txid)
+        .collect(),
+      reveals: self.reveal.clone(),
+      inputs: self.commit_inputs.clone(),
+      source,
+      destination,
+      service_fee: self.service_fee,
+      satpoint_fee: self.satpoint_fee,
+      network_fee: self.network_fee,
+      commit_fee: self.commit_fee,
+      created_at: SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0),
+    }
+  }
 }
 
 #[derive(Debug, Parser)]
 pub struct Mint {
-  #[clap(long, help = "Use fee rate of <FEE_RATE> sats/vB")]
-  pub fee_rate: FeeRate,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
   #[clap(long, help = "Send inscription to <DESTINATION>.")]
   pub destination: Option<Address>,
   #[clap(long, help = "Send inscription from <SOURCE>.")]
@@ -50,15 +88,15 @@ pub struct Mint {
 }
 
 impl Mint {
-  pub const SERVICE_FEE: Amount = Amount::from_sat(3000);
-
   pub fn build(
     self,
-    options: Options,
+    index: &Index,
     service_address: Option<Address>,
-    service_fee: Option<Amount>,
-    mysql: Option<Arc<MysqlDatabase>>,
+    fee_policy: &FeePolicy,
+    mirror: Option<Arc<dyn MirrorStore>>,
   ) -> Result<Output> {
+    let options = index.options();
+
     let extension = "data.".to_owned() + &self.extension.unwrap_or(".txt".to_owned());
 
     let mut inscription = vec![];
@@ -70,10 +108,6 @@ impl Mint {
       )?);
     }
 
-    log::info!("Open index...");
-    let index = Index::read_open(&options)?;
-    // index.update()?;
-
     let source = self.source;
     let reveal_tx_destination = self.destination.unwrap_or_else(|| source.clone());
 
@@ -89,26 +123,13 @@ impl Mint {
     }
 
     // check address types, only support p2tr and p2wpkh
-    let address_type = if let Some(address_type) = source.address_type() {
-      if (address_type == AddressType::P2tr) || (address_type == AddressType::P2wpkh) {
-        address_type
-      } else {
-        bail!(
-          "Address type `{}` is not valid, only support p2tr and p2wpkh",
-          address_type
-        );
-      }
-    } else {
-      bail!("Address `{}` is not valid for {}", source, options.chain());
-    };
+    let address_type = BuildError::check_address_type(&source)?;
 
     let service_address = service_address.unwrap_or(source.clone());
 
     log::info!("Get utxo...");
     let query_address = &format!("{}", source);
-    let mut additional_service_fee = Amount::ZERO;
     let (utxos, satpoints) = if let Some(commit_id) = self.remint {
-      additional_service_fee = Amount::from_sat(3000);
       let (utxos, recommit_tx) =
         index.get_unspent_outputs_by_commit_id(query_address, BTreeMap::new(), commit_id)?;
       (
@@ -129,11 +150,11 @@ impl Mint {
       )
     };
 
-    let mut is_whitelist = false;
-    let inscriptions = if let Some(mysql) = mysql {
-      log::info!("Get inscriptions by mysql...");
-      is_whitelist = mysql.is_whitelist(query_address);
-      mysql.get_inscription_by_address(query_address)?
+    let mut whitelist_level = None;
+    let inscriptions = if let Some(mirror) = mirror {
+      log::info!("Get inscriptions by mirror...");
+      whitelist_level = mirror.whitelist_level(query_address);
+      mirror.get_inscription_by_address(query_address)?
     } else {
       log::info!("Get inscriptions by redb...");
       index.get_inscriptions(None)?
@@ -141,37 +162,37 @@ impl Mint {
 
     let commit_tx_change = [source.clone(), source.clone()];
 
-    let service_fee = if is_whitelist {
-      Amount::ZERO
-    } else {
-      service_fee.unwrap_or(Self::SERVICE_FEE)
-    };
-
-    let reveal_fee_rate = FeeRate::try_from(self.fee_rate.0 + 0.02)?;
-    let (
-      unsigned_commit_tx,
-      reveal_txs,
-      _recovery_key_pair,
-      service_fee,
-      satpoint_fee,
-      network_fee,
-    ) = Mint::create_inscription_transactions(
-      address_type,
-      satpoints,
-      inscription,
-      inscriptions,
-      options.chain().network(),
-      utxos.clone(),
-      commit_tx_change,
-      reveal_tx_destination,
-      self.fee_rate,
-      reveal_fee_rate,
-      false,
-      service_address,
-      service_fee,
+    let service_fee = fee_policy.service_fee(
+      if self.remint.is_some() {
+        FeeMethod::Remints
+      } else {
+        FeeMethod::Mints
+      },
+      u64::try_from(inscription.len())?,
       self.target_postage,
-      additional_service_fee,
-    )?;
+      whitelist_level.as_deref(),
+    );
+
+    let fee_rate = index.fee_rate(self.fee_rate)?;
+    let reveal_fee_rate = options.reveal_fee_policy.apply(fee_rate)?;
+    let (unsigned_commit_tx, reveal_txs, recovery_key_pair, service_fee, satpoint_fee, network_fee) =
+      Mint::create_inscription_transactions(
+        address_type,
+        satpoints,
+        inscription,
+        inscriptions,
+        options.chain().network(),
+        utxos.clone(),
+        commit_tx_change,
+        reveal_tx_destination,
+        fee_rate,
+        reveal_fee_rate,
+        false,
+        service_address,
+        service_fee,
+        self.target_postage,
+        options.reveal_master_key,
+      )?;
 
     let commit_vsize = Self::estimate_vsize(&unsigned_commit_tx, address_type) as u64;
     let commit_fee = Self::calculate_fee(&unsigned_commit_tx, &utxos);
@@ -181,8 +202,21 @@ impl Mint {
     let unsigned_commit_psbt = Self::get_psbt(&unsigned_commit_tx, &utxos, &source)?;
     let unsigned_commit_custom = Self::get_custom(&unsigned_commit_psbt);
 
+    let recovery_key = recovery_key_pair
+      .into_iter()
+      .map(|key_pair| {
+        PrivateKey::new(key_pair.to_inner().secret_key(), options.chain().network()).to_wif()
+      })
+      .collect();
+
     let output = Output {
       commit: serialize_hex(&unsigned_commit_psbt),
+      commit_txid: unsigned_commit_tx.txid(),
+      commit_inputs: unsigned_commit_tx
+        .input
+        .iter()
+        .map(|input| input.previous_output)
+        .collect(),
       commit_custom: unsigned_commit_custom,
       reveal: reveal_txs
         .clone()
@@ -195,13 +229,25 @@ impl Mint {
       network_fee,
       commit_vsize,
       commit_fee,
+      fee_rate: fee_rate.rate(),
+      reveal_fee_rate: reveal_fee_rate.rate(),
+      recovery_key,
     };
     log::info!("Build mint success");
     Ok(output)
   }
 
   pub fn run(self, options: Options) -> Result {
-    print_json(self.build(options, None, Some(Self::SERVICE_FEE), None)?)?;
+    let output = self.build(
+      &Index::read_open(&options)?,
+      None,
+      &options.fee_policy()?,
+      None,
+    )?;
+    let recovery_key = output.recovery_key.clone();
+    let mut output = serde_json::to_value(output)?;
+    output["recovery_key"] = recovery_key.into();
+    print_json(output)?;
     Ok(())
   }
 
@@ -268,7 +314,7 @@ impl Mint {
     service_address: Address,
     service_fee: Amount,
     target_postage: Amount,
-    additional_service_fee: Amount,
+    reveal_master_key: Option<ExtendedPrivKey>,
   ) -> Result<(
     Transaction,
     Vec<Transaction>,
@@ -292,26 +338,26 @@ impl Mint {
           outpoint: *outpoint,
           offset: 0,
         })
-        .ok_or_else(|| anyhow!("wallet contains no cardinal utxos"))?]
+        .ok_or(BuildError::InsufficientFunds {
+          required: target_postage * (inscription.len() as u64) + service_fee,
+          available: Amount::ZERO,
+        })?]
     };
 
     for (inscribed_satpoint, inscription_id) in &inscriptions {
       for satpoint in &satpoints {
-        if inscribed_satpoint == satpoint {
-          return Err(anyhow!("sat at {} already inscribed", satpoint));
-        }
-
         if inscribed_satpoint.outpoint == satpoint.outpoint {
-          return Err(anyhow!(
-          "utxo {} already inscribed with inscription {inscription_id} on sat {inscribed_satpoint}",
-          satpoint.outpoint,
-        ));
+          bail!(BuildError::AlreadyInscribed {
+            satpoint: *inscribed_satpoint,
+            inscription_id: *inscription_id,
+          });
         }
       }
     }
 
     let secp256k1 = Secp256k1::new();
-    let key_pair = UntweakedKeyPair::new(&secp256k1, &mut rand::thread_rng());
+    let key_outpoint = satpoints[0].outpoint;
+    let key_pair = mint::Mint::reveal_key_pair(&secp256k1, reveal_master_key, key_outpoint)?;
     let (public_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
 
     let mut reveal_script = vec![];
@@ -357,8 +403,6 @@ impl Mint {
 
     let mut reveal_fees: Vec<Amount> = vec![];
 
-    let service_fee = service_fee * (repeat as u64) + additional_service_fee;
-
     let mut outputs = vec![];
     for i in 0..repeat {
       let reveal_output = if i == 0 {
@@ -398,6 +442,9 @@ impl Mint {
     }
     reveal_fees.reverse();
 
+    let required = outputs.iter().map(|(_, amount)| *amount).sum::<Amount>();
+    let available = BuildError::cardinal_value(&utxos, &inscriptions);
+
     let unsigned_commit_tx = TransactionBuilder::build_transaction_with_value_v1(
       input_type,
       satpoints,
@@ -406,7 +453,10 @@ impl Mint {
       outputs,
       change,
       commit_fee_rate,
-    )?;
+    )
+    .map_err(|err| BuildError::from_transaction_builder(err, required, available))?;
+
+    mint::Mint::check_key_outpoint(&unsigned_commit_tx, reveal_master_key, key_outpoint)?;
 
     let mut reveal_txs: Vec<Transaction> = vec![];
 
@@ -444,35 +494,21 @@ impl Mint {
       );
 
       if reveal_tx.output[0].value < reveal_tx.output[0].script_pubkey.dust_value().to_sat() {
-        bail!("commit transaction output would be dust");
+        bail!(BuildError::Dust {
+          output_value: Amount::from_sat(reveal_tx.output[0].value),
+          dust_value: reveal_tx.output[0].script_pubkey.dust_value(),
+        });
       }
 
-      let mut sighash_cache = SighashCache::new(&mut reveal_tx);
-
-      let prevout = unsigned_commit_tx.output[i].clone();
-
-      let signature_hash = sighash_cache
-        .taproot_script_spend_signature_hash(
-          0,
-          &Prevouts::All(&[prevout]),
-          TapLeafHash::from_script(&reveal_script[i], LeafVersion::TapScript),
-          SchnorrSighashType::Default,
-        )
-        .expect("signature hash should compute");
-
-      let signature = secp256k1.sign_schnorr(
-        &secp256k1::Message::from_slice(signature_hash.as_inner())
-          .expect("should be cryptographically secure hash"),
+      mint::Mint::sign_reveal_transaction(
+        &secp256k1,
         &key_pair,
+        &mut reveal_tx,
+        unsigned_commit_tx.output[i].clone(),
+        &reveal_script[i],
+        &control_block[i],
       );
 
-      let witness = sighash_cache
-        .witness_mut(0)
-        .expect("getting mutable witness reference should work");
-      witness.push(signature.as_ref());
-      witness.push(reveal_script[i].clone());
-      witness.push(&control_block[i].serialize());
-
       let reveal_weight = reveal_tx.weight();
 
       if !no_limit && reveal_weight > MAX_STANDARD_TX_WEIGHT.try_into().unwrap() {
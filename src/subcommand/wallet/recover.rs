@@ -0,0 +1,305 @@
+use super::*;
+use bitcoin::blockdata::{script, witness::Witness};
+use bitcoin::schnorr::TweakedPublicKey;
+use bitcoin::secp256k1::{self, KeyPair, Secp256k1, XOnlyPublicKey};
+use bitcoin::util::key::PrivateKey;
+use bitcoin::util::sighash::{Prevouts, SighashCache};
+use bitcoin::{PackedLockTime, SchnorrSighashType};
+use bitcoincore_rpc::RawTx;
+
+#[derive(Debug, Parser)]
+pub struct Recover {
+  #[clap(long, help = "Recover outputs of commit transaction <COMMIT>.")]
+  pub commit: Txid,
+  #[clap(long, help = "Send recovered outputs to <DESTINATION>.")]
+  pub destination: Address,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
+  #[clap(long, help = "Sign with commit tx recovery key <RECOVERY_KEY>.")]
+  pub recovery_key: Vec<PrivateKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Output {
+  pub transaction: String,
+  pub txid: Txid,
+  pub recovered: Vec<OutPoint>,
+  pub network_fee: u64,
+  pub fee_rate: f64,
+}
+
+impl Recover {
+  pub fn build(self, index: &Index) -> Result<Output> {
+    let options = index.options();
+
+    if !self
+      .destination
+      .is_valid_for_network(options.chain().network())
+    {
+      bail!(
+        "Address `{}` is not valid for {}",
+        self.destination,
+        options.chain()
+      );
+    }
+
+    log::info!("Get commit transaction...");
+    let commit_tx = index.get_transaction_by_mempool(self.commit)?;
+
+    let fee_rate = index.fee_rate(self.fee_rate)?;
+    let output = self.sweep(&commit_tx, fee_rate, |outpoint| {
+      index.is_output_spent_by_mempool(outpoint)
+    })?;
+
+    log::info!("Build recover success");
+
+    Ok(output)
+  }
+
+  /// Signs a transaction sending every unspent output of `commit_tx` locked
+  /// to one of the recovery keys to the destination.
+  fn sweep(
+    &self,
+    commit_tx: &Transaction,
+    fee_rate: FeeRate,
+    is_spent: impl Fn(OutPoint) -> Result<bool>,
+  ) -> Result<Output> {
+    let network = self.destination.network;
+    let secp256k1 = Secp256k1::new();
+
+    let key_pairs = self
+      .recovery_key
+      .iter()
+      .map(|private_key| {
+        let key_pair = KeyPair::from_secret_key(&secp256k1, &private_key.inner);
+        let (x_only_pub_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
+        let script_pubkey = Address::p2tr_tweaked(
+          TweakedPublicKey::dangerous_assume_tweaked(x_only_pub_key),
+          network,
+        )
+        .script_pubkey();
+        (script_pubkey, key_pair)
+      })
+      .collect::<Vec<(Script, KeyPair)>>();
+
+    let mut inputs = vec![];
+    let mut prevouts = vec![];
+    let mut signers = vec![];
+    for (vout, output) in commit_tx.output.iter().enumerate() {
+      let Some((_, key_pair)) = key_pairs
+        .iter()
+        .find(|(script_pubkey, _)| *script_pubkey == output.script_pubkey) else {
+        continue;
+      };
+
+      let outpoint = OutPoint {
+        txid: self.commit,
+        vout: vout.try_into().unwrap(),
+      };
+
+      if is_spent(outpoint)? {
+        log::info!("Commit output {outpoint} already spent");
+        continue;
+      }
+
+      inputs.push(outpoint);
+      prevouts.push(output.clone());
+      signers.push(*key_pair);
+    }
+
+    if inputs.is_empty() {
+      bail!(
+        "commit transaction {} has no unspent outputs for the given recovery keys",
+        self.commit
+      );
+    }
+
+    let (mut recover_tx, network_fee) =
+      Self::build_recover_transaction(fee_rate, &inputs, &self.destination);
+
+    let input_amount = prevouts.iter().map(|prevout| prevout.value).sum::<u64>();
+    let dust_value = self.destination.script_pubkey().dust_value().to_sat();
+    if input_amount < network_fee + dust_value {
+      bail!(BuildError::InsufficientFunds {
+        required: Amount::from_sat(network_fee + dust_value),
+        available: Amount::from_sat(input_amount),
+      });
+    }
+    recover_tx.output[0].value = input_amount - network_fee;
+
+    let mut sighash_cache = SighashCache::new(&mut recover_tx);
+    for (i, key_pair) in signers.iter().enumerate() {
+      let signature_hash = sighash_cache
+        .taproot_key_spend_signature_hash(i, &Prevouts::All(&prevouts), SchnorrSighashType::Default)
+        .expect("signature hash should compute");
+
+      let signature = secp256k1.sign_schnorr(
+        &secp256k1::Message::from_slice(signature_hash.as_inner())
+          .expect("should be cryptographically secure hash"),
+        key_pair,
+      );
+
+      let witness = sighash_cache
+        .witness_mut(i)
+        .expect("getting mutable witness reference should work");
+      witness.push(signature.as_ref());
+    }
+
+    Ok(Output {
+      txid: recover_tx.txid(),
+      transaction: recover_tx.raw_hex(),
+      recovered: inputs,
+      network_fee,
+      fee_rate: fee_rate.rate(),
+    })
+  }
+
+  pub fn run(self, options: Options) -> Result {
+    print_json(self.build(&Index::read_open(&options)?)?)?;
+    Ok(())
+  }
+
+  fn build_recover_transaction(
+    fee_rate: FeeRate,
+    inputs: &[OutPoint],
+    destination: &Address,
+  ) -> (Transaction, u64) {
+    let mut recover_tx = Transaction {
+      input: inputs
+        .iter()
+        .map(|item| TxIn {
+          previous_output: *item,
+          script_sig: script::Builder::new().into_script(),
+          witness: Witness::from_vec(vec![vec![0; TransactionBuilder::SCHNORR_SIGNATURE_SIZE]]),
+          sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        })
+        .collect(),
+      output: vec![TxOut {
+        script_pubkey: destination.script_pubkey(),
+        value: 0,
+      }],
+      lock_time: PackedLockTime::ZERO,
+      version: 1,
+    };
+
+    let fee = fee_rate.fee(recover_tx.vsize());
+
+    for input in &mut recover_tx.input {
+      input.witness = Witness::new();
+    }
+
+    (recover_tx, fee.to_sat())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    bitcoin::{consensus::encode::deserialize, hashes::hex::FromHex, schnorr::TapTweak},
+  };
+
+  #[test]
+  fn sweep_signs_commit_outputs_with_tweaked_key() {
+    let secp256k1 = Secp256k1::new();
+    let key_pair = KeyPair::new(&secp256k1, &mut secp256k1::rand::thread_rng());
+    let tweaked = key_pair.tap_tweak(&secp256k1, None).to_inner();
+    let (internal_key, _parity) = XOnlyPublicKey::from_keypair(&key_pair);
+    let commit_script =
+      Address::p2tr(&secp256k1, internal_key, None, Network::Testnet).script_pubkey();
+
+    let commit_tx = Transaction {
+      version: 2,
+      lock_time: PackedLockTime::ZERO,
+      input: vec![tx_in(outpoint(1))],
+      output: vec![
+        tx_out(10_000, recipient()),
+        TxOut {
+          value: 20_000,
+          script_pubkey: commit_script.clone(),
+        },
+        TxOut {
+          value: 30_000,
+          script_pubkey: commit_script,
+        },
+      ],
+    };
+    let commit = commit_tx.txid();
+
+    let recover = Recover {
+      commit,
+      destination: recipient(),
+      fee_rate: None,
+      recovery_key: vec![PrivateKey::new(tweaked.secret_key(), Network::Testnet)],
+    };
+
+    let output = recover
+      .sweep(&commit_tx, FeeRate::try_from(2.0).unwrap(), |outpoint| {
+        Ok(outpoint.vout == 2)
+      })
+      .unwrap();
+
+    assert_eq!(
+      output.recovered,
+      [OutPoint {
+        txid: commit,
+        vout: 1
+      }]
+    );
+
+    let recover_tx: Transaction =
+      deserialize(&Vec::from_hex(&output.transaction).unwrap()).unwrap();
+    assert_eq!(recover_tx.txid(), output.txid);
+    assert_eq!(
+      recover_tx.output[0].script_pubkey,
+      recipient().script_pubkey()
+    );
+    assert_eq!(recover_tx.output[0].value, 20_000 - output.network_fee);
+
+    let signature_hash = SighashCache::new(&recover_tx)
+      .taproot_key_spend_signature_hash(
+        0,
+        &Prevouts::All(&[commit_tx.output[1].clone()]),
+        SchnorrSighashType::Default,
+      )
+      .unwrap();
+    let signature =
+      secp256k1::schnorr::Signature::from_slice(&recover_tx.input[0].witness.to_vec()[0]).unwrap();
+    let (output_key, _parity) = XOnlyPublicKey::from_keypair(&tweaked);
+
+    secp256k1
+      .verify_schnorr(
+        &signature,
+        &secp256k1::Message::from_slice(signature_hash.as_inner()).unwrap(),
+        &output_key,
+      )
+      .unwrap();
+  }
+
+  #[test]
+  fn sweep_requires_matching_unspent_output() {
+    let recover = Recover {
+      commit: txid(1),
+      destination: recipient(),
+      fee_rate: None,
+      recovery_key: vec![PrivateKey::new(
+        secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+        Network::Testnet,
+      )],
+    };
+
+    let commit_tx = Transaction {
+      version: 2,
+      lock_time: PackedLockTime::ZERO,
+      input: vec![tx_in(outpoint(1))],
+      output: vec![tx_out(10_000, recipient())],
+    };
+
+    assert!(recover
+      .sweep(&commit_tx, FeeRate::try_from(1.0).unwrap(), |_| Ok(false))
+      .is_err());
+  }
+}
@@ -668,7 +668,7 @@ impl TransactionBuilder {
     }
   }
 
-  fn estimate_vbytes_with_op_return(
+  pub(crate) fn estimate_vbytes_with_op_return(
     inputs: usize,
     input_type: AddressType,
     outputs: Vec<Address>,
@@ -706,7 +706,11 @@ impl TransactionBuilder {
     tx.vsize()
   }
 
-  fn estimate_vbytes_with(inputs: usize, input_type: AddressType, outputs: Vec<Address>) -> usize {
+  pub(crate) fn estimate_vbytes_with(
+    inputs: usize,
+    input_type: AddressType,
+    outputs: Vec<Address>,
+  ) -> usize {
     let witness_size = if input_type == AddressType::P2tr {
       TransactionBuilder::SCHNORR_SIGNATURE_SIZE
     } else {
@@ -1,5 +1,5 @@
 use super::*;
-use crate::index::{ConstructTransaction, MysqlDatabase, TransactionOutputArray};
+use crate::index::{ConstructTransaction, MirrorStore, TransactionOutputArray};
 use bitcoin::consensus::encode::serialize_hex;
 use bitcoin::psbt::Psbt;
 use bitcoin::AddressType;
@@ -12,8 +12,11 @@ pub struct Transfer {
   #[clap(long, help = "Send inscription from <SOURCE>.")]
   pub source: Address,
   pub outgoing: Outgoing,
-  #[clap(long, help = "Use fee rate of <FEE_RATE> sats/vB")]
-  pub fee_rate: FeeRate,
+  #[clap(
+    long,
+    help = "Use fee rate of <FEE_RATE> sats/vB. Defaults to Bitcoin Core's estimate."
+  )]
+  pub fee_rate: Option<FeeRate>,
   #[clap(long, help = "Allow <OP_RETURN>.")]
   pub op_return: Option<String>,
   #[clap(long, help = "Whether to transfer brc20.")]
@@ -28,10 +31,22 @@ pub struct Output {
   pub transaction: String,
   pub commit_custom: Vec<String>,
   pub network_fee: u64,
+  pub fee_rate: f64,
+}
+
+/// The network fee of a transfer as `Output` would show it, quoted by
+/// `Transfer::estimate`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+  pub network_fee: u64,
+  pub vsize: u64,
+  pub fee_rate: f64,
 }
 
 impl Transfer {
-  pub fn build(self, options: Options, mysql: Option<Arc<MysqlDatabase>>) -> Result<Output> {
+  pub fn build(self, index: &Index, mirror: Option<Arc<dyn MirrorStore>>) -> Result<Output> {
+    let options = index.options();
+
     if !self
       .destination
       .is_valid_for_network(options.chain().network())
@@ -51,34 +66,16 @@ impl Transfer {
     }
 
     // check address types, only support p2tr and p2wpkh
-    let address_type = if let Some(address_type) = self.source.address_type() {
-      if (address_type == AddressType::P2tr) || (address_type == AddressType::P2wpkh) {
-        address_type
-      } else {
-        bail!(
-          "Address type `{}` is not valid, only support p2tr and p2wpkh",
-          address_type
-        );
-      }
-    } else {
-      bail!(
-        "Address `{}` is not valid for {}",
-        self.source,
-        options.chain()
-      );
-    };
+    let address_type = BuildError::check_address_type(&self.source)?;
 
     let brc20_transfer = self.brc20_transfer.unwrap_or(false);
-    log::info!("Open index...");
-    let index = Index::read_open(&options)?;
-    // index.update()?;
 
     log::info!("Get utxo...");
     let query_address = &format!("{}", self.source);
 
-    let inscriptions = if let Some(mysql) = mysql {
-      log::info!("Get inscriptions by mysql...");
-      mysql.get_inscription_by_address(query_address)?
+    let inscriptions = if let Some(mirror) = mirror {
+      log::info!("Get inscriptions by mirror...");
+      mirror.get_inscription_by_address(query_address)?
     } else {
       log::info!("Get inscriptions by redb...");
       index.get_inscriptions(None)?
@@ -167,14 +164,14 @@ impl Transfer {
         } else {
           let satpoint = index
             .get_inscription_satpoint_by_id(id)?
-            .ok_or_else(|| anyhow!("Inscription {id} not found"))?;
+            .ok_or(BuildError::InscriptionNotFound(id))?;
           let mut satpoints = vec![satpoint];
 
           for item in &self.addition_outgoing {
             if let Outgoing::InscriptionId(id) = *item {
               let satpoint = index
                 .get_inscription_satpoint_by_id(id)?
-                .ok_or_else(|| anyhow!("Inscription {id} not found"))?;
+                .ok_or(BuildError::InscriptionNotFound(id))?;
               satpoints.push(satpoint)
             } else {
               bail!("Addition outgoing must be satpoint");
@@ -202,11 +199,18 @@ impl Transfer {
             outpoint: *outpoint,
             offset: 0,
           })
-          .ok_or_else(|| anyhow!("wallet contains no cardinal utxos"))?;
+          .ok_or(BuildError::InsufficientFunds {
+            required: amount + self.addition_fee,
+            available: Amount::ZERO,
+          })?;
         (vec![satpoint], amount + self.addition_fee, unspent_outputs)
       }
     };
 
+    let available = BuildError::cardinal_value(&unspent_outputs, &inscriptions);
+
+    let fee_rate = index.fee_rate(self.fee_rate)?;
+
     let unsigned_transaction = if let Some(op_return) = self.op_return {
       TransactionBuilder::build_transaction_with_op_return_v1(
         address_type,
@@ -215,9 +219,10 @@ impl Transfer {
         unspent_outputs.clone(),
         vec![(self.destination, amount)],
         change,
-        self.fee_rate,
+        fee_rate,
         op_return,
-      )?
+      )
+      .map_err(|err| BuildError::from_transaction_builder(err, amount, available))?
     } else {
       TransactionBuilder::build_transaction_with_value_v1(
         address_type,
@@ -226,8 +231,9 @@ impl Transfer {
         unspent_outputs.clone(),
         vec![(self.destination, amount)],
         change,
-        self.fee_rate,
-      )?
+        fee_rate,
+      )
+      .map_err(|err| BuildError::from_transaction_builder(err, amount, available))?
     };
 
     let network_fee = Self::calculate_fee(&unsigned_transaction, &unspent_outputs);
@@ -242,11 +248,45 @@ impl Transfer {
       transaction: serialize_hex(&unsigned_transaction_psbt),
       commit_custom: unsigned_commit_custom,
       network_fee,
+      fee_rate: fee_rate.rate(),
+    })
+  }
+
+  /// The network fee `build` would charge to send `count` inscriptions to
+  /// `destination`, without selecting coins. Each inscription is assumed to
+  /// sit on its own postage-sized output, so one more coin of `address_type`
+  /// pays the fee and change goes back to the same address. `destination`
+  /// defaults to an address of `address_type`.
+  pub fn estimate(
+    network: Network,
+    address_type: AddressType,
+    destination: Option<Address>,
+    count: usize,
+    op_return: Option<String>,
+    fee_rate: FeeRate,
+  ) -> Result<Estimate> {
+    let source = placeholder_address(address_type, network)?;
+    let outputs = vec![destination.unwrap_or_else(|| source.clone()), source];
+
+    let vsize = match op_return {
+      Some(op_return) => TransactionBuilder::estimate_vbytes_with_op_return(
+        count + 1,
+        address_type,
+        outputs,
+        op_return.into_bytes(),
+      ),
+      None => TransactionBuilder::estimate_vbytes_with(count + 1, address_type, outputs),
+    };
+
+    Ok(Estimate {
+      network_fee: fee_rate.fee(vsize).to_sat(),
+      vsize: vsize as u64,
+      fee_rate: fee_rate.rate(),
     })
   }
 
   pub fn run(self, options: Options) -> Result {
-    print_json(self.build(options, None)?)?;
+    print_json(self.build(&Index::read_open(&options)?, None)?)?;
     Ok(())
   }
 
@@ -298,3 +338,46 @@ impl Transfer {
       .unwrap()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn estimate_matches_build() {
+    let source = placeholder_address(AddressType::P2tr, Network::Bitcoin).unwrap();
+    let fee_rate = FeeRate::try_from(5.0).unwrap();
+    let utxos = [
+      (outpoint(1), TransactionBuilder::TARGET_POSTAGE),
+      (outpoint(2), Amount::from_sat(100_000_000)),
+    ]
+    .into_iter()
+    .collect::<BTreeMap<OutPoint, Amount>>();
+
+    let transaction = TransactionBuilder::build_transaction_with_value_v1(
+      AddressType::P2tr,
+      vec![satpoint(1, 0)],
+      [(satpoint(1, 0), inscription_id(1))].into_iter().collect(),
+      utxos.clone(),
+      vec![(recipient(), TransactionBuilder::TARGET_POSTAGE)],
+      [source.clone(), source],
+      fee_rate,
+    )
+    .unwrap();
+
+    assert_eq!(transaction.input.len(), 2);
+    assert_eq!(
+      Transfer::estimate(
+        Network::Bitcoin,
+        AddressType::P2tr,
+        Some(recipient()),
+        1,
+        None,
+        fee_rate,
+      )
+      .unwrap()
+      .network_fee,
+      Transfer::calculate_fee(&transaction, &utxos)
+    );
+  }
+}
@@ -1,8 +1,7 @@
-use bitcoin::Network;
-use clap::{Arg, Command};
-use log::{error, info};
+use clap::{value_parser, Arg, Command};
+use log::{error, info, warn};
 use ord::chain::Chain;
-use ord::index::{Index, MysqlDatabase};
+use ord::index::{Index, MirrorStore, MysqlDatabase, SqliteDatabase};
 use ord::options::Options;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
@@ -17,6 +16,7 @@ fn main() {
       Arg::new("chain")
         .long("chain")
         .takes_value(true)
+        .value_parser(value_parser!(Chain))
         .default_value("test")
         .help("Sets the chain"),
     )
@@ -56,6 +56,11 @@ fn main() {
         .takes_value(true)
         .help("Wait to start up."),
     )
+    .arg(
+      Arg::new("index-addresses")
+        .long("index-addresses")
+        .help("Track unspent outputs of all addresses."),
+    )
     .arg(
       Arg::new("mysql-host")
         .long("mysql-host")
@@ -73,27 +78,41 @@ fn main() {
         .long("mysql-password")
         .takes_value(true)
         .help("Mysql password."),
+    )
+    .arg(
+      Arg::new("mysql-database")
+        .long("mysql-database")
+        .takes_value(true)
+        .help("Mirror inscriptions to mysql database <MYSQL_DATABASE>. Defaults to ord_<CHAIN>."),
+    )
+    .arg(
+      Arg::new("sqlite")
+        .long("sqlite")
+        .takes_value(true)
+        .conflicts_with("mysql-host")
+        .help("Mirror inscriptions to SQLite database at <SQLITE>."),
+    )
+    .arg(
+      Arg::new("backfill")
+        .long("backfill")
+        .help("Rebuild the mirror from the index, resuming an interrupted backfill, and exit."),
+    )
+    .arg(
+      Arg::new("verify")
+        .long("verify")
+        .conflicts_with("backfill")
+        .help("Report inscriptions whose mirror rows disagree with the index, and exit."),
+    )
+    .arg(
+      Arg::new("repair")
+        .long("repair")
+        .requires("verify")
+        .help("Rewrite the mirror rows reported by --verify from the index."),
     );
 
   let matches = args.get_matches();
-  let chain = matches
-    .get_one::<String>("chain")
-    .map(|s| s.as_str())
-    .unwrap();
-
-  let chain_argument = match chain {
-    "main" => Chain::Mainnet,
-    "regtest" => Chain::Regtest,
-    "signet" => Chain::Signet,
-    _ => Chain::Testnet,
-  };
-
-  let network = match chain {
-    "main" => Network::Bitcoin,
-    "regtest" => Network::Regtest,
-    "signet" => Network::Signet,
-    _ => Network::Testnet,
-  };
+  let chain_argument = *matches.get_one::<Chain>("chain").unwrap();
+  let network = chain_argument.network();
 
   let bitcoin_data_dir: Option<PathBuf> = matches
     .get_one::<String>("bitcoin-data-dir")
@@ -109,9 +128,17 @@ fn main() {
     .get_one::<String>("wait-start")
     .map(|s| s.parse().unwrap_or(0));
 
+  let index_addresses = matches.contains_id("index-addresses");
+
+  let backfill = matches.contains_id("backfill");
+  let verify = matches.contains_id("verify");
+  let repair = matches.contains_id("repair");
+
   let mysql_host = matches.get_one::<String>("mysql-host").cloned();
   let mysql_username = matches.get_one::<String>("mysql-username").cloned();
   let mysql_password = matches.get_one::<String>("mysql-password").cloned();
+  let mysql_database = matches.get_one::<String>("mysql-database").cloned();
+  let sqlite: Option<PathBuf> = matches.get_one::<String>("sqlite").map(|s| s.into());
 
   if let Some(w) = wait_start {
     info!("Wait {w}s to start...");
@@ -129,29 +156,107 @@ fn main() {
     config_dir: None,
     cookie_file: None,
     data_dir,
+    esplora_url: None,
+    fee_mode: Default::default(),
+    fee_target: None,
     first_inscription_height: None,
     height_limit: None,
     index: None,
+    index_addresses,
     index_sats: false,
     regtest: false,
+    reveal_fee_policy: Default::default(),
+    reveal_master_key: None,
+    rpc_timeout: None,
     rpc_url,
     signet: false,
     testnet: false,
+    utxo_provider: Default::default(),
     wallet: "ord".to_string(),
   };
 
   let my_struct = Arc::new(Mutex::new(options));
 
-  let database = if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
+  let database: Option<Arc<dyn MirrorStore>> = if let Some(sqlite) = sqlite {
+    info!("Use sqlite...");
+    match SqliteDatabase::open(&sqlite, network) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Sqlite open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
+  } else if mysql_host.is_none() || mysql_username.is_none() || mysql_password.is_none() {
     info!("Use redb...");
     None
   } else {
     info!("Use mysql...");
-    Some(Arc::new(
-      MysqlDatabase::new(mysql_host, mysql_username, mysql_password, network).unwrap(),
-    ))
+    match MysqlDatabase::new(
+      mysql_host,
+      mysql_username,
+      mysql_password,
+      mysql_database,
+      network,
+    ) {
+      Ok(db) => Some(Arc::new(db)),
+      Err(e) => {
+        error!("Mysql open error:{e:#}");
+        std::process::exit(1);
+      }
+    }
   };
 
+  if backfill {
+    let Some(database) = database else {
+      error!("Backfill requires a mirror, set the mysql options or --sqlite");
+      std::process::exit(1);
+    };
+    let result = Index::open_with_mirror(&my_struct.lock().unwrap(), database)
+      .and_then(|index| index.backfill_mirror());
+    match result {
+      Ok(inscriptions) => {
+        info!("Backfill success, wrote {inscriptions} inscriptions");
+        return;
+      }
+      Err(e) => {
+        error!("Backfill error:{e:#}");
+        std::process::exit(1);
+      }
+    }
+  }
+
+  if verify {
+    let Some(database) = database else {
+      error!("Verify requires a mirror, set the mysql options or --sqlite");
+      std::process::exit(1);
+    };
+    let result = Index::open_with_mirror(&my_struct.lock().unwrap(), database)
+      .and_then(|index| index.verify_mirror(repair));
+    match result {
+      Ok(discrepancies) => {
+        for discrepancy in &discrepancies {
+          warn!("{discrepancy}");
+        }
+        if discrepancies.is_empty() {
+          info!("Verify success, mirror matches the index");
+        } else if repair {
+          info!("Repaired {} mirror rows", discrepancies.len());
+        } else {
+          error!(
+            "Found {} mirror rows that disagree with the index",
+            discrepancies.len()
+          );
+          std::process::exit(1);
+        }
+        return;
+      }
+      Err(e) => {
+        error!("Verify error:{e:#}");
+        std::process::exit(1);
+      }
+    }
+  }
+
   let mut count = 0;
   loop {
     if count > 0 {
@@ -164,13 +269,28 @@ fn main() {
       info!("Index {count}th update...");
       let my_struct = thread_struct.lock().unwrap();
       let open_result = if let Some(db) = database {
-        Index::open_with_mysql(&my_struct, db)
+        Index::open_with_mirror(&my_struct, db)
       } else {
         Index::open(&my_struct)
       };
       match open_result {
         Ok(index) => {
-          if let Err(e) = index.update() {
+          match index.catch_up_mirror() {
+            Ok(0) => {}
+            Ok(blocks) => info!("Mirror caught up {blocks} blocks"),
+            Err(e) => error!("Mirror catch up error:{e}"),
+          }
+          let mut result = index.update();
+          if result.is_err() && index.is_reorged() {
+            match index.recover_from_reorg() {
+              Ok(height) => {
+                info!("Index rolled back to {height}, resume indexing...");
+                result = index.update();
+              }
+              Err(e) => error!("Index reorg recovery error:{e}"),
+            }
+          }
+          if let Err(e) = result {
             error!("Index update error:{e}")
           } else {
             info!("Index update success")
@@ -151,4 +151,11 @@ pub trait Api {
 
   #[rpc(name = "listwallets")]
   fn list_wallets(&self) -> Result<Vec<String>, jsonrpc_core::Error>;
+
+  #[rpc(name = "estimatesmartfee")]
+  fn estimate_smart_fee(
+    &self,
+    conf_target: u16,
+    estimate_mode: Option<EstimateMode>,
+  ) -> Result<EstimateSmartFeeResult, jsonrpc_core::Error>;
 }
@@ -344,28 +344,34 @@ impl Api for Server {
   ) -> Result<Value, jsonrpc_core::Error> {
     assert_eq!(blockhash, None, "Blockhash param is unsupported");
     if verbose.unwrap_or(false) {
-      match self.state().transactions.get(&txid) {
-        Some(_) => Ok(
-          serde_json::to_value(GetRawTransactionResult {
-            in_active_chain: Some(true),
-            hex: Vec::new(),
-            txid: Txid::all_zeros(),
-            hash: Wtxid::all_zeros(),
-            size: 0,
-            vsize: 0,
-            version: 0,
-            locktime: 0,
-            vin: Vec::new(),
-            vout: Vec::new(),
-            blockhash: None,
-            confirmations: Some(1),
-            time: None,
-            blocktime: None,
-          })
-          .unwrap(),
-        ),
-        None => Err(Self::not_found()),
-      }
+      let state = self.state();
+      let confirmed = if state.transactions.contains_key(&txid) {
+        true
+      } else if state.mempool().iter().any(|tx| tx.txid() == txid) {
+        false
+      } else {
+        return Err(Self::not_found());
+      };
+
+      Ok(
+        serde_json::to_value(GetRawTransactionResult {
+          in_active_chain: confirmed.then_some(true),
+          hex: Vec::new(),
+          txid: Txid::all_zeros(),
+          hash: Wtxid::all_zeros(),
+          size: 0,
+          vsize: 0,
+          version: 0,
+          locktime: 0,
+          vin: Vec::new(),
+          vout: Vec::new(),
+          blockhash: None,
+          confirmations: confirmed.then_some(1),
+          time: None,
+          blocktime: None,
+        })
+        .unwrap(),
+      )
     } else {
       match self.state().transactions.get(&txid) {
         Some(tx) => Ok(Value::String(hex::encode(serialize(tx)))),
@@ -589,4 +595,27 @@ impl Api for Server {
         .collect::<Vec<String>>(),
     )
   }
+
+  /// 12 sat/vB spread over `conf_target` blocks, plus 1 sat/vB when
+  /// conservative, so callers can tell which target and mode they asked for.
+  fn estimate_smart_fee(
+    &self,
+    conf_target: u16,
+    estimate_mode: Option<EstimateMode>,
+  ) -> Result<EstimateSmartFeeResult, jsonrpc_core::Error> {
+    if conf_target == 0 {
+      return Err(jsonrpc_core::Error::invalid_params("invalid conf_target"));
+    }
+
+    let mut sat_per_kvb = 12_000 / u64::from(conf_target);
+    if estimate_mode == Some(EstimateMode::Conservative) {
+      sat_per_kvb += 1_000;
+    }
+
+    Ok(EstimateSmartFeeResult {
+      fee_rate: Some(Amount::from_sat(sat_per_kvb)),
+      errors: None,
+      blocks: conf_target.into(),
+    })
+  }
 }